@@ -0,0 +1,225 @@
+use std::{collections::HashMap, fs, path::Path};
+
+use md5::{Digest, Md5};
+
+use super::{constants::ANNOUNCEMENTS_FOLDER_NAME, App};
+use crate::{
+    error::{AppError, Result},
+    model::{Announcement, AnnouncementChanges, File, ProgressPayload},
+    utils::sanitize_file_name,
+};
+
+const ANNOUNCEMENT_SNAPSHOT_FILE_NAME: &str = "announcement_snapshot";
+
+// Changes whenever the content users would be notified about changes
+fn fingerprint(announcement: &Announcement) -> String {
+    let topic = &announcement.topic;
+    let mut hasher = Md5::new();
+    hasher.update(topic.title.as_bytes());
+    hasher.update(b"\0");
+    hasher.update(topic.message.as_bytes());
+    for attachment in &topic.attachments {
+        hasher.update(attachment.id.to_le_bytes());
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+// Compares announcements against the fingerprints of the last check and
+// returns the changes together with the new snapshot
+fn diff_announcements(
+    snapshot: &HashMap<i64, String>,
+    announcements: &[Announcement],
+) -> (AnnouncementChanges, HashMap<i64, String>) {
+    let mut changes = AnnouncementChanges::default();
+    // keep announcements which dropped out of the queried window
+    let mut new_snapshot = snapshot.clone();
+    for announcement in announcements {
+        let fingerprint = fingerprint(announcement);
+        match snapshot.get(&announcement.topic.id) {
+            None => changes.created.push(announcement.clone()),
+            Some(old) if *old != fingerprint => changes.updated.push(announcement.clone()),
+            _ => (),
+        }
+        new_snapshot.insert(announcement.topic.id, fingerprint);
+    }
+    (changes, new_snapshot)
+}
+
+impl App {
+    // Announcements of the given courses (all courses if empty), newest first
+    pub async fn list_announcements(
+        &self,
+        course_ids: &[i64],
+        start_date: Option<&str>,
+        end_date: Option<&str>,
+    ) -> Result<Vec<Announcement>> {
+        let courses = self.list_courses().await?;
+        let courses: HashMap<_, _> = courses
+            .iter()
+            .filter(|course| course_ids.is_empty() || course_ids.contains(&course.id))
+            .map(|course| (format!("course_{}", course.id), course))
+            .collect();
+        let context_codes: Vec<_> = courses.keys().cloned().collect();
+        let token = self.config.read().await.token.clone();
+        let topics = self
+            .client
+            .list_announcements(&token, &context_codes, start_date, end_date)
+            .await?;
+
+        let mut announcements: Vec<_> = topics
+            .into_iter()
+            .filter_map(|topic| {
+                let course = courses.get(topic.context_code.as_deref()?)?;
+                Some(Announcement {
+                    course_id: course.id,
+                    course_name: course.name.clone(),
+                    topic,
+                })
+            })
+            .collect();
+        announcements.sort_by(|a, b| b.topic.posted_at.cmp(&a.topic.posted_at));
+        Ok(announcements)
+    }
+
+    pub async fn list_unread_announcements(
+        &self,
+        start_date: Option<&str>,
+    ) -> Result<Vec<Announcement>> {
+        let announcements = self.list_announcements(&[], start_date, None).await?;
+        Ok(announcements
+            .into_iter()
+            .filter(|announcement| announcement.is_unread())
+            .collect())
+    }
+
+    pub async fn mark_announcement_read(
+        &self,
+        course_id: i64,
+        topic_id: i64,
+        read: bool,
+    ) -> Result<()> {
+        let token = self.config.read().await.token.clone();
+        self.client
+            .mark_discussion_topic_message_read(course_id, topic_id, read, &token)
+            .await
+    }
+
+    // Saves attachments under {course}/公告/{title}, returns the local paths
+    pub async fn download_announcement_attachments<F: Fn(ProgressPayload) + Send + Sync>(
+        &self,
+        announcement: &Announcement,
+        progress_handler: F,
+    ) -> Result<Vec<String>> {
+        let courses = self.list_courses().await?;
+        let Some(course) = courses
+            .iter()
+            .find(|course| course.id == announcement.course_id)
+        else {
+            return Err(AppError::CourseNotFound(announcement.course_id));
+        };
+        let guard = self.config.read().await;
+        let token = guard.token.clone();
+        let save_dir = Path::new(&guard.save_path)
+            .join(self.client.get_course_identifier(course))
+            .join(ANNOUNCEMENTS_FOLDER_NAME)
+            .join(sanitize_file_name(&announcement.topic.title));
+        drop(guard);
+        fs::create_dir_all(&save_dir)?;
+
+        let mut paths = vec![];
+        for attachment in &announcement.topic.attachments {
+            let mut file = File::from(attachment);
+            file.display_name = sanitize_file_name(&file.display_name);
+            self.client
+                .download_file(
+                    &file,
+                    &token,
+                    save_dir.to_str().unwrap_or_default(),
+                    &progress_handler,
+                )
+                .await?;
+            paths.push(
+                save_dir
+                    .join(&file.display_name)
+                    .to_string_lossy()
+                    .into_owned(),
+            );
+        }
+        Ok(paths)
+    }
+
+    // Returns announcements created or edited since the previous check. The
+    // first check only records a baseline so existing announcements don't all
+    // show up as new. An unreadable snapshot is replaced by a new baseline.
+    pub async fn check_announcement_updates(
+        &self,
+        start_date: Option<&str>,
+    ) -> Result<AnnouncementChanges> {
+        let announcements = self.list_announcements(&[], start_date, None).await?;
+        let path = App::get_account_data_path(
+            &*self.current_account.read().await,
+            ANNOUNCEMENT_SNAPSHOT_FILE_NAME,
+        );
+        let snapshot: Option<HashMap<i64, String>> = match fs::read(&path) {
+            Ok(content) => match serde_json::from_slice(&content) {
+                Ok(snapshot) => Some(snapshot),
+                Err(e) => {
+                    tracing::warn!("Ignoring corrupt announcement snapshot: {:?}", e);
+                    None
+                }
+            },
+            Err(_) => None,
+        };
+        let (changes, new_snapshot) =
+            diff_announcements(&snapshot.clone().unwrap_or_default(), &announcements);
+        fs::write(&path, serde_json::to_vec(&new_snapshot)?)?;
+        if snapshot.is_none() {
+            return Ok(Default::default());
+        }
+        Ok(changes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::DiscussionTopic;
+
+    fn announcement(id: i64, title: &str, message: &str) -> Announcement {
+        Announcement {
+            course_id: 1,
+            course_name: "Course".to_owned(),
+            topic: DiscussionTopic {
+                id,
+                title: title.to_owned(),
+                message: message.to_owned(),
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn test_diff_announcements() {
+        let announcements = vec![
+            announcement(1, "Exam", "Room 101"),
+            announcement(2, "Lab", "Bring laptops"),
+        ];
+        let (changes, snapshot) = diff_announcements(&HashMap::new(), &announcements);
+        assert_eq!(changes.created.len(), 2);
+        assert!(changes.updated.is_empty());
+
+        // unchanged announcements are not reported again
+        let (changes, snapshot) = diff_announcements(&snapshot, &announcements);
+        assert!(changes.created.is_empty() && changes.updated.is_empty());
+
+        let announcements = vec![
+            announcement(1, "Exam", "Room 202"),
+            announcement(3, "Holiday", "No class"),
+        ];
+        let (changes, snapshot) = diff_announcements(&snapshot, &announcements);
+        assert_eq!(changes.created[0].topic.id, 3);
+        assert_eq!(changes.updated[0].topic.id, 1);
+        // announcement 2 is out of the window but still remembered
+        assert_eq!(snapshot.len(), 3);
+    }
+}
@@ -0,0 +1,76 @@
+use std::sync::Arc;
+
+use super::App;
+use crate::{
+    error::AppError,
+    model::{AuthService, ReloginPayload},
+};
+
+pub type ReloginHandler = Arc<dyn Fn(ReloginPayload) + Send + Sync>;
+
+impl App {
+    // Forward rejected credentials to the handler, tagged with the account they
+    // belong to, and persist tokens renewed by the client
+    pub fn watch_auth(self: &Arc<Self>, handler: ReloginHandler) {
+        let app = Arc::downgrade(self);
+        self.client.set_token_refresh_listener(Arc::new({
+            let app = app.clone();
+            move |session| {
+                let app = app.clone();
+                tokio::spawn(async move {
+                    if let Some(app) = app.upgrade() {
+                        if let Err(e) = app.store_oauth_session(session).await {
+                            tracing::error!("Failed to save refreshed token: {:?}", e);
+                        }
+                    }
+                });
+            }
+        }));
+        self.client.set_auth_listener(Arc::new(move |service| {
+            let app = app.clone();
+            let handler = handler.clone();
+            tokio::spawn(async move {
+                if let Some(app) = app.upgrade() {
+                    let account = app.get_current_account().await;
+                    handler(ReloginPayload { account, service });
+                }
+            });
+        }));
+    }
+
+    // Services whose stored credentials no longer work. Meant to run at startup,
+    // before the user starts an operation which would fail halfway.
+    pub async fn check_credentials(&self) -> Vec<AuthService> {
+        let config = self.get_config().await;
+        let mut expired = vec![];
+        if !config.token.is_empty() {
+            if let Err(AppError::Unauthorized(service)) = self.test_token(&config.token).await {
+                expired.push(service);
+            }
+        }
+
+        let mut jaccount_valid = false;
+        if !config.ja_auth_cookie.is_empty() {
+            match self.login_canvas_website().await {
+                Ok(_) => jaccount_valid = true,
+                Err(AppError::Unauthorized(service)) => expired.push(service),
+                // offline or server errors say nothing about the cookie
+                Err(e) => tracing::warn!("Failed to check jAccount cookie: {:?}", e),
+            }
+        }
+
+        let jbox_info = &config.jbox_login_info;
+        if !jbox_info.access_token.is_empty() && jbox_info.is_expired() {
+            // renewing only needs a valid jAccount cookie
+            let renewed = jaccount_valid && self.login_jbox().await.is_ok();
+            if !renewed {
+                self.client.report_unauthorized(AuthService::Jbox);
+                expired.push(AuthService::Jbox);
+            }
+        }
+        if !expired.is_empty() {
+            tracing::warn!("Expired credentials: {:?}", expired);
+        }
+        expired
+    }
+}
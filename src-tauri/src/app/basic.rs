@@ -3,15 +3,15 @@ use std::process;
 
 use error::{AppError, Result};
 use std::{
+    collections::HashMap,
     fs,
-    io::Write,
+    io::{self, Write},
     path::Path,
-    process::{Command, Stdio},
+    process::Command,
     sync::Arc,
     time::Duration,
 };
 use tauri::{api::path::config_dir, Runtime, Window};
-use tokio::{io::AsyncReadExt, process::Command as TokioCommand};
 use tokio::{sync::RwLock, task::JoinSet};
 use uuid::Uuid;
 use warp::{hyper::Response, Filter};
@@ -29,7 +29,14 @@ use crate::{
 };
 
 use super::{
+    config::migrate_config,
     constants::{COURSES_CACHE_KEY, RELATIONSHIP_CACHE_KEY},
+    ffmpeg::{format_command, job_args},
+    media::media_routes,
+    oauth::{
+        forget_replaced_oauth_login, handle_oauth_callback, oauth_session, OAUTH_CALLBACK_PATH,
+    },
+    secret::{resolve_secrets, secret_store, Secrets},
     App,
 };
 
@@ -126,6 +133,9 @@ impl App {
 
         let config_path = App::get_config_path(account);
         fs::remove_file(&config_path)?;
+        if let Err(e) = secret_store().delete(account) {
+            tracing::warn!("Failed to delete secrets of {:?}: {:?}", account, e);
+        }
 
         let current_account = self.current_account.read().await.clone();
         // if delete current account, then switch to default
@@ -152,16 +162,19 @@ impl App {
         if !App::account_exists(account)? {
             return Err(AppError::AccountNotExists);
         }
-        let config_path = App::get_config_path(account);
-        let config = App::read_config_from_file(&config_path)?;
+        let config = App::load_config(account)?;
         let base_url = Self::get_base_url(&config.account_type);
         self.client.set_base_url(base_url).await;
+        self.sync_oauth_session(&config);
         *self.config.write().await = config;
+        *self.config_error.write().unwrap() = None;
+        self.client.reset_auth_failures();
 
         let mut account_info = App::read_account_info()?;
         account_info.current_account = account.clone();
         App::save_account_info(&account_info)?;
         *self.current_account.write().await = account.clone();
+        *self.search_index.write().await = None;
         self.invalidate_cache()?;
         Ok(())
     }
@@ -188,25 +201,77 @@ impl App {
         tracing::info!("Read current account: {:?}", account_info);
         let config_path = App::get_config_path(&account_info.current_account);
         tracing::info!("Read config path: {}", config_path);
-        let config = App::read_config_from_file(&config_path).unwrap_or_default();
+        let mut config_error = None;
+        let config = match App::load_config(&account_info.current_account) {
+            Ok(config) => config,
+            Err(AppError::IO(e)) if e.kind() == io::ErrorKind::NotFound => Default::default(),
+            Err(e @ AppError::InvalidConfig(..)) => {
+                // move the unreadable file aside so saving the default config
+                // can't destroy the user's settings
+                let backup_path = App::config_backup_path(&config_path, "broken");
+                tracing::error!("Failed to load config: {}, moved to {}", e, backup_path);
+                let _ = fs::rename(&config_path, &backup_path);
+                config_error = Some(format!("{}. The file was moved to {}", e, backup_path));
+                Default::default()
+            }
+            Err(e) => {
+                // e.g. a config of a newer version or a file we can't read, leave
+                // it alone, save_config refuses to overwrite it
+                tracing::error!("Failed to load config: {}", e);
+                config_error = Some(e.to_string());
+                Default::default()
+            }
+        };
+        let app = App::with_config(account_info.current_account, config);
+        *app.config_error.write().unwrap() = config_error;
+        app
+    }
 
+    fn with_config(account: Account, config: AppConfig) -> Self {
         let base_url = Self::get_base_url(&config.account_type);
         let client = Client::with_base_url(base_url);
+        client.set_oauth_session(oauth_session(&config));
 
         Self {
             client: Arc::new(client),
-            current_account: RwLock::new(account_info.current_account),
+            current_account: RwLock::new(account),
             config: RwLock::new(config),
             handle: Default::default(),
             cache: Default::default(),
+            search_index: Default::default(),
+            config_error: Default::default(),
+            oauth_callback: Default::default(),
+            video_downloads: Default::default(),
+            video_batches: Default::default(),
+            ffmpeg_jobs: Default::default(),
+            subtitle_jobs: Default::default(),
+            media_streams: Default::default(),
+            video_session_lock: Default::default(),
+            video_library_lock: Default::default(),
+        }
+    }
+
+    // A standalone app bound to one account, with its own client and cache. Unlike
+    // switch_account this leaves the globally selected account untouched.
+    pub async fn with_account(account: &Account) -> Result<Self> {
+        if !App::account_exists(account)? {
+            return Err(AppError::AccountNotExists);
         }
+        let config = App::load_config(account)?;
+        let app = App::with_config(account.clone(), config);
+        app.init().await?;
+        Ok(app)
+    }
+
+    pub async fn get_current_account(&self) -> Account {
+        self.current_account.read().await.clone()
     }
 
     pub async fn init(&self) -> Result<()> {
         let mut config = self.get_config().await;
         let cookies = &config.video_cookies;
         if !cookies.is_empty() {
-            tracing::info!("Detected saved cookies: {}", cookies);
+            tracing::info!("Detected saved cookies: {}", utils::mask_secret(cookies));
             self.client.init_cookie(cookies);
             if let Ok(Some(consumer_key)) = self.client.get_oauth_consumer_key().await {
                 config.oauth_consumer_key = consumer_key;
@@ -234,8 +299,15 @@ impl App {
     }
 
     pub async fn prepare_proxy(&self) -> Result<bool> {
-        if self.handle.read().await.is_some() {
-            return Ok(true);
+        Ok(self.proxy_port().await?.is_some())
+    }
+
+    // The port the local server is bound to, starting it if needed. It may differ
+    // from the configured port when that was changed after the server started.
+    // None if the server didn't come up.
+    pub(crate) async fn proxy_port(&self) -> Result<Option<u16>> {
+        if let Some((_, port)) = self.handle.read().await.as_ref() {
+            return Ok(Some(*port));
         }
         let proxy_port = self.config.read().await.proxy_port;
         let proxy = warp::get()
@@ -247,28 +319,116 @@ impl App {
 
         let ready_check = warp::path!("ready").map(|| Response::builder().body(""));
 
-        let handle =
-            tokio::spawn(warp::serve(proxy.or(ready_check)).run(([127, 0, 0, 1], proxy_port)));
-        *self.handle.write().await = Some(handle);
+        let callback = self.oauth_callback.clone();
+        let oauth_callback = warp::get()
+            .and(warp::path(OAUTH_CALLBACK_PATH))
+            .and(warp::path::end())
+            .and(warp::query::<HashMap<String, String>>())
+            .map(move |query: HashMap<String, String>| {
+                warp::reply::html(handle_oauth_callback(&callback, &query))
+            });
+
+        let media = media_routes(self.client.clone(), self.media_streams.clone());
+
+        let handle = tokio::spawn(
+            warp::serve(proxy.or(ready_check).or(oauth_callback).or(media))
+                .run(([127, 0, 0, 1], proxy_port)),
+        );
+        *self.handle.write().await = Some((handle, proxy_port));
 
-        self.wait_proxy_ready(proxy_port).await
+        Ok(self
+            .wait_proxy_ready(proxy_port)
+            .await?
+            .then_some(proxy_port))
     }
 
     pub async fn stop_proxy(&self) {
         let mut handle = self.handle.write().await;
-        if let Some(handle) = handle.as_ref() {
+        if let Some((handle, _)) = handle.as_ref() {
             tracing::info!("stop proxy");
             handle.abort();
         }
         *handle = None;
     }
 
+    // e.g. config.json.v0.20261018153000.bak, the timestamp keeps earlier backups
+    fn config_backup_path(config_path: &str, tag: &str) -> String {
+        format!(
+            "{}.{}.{}.bak",
+            config_path,
+            tag,
+            chrono::Local::now().format("%Y%m%d%H%M%S")
+        )
+    }
+
+    // Older configs are upgraded in place. The original file is always backed up
+    // first, fields the migration had to drop can be recovered from it.
     fn read_config_from_file(config_path: &str) -> Result<AppConfig> {
         let content = fs::read(config_path)?;
-        let config = utils::parse_json(&content)?;
+        let (config, migrated_from) = migrate_config(&content, config_path)?;
+        if let Some(version) = migrated_from {
+            let backup_path = App::config_backup_path(config_path, &format!("v{}", version));
+            fs::write(&backup_path, &content)?;
+            fs::write(config_path, serde_json::to_vec(&config)?)?;
+            tracing::info!(
+                "Config {} migrated from version {} to {}, backup: {}",
+                config_path,
+                version,
+                config.version,
+                backup_path
+            );
+        }
+        Ok(config)
+    }
+
+    // Writes the config without credentials, they go to the secret store. A
+    // locked store keeps them in memory until it's unlocked, they are never
+    // written in plaintext.
+    pub(crate) fn write_config_file(account: &Account, config: &AppConfig) -> Result<()> {
+        let mut stripped = config.clone();
+        // the frontend doesn't know about versions
+        stripped.version = CONFIG_VERSION;
+        let secrets = Secrets::take_from(&mut stripped);
+        if !secret_store().save(account, &secrets)? {
+            tracing::warn!(
+                "Secret store is locked, credentials of {:?} are only kept in memory",
+                account
+            );
+        }
+        fs::write(
+            App::get_config_path(account),
+            serde_json::to_vec(&stripped)?,
+        )?;
+        Ok(())
+    }
+
+    // Reads the config with its credentials filled in from the secret store,
+    // moving credentials still stored in plaintext into the store
+    pub(crate) fn load_config(account: &Account) -> Result<AppConfig> {
+        let config = App::read_config_from_file(&App::get_config_path(account))?;
+        let (config, migrate) = resolve_secrets(secret_store(), account, config)?;
+        if migrate {
+            tracing::info!("Moving credentials of {:?} into the secret store", account);
+            // the file keeps its plaintext credentials, try again on the next load
+            if let Err(e) = App::write_config_file(account, &config) {
+                tracing::error!("Failed to move credentials of {:?}: {}", account, e);
+            }
+        }
         Ok(config)
     }
 
+    pub(crate) async fn reload_config(&self) -> Result<()> {
+        let account = self.current_account.read().await.clone();
+        let config = App::load_config(&account)?;
+        let base_url = Self::get_base_url(&config.account_type);
+        if self.client.set_base_url(base_url).await {
+            self.invalidate_cache()?;
+        }
+        self.sync_oauth_session(&config);
+        *self.config.write().await = config;
+        Ok(())
+    }
+
     fn get_config_path(account: &Account) -> String {
         let config_dir = App::config_dir().unwrap();
         let mut config_file_name = "sjtu_canvas_helper_config".to_owned();
@@ -279,6 +439,21 @@ impl App {
         config_path
     }
 
+    // Per-account data file in the config dir, e.g. search_index_{account}.json
+    pub(crate) fn get_account_data_path(account: &Account, name: &str) -> String {
+        let config_dir = App::config_dir().unwrap();
+        let mut file_name = name.to_owned();
+        if let Account::Custom(account_name) = account {
+            file_name += &format!("_{}", account_name);
+        }
+        format!("{}/{}.json", config_dir, file_name)
+    }
+
+    // Why the config of the current account couldn't be loaded at startup
+    pub fn get_config_load_error(&self) -> Option<String> {
+        self.config_error.read().unwrap().clone()
+    }
+
     pub async fn get_config(&self) -> AppConfig {
         self.config.read().await.clone()
     }
@@ -286,8 +461,26 @@ impl App {
     pub async fn get_raw_config(&self) -> Result<String> {
         let account = self.current_account.read().await.clone();
         let config_path = App::get_config_path(&account);
-        let content = fs::read_to_string(config_path)?;
-        Ok(content)
+        let content = fs::read(config_path)?;
+        let mut config: serde_json::Value = utils::parse_json(&content)?;
+        for field in [
+            "token",
+            "ja_auth_cookie",
+            "video_cookies",
+            "canvas_client_secret",
+            "canvas_refresh_token",
+        ] {
+            if let Some(serde_json::Value::String(value)) = config.get_mut(field) {
+                *value = utils::mask_secret(value);
+            }
+        }
+        if let Some(serde_json::Value::String(value)) = config
+            .get_mut("jbox_login_info")
+            .and_then(|info| info.get_mut("access_token"))
+        {
+            *value = utils::mask_secret(value);
+        }
+        Ok(serde_json::to_string(&config)?)
     }
 
     pub async fn list_courses(&self) -> Result<Vec<Course>> {
@@ -358,12 +551,13 @@ impl App {
             .update_grade(
                 course_id,
                 assignment_id,
-                student_id,
+                &[student_id],
                 grade,
                 comment,
                 &self.config.read().await.token,
             )
-            .await
+            .await?;
+        Ok(())
     }
 
     pub async fn delete_submission_comment(
@@ -712,7 +906,7 @@ impl App {
         self.open_path(path.to_str().unwrap_or_default())
     }
 
-    fn open_path(&self, path: &str) -> Result<()> {
+    pub(crate) fn open_path(&self, path: &str) -> Result<()> {
         #[cfg(target_os = "macos")]
         let _ = std::process::Command::new("open").arg(path).output()?;
 
@@ -802,10 +996,30 @@ impl App {
             .await
     }
 
+    // Config edited in the settings, unlike refreshes done by the app a changed
+    // token there means the user replaced the OAuth login
+    pub async fn save_edited_config(&self, mut config: AppConfig) -> Result<()> {
+        forget_replaced_oauth_login(&*self.config.read().await, &mut config);
+        self.save_config(config).await
+    }
+
     pub async fn save_config(&self, config: AppConfig) -> Result<()> {
         let account = self.current_account.read().await.clone();
+        // a config that failed to load and wasn't moved aside still holds the
+        // user's settings, don't replace it with the defaults we fell back to
         let config_path = App::get_config_path(&account);
-        fs::write(&config_path, serde_json::to_vec(&config).unwrap())?;
+        if let Some(error) = self.config_error.read().unwrap().clone() {
+            if Path::new(&config_path).exists() {
+                return Err(AppError::InvalidConfig(
+                    config_path,
+                    format!("not overwriting the config that failed to load: {}", error),
+                ));
+            }
+        }
+        App::write_config_file(&account, &config)?;
+        // credentials may have been renewed
+        self.client.reset_auth_failures();
+        self.sync_oauth_session(&config);
         let base_url = Self::get_base_url(&config.account_type);
         if self.client.set_base_url(base_url).await {
             self.invalidate_cache()?;
@@ -1042,75 +1256,38 @@ impl App {
 
     // return execute command, whether succeeded and exit code
     pub async fn run_video_aggregate<R: Runtime>(
+        &self,
         window: Window<R>,
         params: &VideoAggregateParams,
     ) -> Result<i32> {
-        let scale_percentage = params.sub_video_size_percentage as f64 / 100.0;
-        let scale_width = format!("iw*{}", scale_percentage);
-        let scale_height = format!("ih*{}", scale_percentage);
-
-        let alpha_value = params.sub_video_alpha as f64 / 100.0;
         let output_path = format!("{}/{}", params.output_dir, params.output_name);
-
-        let mut command = TokioCommand::new("ffmpeg")
-            .args([
-                "-i",
-                &params.main_video_path,
-                "-i",
-                &params.sub_video_path,
-                "-filter_complex",
-                &format!(
-                    "[1:v]scale={}:{}[overlay];[0:v][overlay]overlay=W-w:H-h:format=auto:alpha={}",
-                    scale_width, scale_height, alpha_value
-                ),
-                "-c:a",
-                "copy",
-                &output_path,
-            ])
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .spawn()?;
-
-        // catch stdout
-        let mut stdout = command.stdout.take().ok_or(AppError::OpenStdoutError)?;
-        let mut stderr = command.stderr.take().ok_or(AppError::OpenStderrError)?;
-
-        let command_str = format!(
-            "ffmpeg -i \"{}\" -i \"{}\" -filter_complex \"[1:v]scale={}:{}[overlay];[0:v][overlay]overlay=W-w:H-h:format=auto:alpha={}\" -c:a copy \"{}\"",
-            params.main_video_path,
-            params.sub_video_path,
-            scale_width,
-            scale_height,
-            alpha_value,
-            output_path
-        );
-        let _ = window.emit("ffmpeg://output", command_str + "\n");
-        let window_cloned = window.clone();
-
-        tokio::spawn(async move {
-            let mut buffer = [0; 128];
-            while let Ok(bytes_read) = stdout.read(&mut buffer).await {
-                if bytes_read == 0 {
-                    break; // EOF
-                }
-                let output = String::from_utf8_lossy(&buffer[..bytes_read]);
-                let _ = window.emit("ffmpeg://output", output.to_string());
-            }
-        });
-
-        tokio::spawn(async move {
-            let mut buffer = [0; 128];
-            while let Ok(bytes_read) = stderr.read(&mut buffer).await {
-                if bytes_read == 0 {
-                    break; // EOF
-                }
-                let output = String::from_utf8_lossy(&buffer[..bytes_read]);
-                let _ = window_cloned.emit("ffmpeg://output", output.to_string());
-            }
-        });
-
-        let status = command.wait().await?;
-        Ok(status.code().unwrap_or_default())
+        let options = OverlayOptions {
+            position: params.sub_video_position,
+            size_percentage: params.sub_video_size_percentage,
+            alpha: params.sub_video_alpha,
+        };
+        let job_params = FfmpegJobParams {
+            kind: FfmpegJobKind::Overlay {
+                input_path: params.main_video_path.clone(),
+                sub_path: params.sub_video_path.clone(),
+                options,
+            },
+            output_path,
+        };
+        let command = format_command(&job_args(&job_params)?);
+        let (id, cancel_token) = self.create_ffmpeg_job(job_params)?;
+        let _ = window.emit("ffmpeg://output", command + "\n");
+        self.execute_ffmpeg_job(
+            &id,
+            cancel_token,
+            |line| {
+                let _ = window.emit("ffmpeg://output", format!("{}\n", line));
+            },
+            |progress| {
+                let _ = window.emit("ffmpeg://progress", progress);
+            },
+        )
+        .await
     }
 
     pub fn read_log_content() -> Result<String> {
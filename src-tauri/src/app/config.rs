@@ -0,0 +1,141 @@
+use serde_json::{Map, Value};
+
+use crate::{
+    error::{AppError, Result},
+    model::{AppConfig, CONFIG_VERSION},
+};
+
+type MigrationStep = fn(&mut Map<String, Value>);
+
+// MIGRATIONS[i] upgrades a config of version i to version i + 1
+const MIGRATIONS: [MigrationStep; CONFIG_VERSION as usize] = [migrate_v0_to_v1];
+
+// Unversioned configs were parsed leniently by the frontend and may hold
+// nulls or values of outdated types. Drop those fields so they fall back to
+// their defaults instead of failing the whole file, the caller keeps the
+// original file as a backup so nothing dropped here is lost.
+fn migrate_v0_to_v1(config: &mut Map<String, Value>) {
+    config.retain(|_, value| !value.is_null());
+    if let Some(Value::String(port)) = config.get("proxy_port") {
+        match port.trim().parse::<u16>() {
+            Ok(port) => {
+                config.insert("proxy_port".to_owned(), port.into());
+            }
+            Err(_) => {
+                config.remove("proxy_port");
+            }
+        }
+    }
+
+    let string_fields = [
+        "token",
+        "save_path",
+        "account_type",
+        "serve_as_plaintext",
+        "ja_auth_cookie",
+        "video_cookies",
+        "oauth_consumer_key",
+    ];
+    let object_fields = [
+        "jbox_login_info",
+        "course_assignment_file_bindings",
+        "show_alert_map",
+    ];
+    config.retain(|key, value| {
+        let valid = if string_fields.contains(&key.as_str()) {
+            value.is_string()
+        } else if object_fields.contains(&key.as_str()) {
+            value.is_object()
+        } else if key == "proxy_port" {
+            value.is_u64()
+        } else {
+            true
+        };
+        if !valid {
+            tracing::warn!(
+                "Dropping config field {} with unexpected value {}, it is kept in the backup",
+                key,
+                value
+            );
+        }
+        valid
+    });
+}
+
+// Parses a config file of any supported version, returns the config and the
+// version it was migrated from, None if it was already up to date
+pub fn migrate_config(content: &[u8], path: &str) -> Result<(AppConfig, Option<u32>)> {
+    let invalid = |e: serde_json::Error| AppError::InvalidConfig(path.to_owned(), e.to_string());
+    let mut value: Value = serde_json::from_slice(content).map_err(invalid)?;
+    let Some(config) = value.as_object_mut() else {
+        return Err(AppError::InvalidConfig(
+            path.to_owned(),
+            "expected a json object".to_owned(),
+        ));
+    };
+
+    let version = match config.get("version") {
+        None => 0,
+        Some(version) => version
+            .as_u64()
+            .and_then(|version| u32::try_from(version).ok())
+            .ok_or_else(|| {
+                AppError::InvalidConfig(path.to_owned(), format!("invalid version {}", version))
+            })?,
+    };
+    if version > CONFIG_VERSION {
+        return Err(AppError::UnsupportedConfigVersion(version, CONFIG_VERSION));
+    }
+    for step in &MIGRATIONS[version as usize..] {
+        step(config);
+    }
+    config.insert("version".to_owned(), CONFIG_VERSION.into());
+
+    let config: AppConfig = serde_json::from_value(value).map_err(invalid)?;
+    let migrated_from = (version < CONFIG_VERSION).then_some(version);
+    Ok((config, migrated_from))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_migrate_unversioned_config() -> Result<()> {
+        let content = r#"{
+            "token": "token",
+            "save_path": "/tmp",
+            "proxy_port": "4040",
+            "oauth_consumer_key": null,
+            "show_alert_map": [],
+            "jbox_login_info": {"library_id": "1", "space_id": "2", "access_token": "3"}
+        }"#;
+        let (config, migrated_from) = migrate_config(content.as_bytes(), "config.json")?;
+        assert_eq!(migrated_from, Some(0));
+        assert_eq!(config.version, CONFIG_VERSION);
+        assert_eq!(config.token, "token");
+        assert_eq!(config.proxy_port, 4040);
+        assert!(config.show_alert_map.is_empty());
+        assert_eq!(config.jbox_login_info.space_id, "2");
+
+        // current configs pass through untouched
+        let content = serde_json::to_vec(&config)?;
+        let (migrated, migrated_from) = migrate_config(&content, "config.json")?;
+        assert_eq!(migrated_from, None);
+        assert_eq!(migrated, config);
+        Ok(())
+    }
+
+    #[test]
+    fn test_migrate_invalid_config() {
+        let result = migrate_config(b"{\"token\": ", "config.json");
+        assert!(matches!(result, Err(AppError::InvalidConfig(..))));
+
+        let content = format!("{{\"version\": {}}}", CONFIG_VERSION + 1);
+        let result = migrate_config(content.as_bytes(), "config.json");
+        assert!(matches!(
+            result,
+            Err(AppError::UnsupportedConfigVersion(..))
+        ));
+    }
+}
@@ -1,2 +1,5 @@
 pub const COURSES_CACHE_KEY: &str = "courses_cache_key";
 pub const RELATIONSHIP_CACHE_KEY: &str = "relationship_cache_key";
+pub const DISCUSSIONS_FOLDER_NAME: &str = "讨论";
+pub const ANNOUNCEMENTS_FOLDER_NAME: &str = "公告";
+pub const MODULES_FOLDER_NAME: &str = "模块";
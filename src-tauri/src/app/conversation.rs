@@ -0,0 +1,188 @@
+use std::collections::HashSet;
+
+use super::App;
+use crate::{
+    error::{AppError, Result},
+    model::{
+        Attachment, Conversation, ConversationScope, File, ProgressPayload, Submission, User,
+        WorkflowState,
+    },
+};
+
+fn check_recipients(course_users: &[User], recipient_ids: &[i64]) -> Result<()> {
+    if recipient_ids.is_empty() {
+        return Err(AppError::InvalidRecipients("no recipient given".to_owned()));
+    }
+    let user_ids: HashSet<_> = course_users.iter().map(|user| user.id).collect();
+    let unknown: Vec<_> = recipient_ids
+        .iter()
+        .filter(|id| !user_ids.contains(id))
+        .map(|id| id.to_string())
+        .collect();
+    if !unknown.is_empty() {
+        return Err(AppError::InvalidRecipients(format!(
+            "users {} are not in this course",
+            unknown.join(", ")
+        )));
+    }
+    Ok(())
+}
+
+// Students who have nothing submitted, including those without a submission record
+fn filter_missing_students(students: Vec<User>, submissions: &[Submission]) -> Vec<User> {
+    let submitted: HashSet<_> = submissions
+        .iter()
+        .filter(|submission| {
+            submission.submitted_at.is_some()
+                || submission.workflow_state != WorkflowState::Unsubmitted
+        })
+        .map(|submission| submission.user_id)
+        .collect();
+    students
+        .into_iter()
+        .filter(|student| !submitted.contains(&student.id))
+        .collect()
+}
+
+impl App {
+    pub async fn list_conversations(
+        &self,
+        scope: ConversationScope,
+        page: u64,
+    ) -> Result<Vec<Conversation>> {
+        let token = self.config.read().await.token.clone();
+        self.client.list_conversations(scope, page, &token).await
+    }
+
+    pub async fn get_conversation(&self, conversation_id: i64) -> Result<Conversation> {
+        let token = self.config.read().await.token.clone();
+        self.client.get_conversation(conversation_id, &token).await
+    }
+
+    pub async fn mark_conversation_read(&self, conversation_id: i64, read: bool) -> Result<()> {
+        let token = self.config.read().await.token.clone();
+        let workflow_state = if read { "read" } else { "unread" };
+        self.client
+            .update_conversation_state(conversation_id, workflow_state, &token)
+            .await
+    }
+
+    pub async fn download_conversation_attachment<F: Fn(ProgressPayload) + Send>(
+        &self,
+        attachment: &Attachment,
+        progress_handler: F,
+    ) -> Result<()> {
+        self.download_file(&File::from(attachment), progress_handler)
+            .await
+    }
+
+    // Recipients must be users of the course the message is sent in
+    pub async fn send_conversation(
+        &self,
+        course_id: i64,
+        recipient_ids: &[i64],
+        subject: &str,
+        body: &str,
+        bulk: bool,
+    ) -> Result<Vec<Conversation>> {
+        let course_users = self.list_course_users(course_id).await?;
+        check_recipients(&course_users, recipient_ids)?;
+        let token = self.config.read().await.token.clone();
+        self.client
+            .create_conversation(course_id, recipient_ids, subject, body, bulk, &token)
+            .await
+    }
+
+    pub async fn reply_conversation(
+        &self,
+        conversation_id: i64,
+        body: &str,
+    ) -> Result<Conversation> {
+        let token = self.config.read().await.token.clone();
+        self.client
+            .reply_conversation(conversation_id, body, &token)
+            .await
+    }
+
+    pub async fn list_students_missing_assignment(
+        &self,
+        course_id: i64,
+        assignment_id: i64,
+    ) -> Result<Vec<User>> {
+        let token = self.config.read().await.token.clone();
+        let students = self.client.list_course_students(course_id, &token).await?;
+        let submissions = self
+            .client
+            .list_course_assignment_submissions(course_id, assignment_id, &token)
+            .await?;
+        Ok(filter_missing_students(students, &submissions))
+    }
+
+    // Send everyone missing the assignment a private message, return who was messaged
+    pub async fn message_students_missing_assignment(
+        &self,
+        course_id: i64,
+        assignment_id: i64,
+        subject: &str,
+        body: &str,
+    ) -> Result<Vec<User>> {
+        let students = self
+            .list_students_missing_assignment(course_id, assignment_id)
+            .await?;
+        if students.is_empty() {
+            return Ok(students);
+        }
+        let recipient_ids: Vec<_> = students.iter().map(|student| student.id).collect();
+        self.send_conversation(course_id, &recipient_ids, subject, body, true)
+            .await?;
+        tracing::info!(
+            "Messaged {} students missing assignment {}",
+            students.len(),
+            assignment_id
+        );
+        Ok(students)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(id: i64) -> User {
+        User {
+            id,
+            name: format!("Student {}", id),
+            ..Default::default()
+        }
+    }
+
+    fn submission(user_id: i64, workflow_state: WorkflowState) -> Submission {
+        Submission {
+            user_id,
+            workflow_state,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_check_recipients() {
+        let users = vec![user(1), user(2)];
+        assert!(check_recipients(&users, &[1, 2]).is_ok());
+        assert!(check_recipients(&users, &[]).is_err());
+        let err = check_recipients(&users, &[1, 3]).unwrap_err();
+        assert!(err.to_string().contains('3'));
+    }
+
+    #[test]
+    fn test_filter_missing_students() {
+        let students = vec![user(1), user(2), user(3), user(4)];
+        let submissions = vec![
+            submission(1, WorkflowState::Submitted),
+            submission(2, WorkflowState::Unsubmitted),
+            submission(3, WorkflowState::Graded),
+        ];
+        let missing = filter_missing_students(students, &submissions);
+        let ids: Vec<_> = missing.iter().map(|student| student.id).collect();
+        assert_eq!(ids, vec![2, 4]);
+    }
+}
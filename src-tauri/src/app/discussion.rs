@@ -0,0 +1,824 @@
+use std::{
+    collections::HashMap,
+    fs,
+    future::Future,
+    path::{Path, PathBuf},
+    pin::Pin,
+};
+
+use lazy_static::lazy_static;
+use md5::{Digest, Md5};
+use regex::{Captures, Regex};
+use select::{document::Document, node::Node, predicate::Name};
+
+use super::{constants::DISCUSSIONS_FOLDER_NAME, App};
+use crate::{
+    error::{AppError, Result},
+    model::{
+        Attachment, Course, DiscussionTopic, DiscussionView, EnrollmentRole, File, FullDiscussion,
+        Reply,
+    },
+    utils::{escape_html, sanitize_file_name},
+};
+
+const ATTACHMENTS_DIR_NAME: &str = "attachments";
+
+lazy_static! {
+    static ref BLANK_LINES_RE: Regex = Regex::new(r"\n[ \t]*\n(\s*\n)+").unwrap();
+    static ref FILE_URL_RE: Regex = Regex::new(r"/files/(\d+)").unwrap();
+    static ref RESOURCE_ATTR_RE: Regex = Regex::new(r#"(src|href)="([^"]*)""#).unwrap();
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+struct ArchivedEntry {
+    author: String,
+    created_at: String,
+    // html with resources already pointing to local files
+    message: String,
+    deleted: bool,
+    // relative paths of downloaded attachments
+    attachments: Vec<String>,
+    replies: Vec<ArchivedEntry>,
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+struct DiscussionArchive {
+    title: String,
+    author: String,
+    posted_at: String,
+    message: String,
+    attachments: Vec<String>,
+    entries: Vec<ArchivedEntry>,
+}
+
+impl DiscussionArchive {
+    fn count_entries(entries: &[ArchivedEntry]) -> usize {
+        entries
+            .iter()
+            .map(|entry| 1 + Self::count_entries(&entry.replies))
+            .sum()
+    }
+
+    fn render_markdown(&self) -> String {
+        let mut out = format!("# {}\n\n", self.title);
+        out += &format!("> 作者：{}　发布于：{}\n\n", self.author, self.posted_at);
+        out += &html_to_markdown(&self.message);
+        out += "\n\n";
+        render_markdown_attachments(&self.attachments, &mut out);
+        out += &format!(
+            "---\n\n## 回复（{}）\n\n",
+            Self::count_entries(&self.entries)
+        );
+        for entry in &self.entries {
+            render_markdown_entry(entry, 0, &mut out);
+        }
+        out
+    }
+
+    fn render_html(&self) -> String {
+        let mut body = format!("<h1>{}</h1>\n", escape_html(&self.title));
+        body += &format!(
+            "<p class=\"meta\">作者：{}　发布于：{}</p>\n",
+            escape_html(&self.author),
+            escape_html(&self.posted_at)
+        );
+        body += &format!("<div class=\"message\">{}</div>\n", self.message);
+        render_html_attachments(&self.attachments, &mut body);
+        body += &format!("<h2>回复（{}）</h2>\n", Self::count_entries(&self.entries));
+        for entry in &self.entries {
+            render_html_entry(entry, &mut body);
+        }
+        render_html_document(&self.title, &body)
+    }
+}
+
+// Standalone html page shared by the offline exports
+pub(super) fn render_html_document(title: &str, body: &str) -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{}</title>
+<style>
+body {{ max-width: 960px; margin: 0 auto; padding: 24px; font-family: -apple-system, "PingFang SC", "Microsoft YaHei", sans-serif; line-height: 1.6; }}
+img {{ max-width: 100%; }}
+.meta {{ color: #888; font-size: 0.9em; }}
+.entry {{ border-left: 3px solid #ddd; padding-left: 12px; margin: 12px 0; }}
+.deleted {{ color: #aaa; font-style: italic; }}
+</style>
+</head>
+<body>
+{}</body>
+</html>
+"#,
+        escape_html(title),
+        body
+    )
+}
+
+fn render_markdown_attachments(attachments: &[String], out: &mut String) {
+    if attachments.is_empty() {
+        return;
+    }
+    *out += "**附件**\n\n";
+    for path in attachments {
+        *out += &format!(
+            "- [{}]({})\n",
+            attachment_name(path),
+            path.replace(' ', "%20")
+        );
+    }
+    *out += "\n";
+}
+
+fn render_markdown_entry(entry: &ArchivedEntry, depth: usize, out: &mut String) {
+    let mut content = format!("**{}**　{}\n\n", entry.author, entry.created_at);
+    if entry.deleted {
+        content += "*该回复已被删除*\n\n";
+    } else {
+        content += &html_to_markdown(&entry.message);
+        content += "\n\n";
+        render_markdown_attachments(&entry.attachments, &mut content);
+    }
+    let prefix = "> ".repeat(depth);
+    for line in content.lines() {
+        if line.is_empty() {
+            *out += prefix.trim_end();
+        } else {
+            *out += &prefix;
+            *out += line;
+        }
+        *out += "\n";
+    }
+    for reply in &entry.replies {
+        render_markdown_entry(reply, depth + 1, out);
+    }
+    if depth == 0 {
+        *out += "\n";
+    }
+}
+
+fn render_html_attachments(attachments: &[String], out: &mut String) {
+    if attachments.is_empty() {
+        return;
+    }
+    *out += "<p><strong>附件</strong></p>\n<ul>\n";
+    for path in attachments {
+        *out += &format!(
+            "<li><a href=\"{}\">{}</a></li>\n",
+            escape_html(path),
+            escape_html(attachment_name(path))
+        );
+    }
+    *out += "</ul>\n";
+}
+
+fn render_html_entry(entry: &ArchivedEntry, out: &mut String) {
+    *out += "<div class=\"entry\">\n";
+    *out += &format!(
+        "<p class=\"meta\"><strong>{}</strong>　{}</p>\n",
+        escape_html(&entry.author),
+        escape_html(&entry.created_at)
+    );
+    if entry.deleted {
+        *out += "<p class=\"deleted\">该回复已被删除</p>\n";
+    } else {
+        *out += &format!("<div class=\"message\">{}</div>\n", entry.message);
+        render_html_attachments(&entry.attachments, out);
+    }
+    for reply in &entry.replies {
+        render_html_entry(reply, out);
+    }
+    *out += "</div>\n";
+}
+
+fn attachment_name(path: &str) -> &str {
+    path.rsplit('/').next().unwrap_or(path)
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    let mut collapsed = String::with_capacity(text.len());
+    let mut last_is_space = false;
+    for c in text.chars() {
+        if c.is_whitespace() {
+            if !last_is_space {
+                collapsed.push(' ');
+            }
+            last_is_space = true;
+        } else {
+            collapsed.push(c);
+            last_is_space = false;
+        }
+    }
+    collapsed
+}
+
+fn write_markdown_children(node: &Node, out: &mut String) {
+    for child in node.children() {
+        write_markdown(&child, out);
+    }
+}
+
+fn write_markdown(node: &Node, out: &mut String) {
+    if let Some(text) = node.as_text() {
+        *out += &collapse_whitespace(text);
+        return;
+    }
+    let Some(name) = node.name() else {
+        return;
+    };
+    match name {
+        "script" | "style" | "head" => {}
+        "br" => *out += "  \n",
+        "p" | "div" => {
+            write_markdown_children(node, out);
+            *out += "\n\n";
+        }
+        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+            let level = name[1..].parse::<usize>().unwrap_or(1);
+            *out += &format!("{} ", "#".repeat(level));
+            write_markdown_children(node, out);
+            *out += "\n\n";
+        }
+        "strong" | "b" => {
+            *out += "**";
+            write_markdown_children(node, out);
+            *out += "**";
+        }
+        "em" | "i" => {
+            *out += "*";
+            write_markdown_children(node, out);
+            *out += "*";
+        }
+        "code" => *out += &format!("`{}`", node.text()),
+        "pre" => *out += &format!("\n```\n{}\n```\n\n", node.text().trim_end()),
+        "a" => {
+            let href = node.attr("href").unwrap_or_default();
+            let mut text = String::new();
+            write_markdown_children(node, &mut text);
+            *out += &format!("[{}]({})", text.trim(), href.replace(' ', "%20"));
+        }
+        "img" => {
+            let src = node.attr("src").unwrap_or_default();
+            let alt = node.attr("alt").unwrap_or_default();
+            *out += &format!("![{}]({})", alt, src.replace(' ', "%20"));
+        }
+        "ul" | "ol" => {
+            let ordered = name == "ol";
+            *out += "\n";
+            for (index, item) in node
+                .children()
+                .filter(|child| child.name() == Some("li"))
+                .enumerate()
+            {
+                let mut text = String::new();
+                write_markdown_children(&item, &mut text);
+                let marker = if ordered {
+                    format!("{}.", index + 1)
+                } else {
+                    "-".to_owned()
+                };
+                *out += &format!("{} {}\n", marker, text.trim());
+            }
+            *out += "\n";
+        }
+        "blockquote" => {
+            let mut text = String::new();
+            write_markdown_children(node, &mut text);
+            for line in text.trim().lines() {
+                *out += &format!("> {}\n", line);
+            }
+            *out += "\n";
+        }
+        _ => write_markdown_children(node, out),
+    }
+}
+
+// Canvas stores messages as html, convert the common tags so the markdown archive stays readable
+fn html_to_markdown(html: &str) -> String {
+    let document = Document::from(html);
+    let mut out = String::new();
+    match document.find(Name("body")).next() {
+        Some(body) => write_markdown_children(&body, &mut out),
+        None => {
+            for node in document.find(|node: &Node| node.parent().is_none()) {
+                write_markdown(&node, &mut out);
+            }
+        }
+    }
+    let out = out
+        .lines()
+        .map(|line| line.trim_start_matches(' ').trim_end_matches(' '))
+        .collect::<Vec<_>>()
+        .join("\n");
+    BLANK_LINES_RE.replace_all(&out, "\n\n").trim().to_owned()
+}
+
+// Downloads the resources referenced by exported html and remembers where they were saved
+pub(super) struct ResourceLocalizer<'a> {
+    app: &'a App,
+    token: String,
+    base_url: String,
+    export_dir: PathBuf,
+    // remote url -> path relative to export dir
+    localized: HashMap<String, String>,
+}
+
+impl<'a> ResourceLocalizer<'a> {
+    pub(super) fn new(app: &'a App, token: String, base_url: String, export_dir: &Path) -> Self {
+        Self {
+            app,
+            token,
+            base_url,
+            export_dir: export_dir.to_owned(),
+            localized: Default::default(),
+        }
+    }
+
+    async fn download_canvas_file(&mut self, file: &File) -> Result<String> {
+        let local_name = sanitize_file_name(&format!("{}_{}", file.id, file.display_name));
+        let relative_path = format!("{}/{}", ATTACHMENTS_DIR_NAME, local_name);
+        let attachments_dir = self.export_dir.join(ATTACHMENTS_DIR_NAME);
+        if !attachments_dir.join(&local_name).exists() {
+            fs::create_dir_all(&attachments_dir)?;
+            let mut file = file.clone();
+            file.display_name = local_name;
+            self.app
+                .client
+                .download_file(
+                    &file,
+                    &self.token,
+                    attachments_dir.to_str().unwrap_or_default(),
+                    |_| {},
+                )
+                .await?;
+        }
+        Ok(relative_path)
+    }
+
+    async fn localize_attachment(&mut self, attachment: &Attachment) -> Result<String> {
+        self.download_canvas_file(&File::from(attachment)).await
+    }
+
+    async fn download_external_image(&mut self, url: &str) -> Result<String> {
+        let ext = Path::new(url.split(['?', '#']).next().unwrap_or_default())
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .filter(|ext| ext.len() <= 5)
+            .unwrap_or("img")
+            .to_owned();
+        let local_name = format!("{:x}.{}", Md5::digest(url), ext);
+        let attachments_dir = self.export_dir.join(ATTACHMENTS_DIR_NAME);
+        let path = attachments_dir.join(&local_name);
+        if !path.exists() {
+            let bytes = self
+                .app
+                .client
+                .get_request(url, None::<&str>)
+                .await?
+                .error_for_status()?
+                .bytes()
+                .await?;
+            fs::create_dir_all(&attachments_dir)?;
+            fs::write(&path, bytes)?;
+        }
+        Ok(format!("{}/{}", ATTACHMENTS_DIR_NAME, local_name))
+    }
+
+    async fn localize_url(&mut self, attr: &str, raw_url: &str) -> Result<Option<String>> {
+        let url = raw_url.replace("&amp;", "&");
+        let url = if url.starts_with('/') && !url.starts_with("//") {
+            format!("{}{}", self.base_url, url)
+        } else {
+            url
+        };
+        if let Some(relative_path) = self.localized.get(&url) {
+            return Ok(Some(relative_path.clone()));
+        }
+
+        let relative_path = match FILE_URL_RE.captures(&url) {
+            Some(captures) if url.starts_with(&self.base_url) => {
+                let file_id = captures[1].parse::<i64>().unwrap_or_default();
+                let file = self.app.client.get_file(file_id, &self.token).await?;
+                self.download_canvas_file(&file).await?
+            }
+            _ if attr == "src" && url.starts_with("http") => {
+                self.download_external_image(&url).await?
+            }
+            // keep ordinary links as they are
+            _ => return Ok(None),
+        };
+        self.localized.insert(url, relative_path.clone());
+        Ok(Some(relative_path))
+    }
+
+    pub(super) async fn localize_html(&mut self, html: &str) -> String {
+        let mut replacements = HashMap::new();
+        for captures in RESOURCE_ATTR_RE.captures_iter(html) {
+            let attr = &captures[1];
+            let raw_url = &captures[2];
+            if replacements.contains_key(raw_url) {
+                continue;
+            }
+            match self.localize_url(attr, raw_url).await {
+                Ok(Some(relative_path)) => {
+                    replacements.insert(raw_url.to_owned(), relative_path);
+                }
+                Ok(None) => {}
+                Err(e) => tracing::warn!("Failed to download {}: {:?}", raw_url, e),
+            }
+        }
+        RESOURCE_ATTR_RE
+            .replace_all(html, |captures: &Captures| {
+                match replacements.get(&captures[2]) {
+                    Some(relative_path) => format!("{}=\"{}\"", &captures[1], relative_path),
+                    None => captures[0].to_owned(),
+                }
+            })
+            .into_owned()
+    }
+
+    async fn localize_attachments<'b, I>(&mut self, attachments: I) -> Vec<String>
+    where
+        I: IntoIterator<Item = &'b Attachment>,
+    {
+        let mut paths = vec![];
+        for attachment in attachments {
+            match self.localize_attachment(attachment).await {
+                Ok(path) => paths.push(path),
+                Err(e) => tracing::warn!(
+                    "Failed to download attachment {}: {:?}",
+                    attachment.display_name,
+                    e
+                ),
+            }
+        }
+        paths
+    }
+}
+
+struct EntryConverter<'a, 'b> {
+    localizer: &'b mut ResourceLocalizer<'a>,
+    participants: HashMap<i64, String>,
+}
+
+impl EntryConverter<'_, '_> {
+    fn author_name(&self, user_id: Option<i64>) -> String {
+        match user_id {
+            Some(user_id) => self
+                .participants
+                .get(&user_id)
+                .cloned()
+                .unwrap_or_else(|| format!("用户 {}", user_id)),
+            None => "匿名用户".to_owned(),
+        }
+    }
+
+    async fn convert_view(&mut self, view: &DiscussionView) -> ArchivedEntry {
+        let mut entry = ArchivedEntry {
+            author: self.author_name(view.user_id),
+            created_at: view.created_at.clone().unwrap_or_default(),
+            deleted: view.deleted.unwrap_or_default(),
+            ..Default::default()
+        };
+        if !entry.deleted {
+            let message = view.message.clone().unwrap_or_default();
+            entry.message = self.localizer.localize_html(&message).await;
+            entry.attachments = self
+                .localizer
+                .localize_attachments(view.attachment.iter())
+                .await;
+        }
+        for reply in &view.replies {
+            let archived = self.convert_reply(reply).await;
+            entry.replies.push(archived);
+        }
+        entry
+    }
+
+    // replies can be nested arbitrarily deep, so box the recursive future
+    fn convert_reply<'c>(
+        &'c mut self,
+        reply: &'c Reply,
+    ) -> Pin<Box<dyn Future<Output = ArchivedEntry> + Send + 'c>> {
+        Box::pin(async move {
+            let mut entry = ArchivedEntry {
+                author: self.author_name(Some(reply.user_id)),
+                created_at: reply.created_at.clone().unwrap_or_default(),
+                deleted: reply.deleted.unwrap_or_default(),
+                ..Default::default()
+            };
+            if !entry.deleted {
+                let message = reply.message.clone().unwrap_or_default();
+                entry.message = self.localizer.localize_html(&message).await;
+                entry.attachments = self
+                    .localizer
+                    .localize_attachments(reply.attachment.iter())
+                    .await;
+            }
+            for nested in &reply.replies {
+                let archived = self.convert_reply(nested).await;
+                entry.replies.push(archived);
+            }
+            entry
+        })
+    }
+}
+
+// Apis for discussion export
+impl App {
+    async fn archive_discussion(
+        &self,
+        topic: &DiscussionTopic,
+        discussion: &FullDiscussion,
+        export_dir: &Path,
+    ) -> Result<DiscussionArchive> {
+        let token = self.config.read().await.token.clone();
+        let base_url = self.client.get_base_url().await;
+        let mut localizer = ResourceLocalizer::new(self, token, base_url, export_dir);
+
+        let message = localizer.localize_html(&topic.message).await;
+        let attachments = localizer.localize_attachments(&topic.attachments).await;
+        let participants = discussion
+            .participants
+            .iter()
+            .map(|participant| (participant.id, participant.display_name.clone()))
+            .collect();
+        let mut converter = EntryConverter {
+            localizer: &mut localizer,
+            participants,
+        };
+        let mut entries = vec![];
+        for view in &discussion.view {
+            entries.push(converter.convert_view(view).await);
+        }
+
+        Ok(DiscussionArchive {
+            title: topic.title.clone(),
+            author: topic.user_name.clone().unwrap_or_default(),
+            posted_at: topic
+                .posted_at
+                .clone()
+                .or(topic.created_at.clone())
+                .unwrap_or_default(),
+            message,
+            attachments,
+            entries,
+        })
+    }
+
+    // Export a topic with its full reply tree as index.md and index.html, return the export directory
+    pub async fn export_discussion(
+        &self,
+        course: &Course,
+        topic: &DiscussionTopic,
+    ) -> Result<String> {
+        let token = self.config.read().await.token.clone();
+        let save_path = self.config.read().await.save_path.clone();
+        let discussion = self
+            .client
+            .get_full_discussion(course.id, topic.id, &token)
+            .await?;
+        let export_dir = Path::new(&save_path)
+            .join(self.client.get_course_identifier(course))
+            .join(DISCUSSIONS_FOLDER_NAME)
+            .join(sanitize_file_name(&format!("{}_{}", topic.title, topic.id)));
+        fs::create_dir_all(&export_dir)?;
+
+        let archive = self
+            .archive_discussion(topic, &discussion, &export_dir)
+            .await?;
+        fs::write(export_dir.join("index.md"), archive.render_markdown())?;
+        fs::write(export_dir.join("index.html"), archive.render_html())?;
+        tracing::info!("Discussion {} exported to {:?}", topic.id, export_dir);
+        Ok(export_dir.to_str().unwrap_or_default().to_owned())
+    }
+
+    pub async fn export_course_discussions(&self, course: &Course) -> Result<Vec<String>> {
+        let topics = self.list_discussion_topics(course.id).await?;
+        let mut export_dirs = vec![];
+        for topic in topics {
+            match self.export_discussion(course, &topic).await {
+                Ok(export_dir) => export_dirs.push(export_dir),
+                Err(e) => tracing::error!("Failed to export discussion {}: {:?}", topic.id, e),
+            }
+        }
+        Ok(export_dirs)
+    }
+}
+
+// Apis for posting and reading discussion entries
+impl App {
+    fn check_can_reply(topic: &DiscussionTopic, has_attachment: bool) -> Result<()> {
+        if !topic.permissions.reply || topic.locked_for_user.unwrap_or_default() {
+            return Err(AppError::PermissionDenied(format!(
+                "not allowed to reply to discussion \"{}\"",
+                topic.title
+            )));
+        }
+        if has_attachment && !topic.permissions.attach {
+            return Err(AppError::PermissionDenied(format!(
+                "not allowed to attach files in discussion \"{}\"",
+                topic.title
+            )));
+        }
+        Ok(())
+    }
+
+    async fn check_can_rate(&self, course_id: i64, topic: &DiscussionTopic) -> Result<()> {
+        if !topic.allow_rating.unwrap_or_default() {
+            return Err(AppError::PermissionDenied(format!(
+                "rating is disabled in discussion \"{}\"",
+                topic.title
+            )));
+        }
+        if topic.only_graders_can_rate.unwrap_or_default() {
+            let courses = self.list_courses().await?;
+            let is_grader = courses
+                .iter()
+                .find(|course| course.id == course_id)
+                .map(|course| {
+                    course.enrollments.iter().any(|enrollment| {
+                        matches!(
+                            enrollment.role,
+                            EnrollmentRole::TaEnrollment | EnrollmentRole::TeacherEnrollment
+                        )
+                    })
+                })
+                .unwrap_or_default();
+            if !is_grader {
+                return Err(AppError::PermissionDenied(format!(
+                    "only graders can rate in discussion \"{}\"",
+                    topic.title
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn post_discussion_entry(
+        &self,
+        course_id: i64,
+        topic: &DiscussionTopic,
+        message: &str,
+        attachment_path: Option<&str>,
+    ) -> Result<DiscussionView> {
+        Self::check_can_reply(topic, attachment_path.is_some())?;
+        let token = self.config.read().await.token.clone();
+        self.client
+            .post_discussion_entry(course_id, topic.id, message, attachment_path, &token)
+            .await
+    }
+
+    // entry_id is the id of the DiscussionView or Reply being replied to
+    pub async fn reply_discussion_entry(
+        &self,
+        course_id: i64,
+        topic: &DiscussionTopic,
+        entry_id: i64,
+        message: &str,
+        attachment_path: Option<&str>,
+    ) -> Result<Reply> {
+        Self::check_can_reply(topic, attachment_path.is_some())?;
+        let token = self.config.read().await.token.clone();
+        self.client
+            .post_discussion_reply(
+                course_id,
+                topic.id,
+                entry_id,
+                message,
+                attachment_path,
+                &token,
+            )
+            .await
+    }
+
+    pub async fn rate_discussion_entry(
+        &self,
+        course_id: i64,
+        topic: &DiscussionTopic,
+        entry_id: i64,
+        liked: bool,
+    ) -> Result<()> {
+        self.check_can_rate(course_id, topic).await?;
+        let token = self.config.read().await.token.clone();
+        self.client
+            .rate_discussion_entry(course_id, topic.id, entry_id, liked, &token)
+            .await
+    }
+
+    // Return the refreshed topic so that unread_count is up to date
+    pub async fn mark_discussion_entry_read(
+        &self,
+        course_id: i64,
+        topic_id: i64,
+        entry_id: i64,
+        read: bool,
+    ) -> Result<DiscussionTopic> {
+        let token = self.config.read().await.token.clone();
+        self.client
+            .mark_discussion_entry_read(course_id, topic_id, entry_id, read, &token)
+            .await?;
+        self.client
+            .get_discussion_topic(course_id, topic_id, &token)
+            .await
+    }
+
+    pub async fn mark_discussion_topic_read(
+        &self,
+        course_id: i64,
+        topic_id: i64,
+    ) -> Result<DiscussionTopic> {
+        let token = self.config.read().await.token.clone();
+        self.client
+            .mark_discussion_topic_read(course_id, topic_id, &token)
+            .await?;
+        self.client
+            .get_discussion_topic(course_id, topic_id, &token)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::Permissions;
+
+    fn entry(author: &str, message: &str, replies: Vec<ArchivedEntry>) -> ArchivedEntry {
+        ArchivedEntry {
+            author: author.to_owned(),
+            created_at: "2024-03-01T08:00:00Z".to_owned(),
+            message: message.to_owned(),
+            replies,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_html_to_markdown() {
+        let html = r#"<p>Hello <strong>world</strong>, see <a href="attachments/1_a.pdf">slides</a></p>
+<ul><li>one</li><li>two</li></ul><p><img src="attachments/x.png" alt="fig"></p>"#;
+        assert_eq!(
+            html_to_markdown(html),
+            "Hello **world**, see [slides](attachments/1_a.pdf)\n\n- one\n- two\n\n![fig](attachments/x.png)"
+        );
+        assert_eq!(html_to_markdown("plain text"), "plain text");
+    }
+
+    #[test]
+    fn test_render_nested_replies() {
+        let archive = DiscussionArchive {
+            title: "Lab 1".to_owned(),
+            author: "TA".to_owned(),
+            posted_at: "2024-03-01".to_owned(),
+            message: "<p>Questions here</p>".to_owned(),
+            attachments: vec!["attachments/1_lab1.pdf".to_owned()],
+            entries: vec![entry(
+                "Alice",
+                "<p>Q1?</p>",
+                vec![entry(
+                    "Bob",
+                    "<p>A1</p>",
+                    vec![entry("Alice", "<p>Thanks</p>", vec![])],
+                )],
+            )],
+        };
+        let markdown = archive.render_markdown();
+        assert!(markdown.starts_with("# Lab 1\n"));
+        assert!(markdown.contains("- [1_lab1.pdf](attachments/1_lab1.pdf)"));
+        assert!(markdown.contains("## 回复（3）"));
+        assert!(markdown.contains("**Alice**　2024-03-01T08:00:00Z\n\nQ1?"));
+        assert!(markdown.contains("> **Bob**"));
+        assert!(markdown.contains("> > Thanks"));
+
+        let html = archive.render_html();
+        assert!(html.contains("<title>Lab 1</title>"));
+        assert_eq!(html.matches("<div class=\"entry\">").count(), 3);
+        assert!(html.contains("<a href=\"attachments/1_lab1.pdf\">1_lab1.pdf</a>"));
+    }
+
+    #[test]
+    fn test_check_can_reply() {
+        let mut topic = DiscussionTopic {
+            title: "Lab 1".to_owned(),
+            permissions: Permissions {
+                reply: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert!(App::check_can_reply(&topic, false).is_ok());
+        assert!(App::check_can_reply(&topic, true).is_err());
+
+        topic.permissions.attach = true;
+        assert!(App::check_can_reply(&topic, true).is_ok());
+
+        topic.locked_for_user = Some(true);
+        assert!(App::check_can_reply(&topic, false).is_err());
+
+        topic.locked_for_user = None;
+        topic.permissions.reply = false;
+        assert!(App::check_can_reply(&topic, false).is_err());
+    }
+}
@@ -0,0 +1,672 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::Path,
+    process::Stdio,
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, Instant},
+};
+
+use tokio::{
+    io::AsyncReadExt,
+    process::Command,
+    sync::{Semaphore, SemaphorePermit},
+};
+use tokio_util::sync::CancellationToken;
+use uuid::Uuid;
+
+use super::App;
+use crate::{
+    error::{AppError, Result},
+    model::{
+        FfmpegJob, FfmpegJobKind, FfmpegJobParams, FfmpegJobState, FfmpegProgress, OverlayOptions,
+        OverlayPosition,
+    },
+};
+
+// Called whenever a job is added or changes state, progress is reported at
+// most once per PROGRESS_EMIT_INTERVAL
+pub type FfmpegJobListener = Arc<dyn Fn(FfmpegJob) + Send + Sync>;
+
+// ffmpeg prints its progress several times a second
+const PROGRESS_EMIT_INTERVAL: Duration = Duration::from_millis(500);
+// Finished jobs kept in the list, the oldest ones are dropped first
+const MAX_FINISHED_JOBS: usize = 20;
+
+// Jobs started through the app, waiting for a permit to run
+pub struct FfmpegJobs {
+    jobs: Mutex<Vec<FfmpegJob>>,
+    cancel_tokens: Mutex<HashMap<String, CancellationToken>>,
+    // when the progress of a running job was last reported
+    progress_emitted_at: Mutex<HashMap<String, Instant>>,
+    // ffmpeg keeps several cores busy on its own, so only a few jobs run at once
+    permits: Semaphore,
+    listener: RwLock<Option<FfmpegJobListener>>,
+}
+
+impl Default for FfmpegJobs {
+    fn default() -> Self {
+        Self {
+            jobs: Default::default(),
+            cancel_tokens: Default::default(),
+            progress_emitted_at: Default::default(),
+            permits: Semaphore::new((num_cpus::get() / 4).max(1)),
+            listener: Default::default(),
+        }
+    }
+}
+
+// `HH:MM:SS.xx` as printed by ffmpeg, in seconds
+fn parse_ffmpeg_timestamp(timestamp: &str) -> Option<f64> {
+    let mut parts = timestamp.trim().splitn(3, ':');
+    let hours: f64 = parts.next()?.parse().ok()?;
+    let minutes: f64 = parts.next()?.parse().ok()?;
+    let seconds: f64 = parts.next()?.parse().ok()?;
+    Some(hours * 3600.0 + minutes * 60.0 + seconds)
+}
+
+// The value of `key` in a line like `Duration: 00:01:02.50, start: ...` or
+// `frame=  100 fps= 25 ... time=00:00:04.00 bitrate=...`
+fn parse_ffmpeg_field(line: &str, key: &str) -> Option<f64> {
+    let value = line.split(key).nth(1)?.trim_start();
+    let end = value
+        .find(|c: char| c == ',' || c.is_whitespace())
+        .unwrap_or(value.len());
+    parse_ffmpeg_timestamp(&value[..end])
+}
+
+// Turns the time ffmpeg has encoded so far into a percentage of the input.
+// Without a known duration the one ffmpeg reports for its first input is used.
+#[derive(Debug)]
+pub(super) struct FfmpegProgressParser {
+    duration: Option<f64>,
+    // seconds of input per second of output
+    speed: f64,
+}
+
+impl Default for FfmpegProgressParser {
+    fn default() -> Self {
+        Self::new(None, 1.0)
+    }
+}
+
+impl FfmpegProgressParser {
+    pub fn new(duration: Option<f64>, speed: f64) -> Self {
+        Self { duration, speed }
+    }
+
+    pub fn parse_line(&mut self, line: &str) -> Option<FfmpegProgress> {
+        if line.trim_start().starts_with("Duration:") {
+            if self.duration.is_none() {
+                self.duration = parse_ffmpeg_field(line, "Duration:");
+            }
+            return None;
+        }
+        let time = parse_ffmpeg_field(line, "time=")?;
+        let duration = self.duration.unwrap_or_default();
+        let percentage = if duration > 0.0 {
+            (time * self.speed / duration * 100.0).clamp(0.0, 100.0)
+        } else {
+            0.0
+        };
+        Some(FfmpegProgress {
+            time,
+            duration,
+            percentage,
+        })
+    }
+}
+
+fn overlay_filter(options: &OverlayOptions) -> String {
+    let scale = options.size_percentage as f64 / 100.0;
+    let alpha = options.alpha as f64 / 100.0;
+    let position = match options.position {
+        OverlayPosition::TopLeft => "0:0",
+        OverlayPosition::TopRight => "W-w:0",
+        OverlayPosition::BottomLeft => "0:H-h",
+        OverlayPosition::BottomRight => "W-w:H-h",
+    };
+    format!(
+        "[1:v]scale=iw*{scale}:ih*{scale},format=yuva420p,colorchannelmixer=aa={alpha}[overlay];[0:v][overlay]overlay={position}:format=auto"
+    )
+}
+
+fn overlay_args(main: &str, sub: &str, output: &str, options: &OverlayOptions) -> Vec<String> {
+    [
+        "-i",
+        main,
+        "-i",
+        sub,
+        "-filter_complex",
+        &overlay_filter(options),
+        "-c:a",
+        "copy",
+        output,
+    ]
+    .map(str::to_owned)
+    .to_vec()
+}
+
+// Quotes arguments with spaces so the logged command can be pasted in a shell
+pub(super) fn format_command(args: &[String]) -> String {
+    let args: Vec<_> = args
+        .iter()
+        .map(|arg| {
+            if arg.contains([' ', ';', '[']) {
+                format!("\"{}\"", arg)
+            } else {
+                arg.clone()
+            }
+        })
+        .collect();
+    format!("ffmpeg {}", args.join(" "))
+}
+
+// older ffmpeg versions only take atempo factors up to 2
+fn atempo_chain(speed: f64) -> String {
+    let mut filters = vec![];
+    let mut remaining = speed;
+    while remaining > 2.0 {
+        filters.push("atempo=2".to_owned());
+        remaining /= 2.0;
+    }
+    filters.push(format!("atempo={}", remaining));
+    filters.join(",")
+}
+
+fn is_finished(job: &FfmpegJob) -> bool {
+    !matches!(job.state, FfmpegJobState::Queued | FfmpegJobState::Running)
+}
+
+fn job_input(kind: &FfmpegJobKind) -> &str {
+    match kind {
+        FfmpegJobKind::Overlay { input_path, .. }
+        | FfmpegJobKind::Transcode { input_path, .. }
+        | FfmpegJobKind::ExtractAudio { input_path, .. }
+        | FfmpegJobKind::SpeedUp { input_path, .. }
+        | FfmpegJobKind::SampleFrames { input_path, .. } => input_path,
+    }
+}
+
+// Paths are compared as resolved when both exist, `./a.mp4` is `a.mp4`
+fn is_same_file(a: &str, b: &str) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => Path::new(a) == Path::new(b),
+    }
+}
+
+fn job_speed(kind: &FfmpegJobKind) -> f64 {
+    match kind {
+        FfmpegJobKind::SpeedUp { speed, .. } => *speed,
+        _ => 1.0,
+    }
+}
+
+// The output of a job is picked by the user, so it's overwritten. It may not
+// be one of the inputs, ffmpeg would truncate it while still reading it.
+pub(super) fn job_args(params: &FfmpegJobParams) -> Result<Vec<String>> {
+    let output = params.output_path.as_str();
+    let mut inputs = vec![job_input(&params.kind)];
+    if let FfmpegJobKind::Overlay { sub_path, .. } = &params.kind {
+        inputs.push(sub_path);
+    }
+    if inputs.iter().any(|input| is_same_file(input, output)) {
+        return Err(AppError::FfmpegError(format!(
+            "output {} is an input of the job",
+            output
+        )));
+    }
+    let mut args = vec!["-y".to_owned()];
+    match &params.kind {
+        FfmpegJobKind::Overlay {
+            input_path,
+            sub_path,
+            options,
+        } => args.extend(overlay_args(input_path, sub_path, output, options)),
+        FfmpegJobKind::Transcode { input_path, crf } => args.extend(
+            [
+                "-i",
+                input_path,
+                "-c:v",
+                "libx265",
+                "-crf",
+                &crf.to_string(),
+                // lets players on macOS recognize the stream
+                "-tag:v",
+                "hvc1",
+                "-c:a",
+                "copy",
+                output,
+            ]
+            .map(str::to_owned),
+        ),
+        FfmpegJobKind::ExtractAudio {
+            input_path,
+            for_speech,
+        } => {
+            args.extend(["-i", input_path, "-vn"].map(str::to_owned));
+            if *for_speech {
+                args.extend(["-ar", "16000", "-ac", "1", "-c:a", "pcm_s16le"].map(str::to_owned));
+            }
+            args.push(output.to_owned());
+        }
+        FfmpegJobKind::SpeedUp { input_path, speed } => {
+            if !(0.5..=4.0).contains(speed) {
+                return Err(AppError::FfmpegError(format!(
+                    "unsupported speed {}",
+                    speed
+                )));
+            }
+            // screen recordings may come without audio, so it's mapped optionally
+            args.extend(
+                [
+                    "-i",
+                    input_path,
+                    "-map",
+                    "0:v",
+                    "-map",
+                    "0:a?",
+                    "-filter:v",
+                    &format!("setpts=PTS/{}", speed),
+                    "-filter:a",
+                    &atempo_chain(*speed),
+                    output,
+                ]
+                .map(str::to_owned),
+            );
+        }
+        FfmpegJobKind::SampleFrames {
+            input_path,
+            interval,
+        } => {
+            if *interval <= 0.0 {
+                return Err(AppError::FfmpegError(format!(
+                    "unsupported interval {}",
+                    interval
+                )));
+            }
+            let filter = format!("fps=1/{}", interval);
+            args.extend(["-i", input_path, "-vf", &filter, "-an", output].map(str::to_owned));
+        }
+    }
+    Ok(args)
+}
+
+// Duration of a media file in seconds, None if ffprobe isn't there or fails
+pub(super) async fn probe_duration(path: &str) -> Option<f64> {
+    let output = Command::new("ffprobe")
+        .args([
+            "-v",
+            "error",
+            "-show_entries",
+            "format=duration",
+            "-of",
+            "default=noprint_wrappers=1:nokey=1",
+            path,
+        ])
+        .output()
+        .await
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8_lossy(&output.stdout).trim().parse().ok()
+}
+
+// Runs ffmpeg, handing every line it prints and the progress parsed from
+// them to the callbacks. Returns the exit code, or kills ffmpeg and fails
+// with `FfmpegCancelled` once the token is cancelled.
+pub(super) async fn run_ffmpeg<O: Fn(&str), P: Fn(FfmpegProgress)>(
+    args: &[String],
+    mut parser: FfmpegProgressParser,
+    cancel_token: &CancellationToken,
+    on_output: O,
+    on_progress: P,
+) -> Result<i32> {
+    tracing::info!("Running {}", format_command(args));
+    let mut command = Command::new("ffmpeg")
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()?;
+    let mut stderr = command.stderr.take().ok_or(AppError::OpenStderrError)?;
+
+    let mut buffer = [0; 1024];
+    let mut pending = String::new();
+    loop {
+        let bytes_read = tokio::select! {
+            _ = cancel_token.cancelled() => {
+                command.kill().await?;
+                return Err(AppError::FfmpegCancelled);
+            }
+            read = stderr.read(&mut buffer) => match read {
+                Ok(0) | Err(_) => break, // EOF
+                Ok(bytes_read) => bytes_read,
+            },
+        };
+        pending.push_str(&String::from_utf8_lossy(&buffer[..bytes_read]));
+        // progress lines end with \r, everything else with \n
+        while let Some(end) = pending.find(['\r', '\n']) {
+            let line: String = pending.drain(..=end).collect();
+            let line = line.trim_end();
+            if line.is_empty() {
+                continue;
+            }
+            on_output(line);
+            if let Some(progress) = parser.parse_line(line) {
+                on_progress(progress);
+            }
+        }
+    }
+    if !pending.trim().is_empty() {
+        on_output(pending.trim_end());
+    }
+
+    let status = command.wait().await?;
+    Ok(status.code().unwrap_or_default())
+}
+
+impl App {
+    pub fn watch_ffmpeg_jobs(&self, listener: FfmpegJobListener) {
+        *self.ffmpeg_jobs.listener.write().unwrap() = Some(listener);
+    }
+
+    pub fn list_ffmpeg_jobs(&self) -> Vec<FfmpegJob> {
+        self.ffmpeg_jobs.jobs.lock().unwrap().clone()
+    }
+
+    // Drops finished jobs from the list
+    pub fn clear_ffmpeg_jobs(&self) {
+        self.ffmpeg_jobs
+            .jobs
+            .lock()
+            .unwrap()
+            .retain(|job| !is_finished(job));
+    }
+
+    fn emit_ffmpeg_job(&self, job: FfmpegJob) {
+        if let Some(listener) = self.ffmpeg_jobs.listener.read().unwrap().as_ref() {
+            listener(job);
+        }
+    }
+
+    fn update_ffmpeg_job<F: FnOnce(&mut FfmpegJob)>(
+        &self,
+        id: &str,
+        update: F,
+    ) -> Option<FfmpegJob> {
+        let mut jobs = self.ffmpeg_jobs.jobs.lock().unwrap();
+        let job = jobs.iter_mut().find(|job| job.id == id)?;
+        update(job);
+        Some(job.clone())
+    }
+
+    fn set_ffmpeg_job_state<F: FnOnce(&mut FfmpegJob)>(&self, id: &str, update: F) {
+        if let Some(job) = self.update_ffmpeg_job(id, update) {
+            self.emit_ffmpeg_job(job);
+        }
+    }
+
+    fn set_ffmpeg_job_progress(&self, id: &str, progress: &FfmpegProgress) {
+        let Some(job) = self.update_ffmpeg_job(id, |job| job.progress = progress.clone()) else {
+            return;
+        };
+        let now = Instant::now();
+        let due = {
+            let mut emitted_at = self.ffmpeg_jobs.progress_emitted_at.lock().unwrap();
+            let due = emitted_at
+                .get(id)
+                .is_none_or(|last| now.duration_since(*last) >= PROGRESS_EMIT_INTERVAL);
+            if due {
+                emitted_at.insert(id.to_owned(), now);
+            }
+            due
+        };
+        if due {
+            self.emit_ffmpeg_job(job);
+        }
+    }
+
+    fn prune_ffmpeg_jobs(&self) {
+        let mut jobs = self.ffmpeg_jobs.jobs.lock().unwrap();
+        let finished = jobs.iter().filter(|job| is_finished(job)).count();
+        let mut excess = finished.saturating_sub(MAX_FINISHED_JOBS);
+        // jobs are kept in the order they were created
+        jobs.retain(|job| {
+            if excess > 0 && is_finished(job) {
+                excess -= 1;
+                return false;
+            }
+            true
+        });
+    }
+
+    // Queues a job, it's run by `execute_ffmpeg_job`
+    pub(super) fn create_ffmpeg_job(
+        &self,
+        params: FfmpegJobParams,
+    ) -> Result<(String, CancellationToken)> {
+        self.create_ffmpeg_job_with_token(params, CancellationToken::new())
+    }
+
+    // For jobs which are a step of a larger task, cancelling the task's token
+    // cancels them too
+    pub(super) fn create_ffmpeg_job_with_token(
+        &self,
+        params: FfmpegJobParams,
+        cancel_token: CancellationToken,
+    ) -> Result<(String, CancellationToken)> {
+        // fail on invalid params right away rather than once the job's turn comes
+        job_args(&params)?;
+        let job = FfmpegJob {
+            id: Uuid::new_v4().to_string(),
+            params,
+            state: FfmpegJobState::Queued,
+            progress: Default::default(),
+            error: None,
+        };
+        self.ffmpeg_jobs
+            .cancel_tokens
+            .lock()
+            .unwrap()
+            .insert(job.id.clone(), cancel_token.clone());
+        self.ffmpeg_jobs.jobs.lock().unwrap().push(job.clone());
+        let id = job.id.clone();
+        self.emit_ffmpeg_job(job);
+        Ok((id, cancel_token))
+    }
+
+    // Other heavy commands, e.g. speech to text, share the limit of ffmpeg jobs
+    pub(super) async fn acquire_ffmpeg_permit(
+        &self,
+        cancel_token: &CancellationToken,
+    ) -> Result<SemaphorePermit<'_>> {
+        tokio::select! {
+            _ = cancel_token.cancelled() => Err(AppError::FfmpegCancelled),
+            permit = self.ffmpeg_jobs.permits.acquire() => {
+                permit.map_err(|e| AppError::FfmpegError(e.to_string()))
+            }
+        }
+    }
+
+    // Waits for a permit, then runs the job. Returns the exit code of ffmpeg.
+    pub(super) async fn execute_ffmpeg_job<O: Fn(&str), P: Fn(FfmpegProgress)>(
+        &self,
+        id: &str,
+        cancel_token: CancellationToken,
+        on_output: O,
+        on_progress: P,
+    ) -> Result<i32> {
+        let params = self
+            .list_ffmpeg_jobs()
+            .into_iter()
+            .find(|job| job.id == id)
+            .map(|job| job.params)
+            .ok_or_else(|| AppError::FfmpegError(format!("job {} not found", id)))?;
+        let last_line = Mutex::new(String::new());
+        let result = async {
+            let args = job_args(&params)?;
+            let _permit = self.acquire_ffmpeg_permit(&cancel_token).await?;
+            self.set_ffmpeg_job_state(id, |job| job.state = FfmpegJobState::Running);
+            let duration = probe_duration(job_input(&params.kind)).await;
+            let parser = FfmpegProgressParser::new(duration, job_speed(&params.kind));
+            run_ffmpeg(
+                &args,
+                parser,
+                &cancel_token,
+                |line| {
+                    *last_line.lock().unwrap() = line.to_owned();
+                    on_output(line);
+                },
+                |progress| {
+                    self.set_ffmpeg_job_progress(id, &progress);
+                    on_progress(progress);
+                },
+            )
+            .await
+        }
+        .await;
+        self.ffmpeg_jobs.cancel_tokens.lock().unwrap().remove(id);
+        self.ffmpeg_jobs
+            .progress_emitted_at
+            .lock()
+            .unwrap()
+            .remove(id);
+
+        let last_line = last_line.into_inner().unwrap();
+        self.set_ffmpeg_job_state(id, |job| match &result {
+            Ok(0) => {
+                job.state = FfmpegJobState::Succeeded;
+                job.progress.percentage = 100.0;
+            }
+            Ok(exit_code) => {
+                job.state = FfmpegJobState::Failed;
+                job.error = Some(format!("exit code {}: {}", exit_code, last_line));
+            }
+            Err(AppError::FfmpegCancelled) => job.state = FfmpegJobState::Cancelled,
+            Err(e) => {
+                job.state = FfmpegJobState::Failed;
+                job.error = Some(e.to_string());
+            }
+        });
+        self.prune_ffmpeg_jobs();
+        tracing::info!("ffmpeg job {} finished: {:?}", id, result);
+        result
+    }
+
+    // Queues a job in the background and returns its id
+    pub fn submit_ffmpeg_job(self: &Arc<Self>, params: FfmpegJobParams) -> Result<String> {
+        let (id, cancel_token) = self.create_ffmpeg_job(params)?;
+        let app = self.clone();
+        let job_id = id.clone();
+        tokio::spawn(async move {
+            let _ = app
+                .execute_ffmpeg_job(&job_id, cancel_token, |_| {}, |_| {})
+                .await;
+        });
+        Ok(id)
+    }
+
+    // Removes a queued job from the queue or kills a running one
+    pub fn cancel_ffmpeg_job(&self, id: &str) -> bool {
+        match self.ffmpeg_jobs.cancel_tokens.lock().unwrap().get(id) {
+            Some(cancel_token) => {
+                cancel_token.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ffmpeg_progress() {
+        let mut parser = FfmpegProgressParser::default();
+        assert_eq!(parser.parse_line("ffmpeg version 6.1"), None);
+        assert_eq!(
+            parser.parse_line("  Duration: 00:02:00.00, start: 0.000000, bitrate: 1000 kb/s"),
+            None
+        );
+        // the duration of later inputs is ignored
+        parser.parse_line("  Duration: 01:00:00.00, start: 0.000000, bitrate: 10 kb/s");
+        let progress = parser
+            .parse_line("frame= 1500 fps=250 q=28.0 size=1024kB time=00:00:30.00 bitrate=279.6kbits/s speed=5x")
+            .unwrap();
+        assert_eq!(progress.duration, 120.0);
+        assert_eq!(progress.time, 30.0);
+        assert_eq!(progress.percentage, 25.0);
+        assert_eq!(parser.parse_line("frame=1 time=N/A bitrate=N/A"), None);
+
+        // the output of a sped up video is shorter than its input
+        let mut parser = FfmpegProgressParser::new(Some(120.0), 2.0);
+        let progress = parser
+            .parse_line("frame=1 time=00:00:30.00 speed=5x")
+            .unwrap();
+        assert_eq!(progress.percentage, 50.0);
+    }
+
+    #[test]
+    fn test_job_args() -> Result<()> {
+        let params = FfmpegJobParams {
+            kind: FfmpegJobKind::SpeedUp {
+                input_path: "in.mp4".to_owned(),
+                speed: 3.0,
+            },
+            output_path: "out.mp4".to_owned(),
+        };
+        let args = job_args(&params)?;
+        let value_of = |flag: &str| {
+            let position = args.iter().position(|arg| arg == flag).unwrap();
+            args[position + 1].clone()
+        };
+        assert_eq!(value_of("-filter:v"), "setpts=PTS/3");
+        assert_eq!(value_of("-filter:a"), "atempo=2,atempo=1.5");
+        assert!(args.contains(&"0:a?".to_owned()));
+        assert_eq!(args.last().unwrap(), "out.mp4");
+
+        let params = FfmpegJobParams {
+            kind: FfmpegJobKind::SpeedUp {
+                input_path: "in.mp4".to_owned(),
+                speed: 10.0,
+            },
+            output_path: "out.mp4".to_owned(),
+        };
+        assert!(job_args(&params).is_err());
+
+        let params = FfmpegJobParams {
+            kind: FfmpegJobKind::Transcode {
+                input_path: "videos/in.mp4".to_owned(),
+                crf: 28,
+            },
+            output_path: "videos//in.mp4".to_owned(),
+        };
+        assert!(job_args(&params).is_err());
+        let params = FfmpegJobParams {
+            kind: FfmpegJobKind::Overlay {
+                input_path: "main.mp4".to_owned(),
+                sub_path: "sub.mp4".to_owned(),
+                options: Default::default(),
+            },
+            output_path: "sub.mp4".to_owned(),
+        };
+        assert!(job_args(&params).is_err());
+
+        let params = FfmpegJobParams {
+            kind: FfmpegJobKind::SampleFrames {
+                input_path: "in.mp4".to_owned(),
+                interval: 2.5,
+            },
+            output_path: "frames/%06d.png".to_owned(),
+        };
+        let args = job_args(&params)?;
+        assert_eq!(args[4], "fps=1/2.5");
+        Ok(())
+    }
+}
@@ -0,0 +1,252 @@
+use std::{path::Path, time::Duration};
+
+use xlsxwriter::Workbook;
+
+use super::App;
+use crate::{
+    error::{AppError, Result},
+    model::{Assignment, GroupCategoryWithGroups, Progress, User},
+};
+
+// Canvas applies bulk grades in a background job, poll it for at most a minute
+const PROGRESS_POLL_INTERVAL: Duration = Duration::from_secs(1);
+const PROGRESS_POLL_TIMES: u32 = 60;
+
+const ROSTER_HEADERS: [&str; 7] = [
+    "group_category",
+    "group_id",
+    "group_name",
+    "user_id",
+    "name",
+    "login_id",
+    "email",
+];
+
+// One row per group member, empty groups still get a row so they show up in the roster
+fn roster_rows(categories: &[GroupCategoryWithGroups]) -> Vec<Vec<String>> {
+    let mut rows = vec![];
+    for category in categories {
+        for group in &category.groups {
+            let group_columns = vec![
+                category.category.name.clone(),
+                group.id.to_string(),
+                group.name.clone(),
+            ];
+            if group.members.is_empty() {
+                let mut row = group_columns.clone();
+                row.extend(vec![String::new(); 4]);
+                rows.push(row);
+            }
+            for member in &group.members {
+                let mut row = group_columns.clone();
+                row.push(member.id.to_string());
+                row.push(member.name.clone());
+                row.push(member.login_id.clone());
+                row.push(member.email.clone().unwrap_or_default());
+                rows.push(row);
+            }
+        }
+    }
+    rows
+}
+
+// Returns the group category of the assignment if its groups can be graded as a whole
+fn group_grading_category(assignment: &Assignment) -> Result<i64> {
+    let Some(category_id) = assignment.group_category_id else {
+        return Err(AppError::GroupGrading(format!(
+            "{} is not a group assignment",
+            assignment.name
+        )));
+    };
+    if assignment.grade_group_students_individually == Some(true) {
+        return Err(AppError::GroupGrading(format!(
+            "{} grades group members individually",
+            assignment.name
+        )));
+    }
+    Ok(category_id)
+}
+
+impl App {
+    // All group categories of a course with their groups and members
+    pub async fn list_course_groups(&self, course_id: i64) -> Result<Vec<GroupCategoryWithGroups>> {
+        let token = self.config.read().await.token.clone();
+        let categories = self.client.list_group_categories(course_id, &token).await?;
+        let mut result = vec![];
+        for category in categories {
+            let mut groups = self
+                .client
+                .list_category_groups(category.id, &token)
+                .await?;
+            for group in groups.iter_mut() {
+                group.members = self.client.list_group_users(group.id, &token).await?;
+            }
+            result.push(GroupCategoryWithGroups { category, groups });
+        }
+        Ok(result)
+    }
+
+    pub async fn list_group_users(&self, group_id: i64) -> Result<Vec<User>> {
+        let token = self.config.read().await.token.clone();
+        self.client.list_group_users(group_id, &token).await
+    }
+
+    async fn wait_for_progress(&self, mut progress: Progress, token: &str) -> Result<()> {
+        for _ in 0..PROGRESS_POLL_TIMES {
+            match progress.workflow_state.as_str() {
+                "completed" => return Ok(()),
+                "failed" => {
+                    return Err(AppError::GroupGrading(
+                        progress
+                            .message
+                            .unwrap_or_else(|| "canvas failed to apply the grades".to_owned()),
+                    ))
+                }
+                _ => {}
+            }
+            tokio::time::sleep(PROGRESS_POLL_INTERVAL).await;
+            progress = self.client.get_progress(progress.id, token).await?;
+        }
+        Err(AppError::GroupGrading(
+            "timed out waiting for canvas to apply the grades".to_owned(),
+        ))
+    }
+
+    // Give every member of the group the same grade in one request and wait until
+    // canvas has applied it, return the graded members
+    pub async fn grade_group_submission(
+        &self,
+        course_id: i64,
+        assignment: &Assignment,
+        group_id: i64,
+        grade: &str,
+        comment: Option<&str>,
+    ) -> Result<Vec<User>> {
+        let category_id = group_grading_category(assignment)?;
+        let token = self.config.read().await.token.clone();
+        let groups = self
+            .client
+            .list_category_groups(category_id, &token)
+            .await?;
+        if !groups.iter().any(|group| group.id == group_id) {
+            return Err(AppError::GroupGrading(format!(
+                "group {} is not in the group set of {}",
+                group_id, assignment.name
+            )));
+        }
+        let members = self.client.list_group_users(group_id, &token).await?;
+        if members.is_empty() {
+            return Ok(members);
+        }
+        let student_ids: Vec<_> = members.iter().map(|member| member.id).collect();
+        let progress = self
+            .client
+            .update_grade(
+                course_id,
+                assignment.id,
+                &student_ids,
+                grade,
+                comment,
+                &token,
+            )
+            .await?;
+        self.wait_for_progress(progress, &token).await?;
+        tracing::info!(
+            "Graded {} members of group {} for assignment {}",
+            members.len(),
+            group_id,
+            assignment.name
+        );
+        Ok(members)
+    }
+
+    pub async fn export_group_rosters(&self, course_id: i64, save_name: &str) -> Result<()> {
+        let categories = self.list_course_groups(course_id).await?;
+        let save_path = self.config.read().await.save_path.clone();
+        let path = Path::new(&save_path).join(save_name);
+
+        let workbook = Workbook::new(path.to_str().unwrap())?;
+        let mut sheet = workbook.add_worksheet(None)?;
+        for (col, header) in ROSTER_HEADERS.iter().enumerate() {
+            sheet.write_string(0, col as u16, header, None)?;
+        }
+        for (row, row_data) in roster_rows(&categories).iter().enumerate() {
+            for (col, col_data) in row_data.iter().enumerate() {
+                sheet.write_string(row as u32 + 1, col as u16, col_data, None)?;
+            }
+        }
+        workbook.close()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{Group, GroupCategory};
+
+    #[test]
+    fn test_roster_rows() {
+        let categories = vec![GroupCategoryWithGroups {
+            category: GroupCategory {
+                id: 1,
+                name: "Project".to_owned(),
+                ..Default::default()
+            },
+            groups: vec![
+                Group {
+                    id: 10,
+                    name: "Team A".to_owned(),
+                    members: vec![
+                        User {
+                            id: 100,
+                            name: "Alice".to_owned(),
+                            ..Default::default()
+                        },
+                        User {
+                            id: 101,
+                            name: "Bob".to_owned(),
+                            email: Some("bob@sjtu.edu.cn".to_owned()),
+                            ..Default::default()
+                        },
+                    ],
+                    ..Default::default()
+                },
+                Group {
+                    id: 11,
+                    name: "Team B".to_owned(),
+                    ..Default::default()
+                },
+            ],
+        }];
+        let rows = roster_rows(&categories);
+        assert_eq!(rows.len(), 3);
+        assert_eq!(
+            rows[1],
+            vec![
+                "Project",
+                "10",
+                "Team A",
+                "101",
+                "Bob",
+                "",
+                "bob@sjtu.edu.cn"
+            ]
+        );
+        assert_eq!(rows[2], vec!["Project", "11", "Team B", "", "", "", ""]);
+        assert!(rows.iter().all(|row| row.len() == ROSTER_HEADERS.len()));
+    }
+
+    #[test]
+    fn test_group_grading_category() {
+        let mut assignment = Assignment {
+            name: "Project".to_owned(),
+            ..Default::default()
+        };
+        assert!(group_grading_category(&assignment).is_err());
+        assignment.group_category_id = Some(7);
+        assert_eq!(group_grading_category(&assignment).unwrap(), 7);
+        assignment.grade_group_students_individually = Some(true);
+        assert!(group_grading_category(&assignment).is_err());
+    }
+}
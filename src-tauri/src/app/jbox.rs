@@ -21,8 +21,12 @@ impl App {
         save_dir: &str,
         progress_handler: F,
     ) -> Result<()> {
-        let config = self.get_config().await;
-        let info = config.jbox_login_info;
+        let mut info = self.get_config().await.jbox_login_info;
+        if info.is_expired() {
+            tracing::info!("JBox token expired, logging in again");
+            self.login_jbox().await?;
+            info = self.get_config().await.jbox_login_info;
+        }
         self.client
             .upload_file(file, save_dir, &info, progress_handler)
             .await
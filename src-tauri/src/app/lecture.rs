@@ -0,0 +1,543 @@
+use std::{
+    collections::HashSet,
+    fs,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+
+use chrono::{DateTime, Local, NaiveDateTime};
+use tokio_util::sync::CancellationToken;
+
+use super::{
+    ffmpeg::job_args,
+    library::{library_entry, render_video_name},
+    video::video_save_path,
+    App,
+};
+use crate::{
+    error::{AppError, Result},
+    model::{
+        CanvasVideo, FfmpegJobKind, FfmpegJobParams, LectureMergeParams, LectureMergeProgress,
+        LectureMergeStage, ProgressPayload, VideoBatchFailure, VideoBatchProgress,
+        VideoBatchReport, VideoCourse, VideoInfo, VideoLibraryEntry, VideoPlayInfo,
+    },
+    utils::sanitize_file_name,
+};
+
+const LECTURE_TIME_FORMAT: &str = "%Y-%m-%d_%H-%M";
+
+// One channel of a lecture recording with the name it's saved as
+struct LectureFile {
+    save_name: String,
+    play: VideoPlayInfo,
+    entry: VideoLibraryEntry,
+}
+
+pub(super) fn format_lecture_time_ms(timestamp_ms: i64) -> String {
+    DateTime::from_timestamp_millis(timestamp_ms)
+        .map(|time| {
+            time.with_timezone(&Local)
+                .format(LECTURE_TIME_FORMAT)
+                .to_string()
+        })
+        .unwrap_or_else(|| timestamp_ms.to_string())
+}
+
+// Canvas sends the begin time of a lecture as `2024-03-01 08:00:00`
+fn format_lecture_time(time: &str) -> String {
+    NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M:%S")
+        .map(|time| time.format(LECTURE_TIME_FORMAT).to_string())
+        .unwrap_or_else(|_| time.to_owned())
+}
+
+// `{subject}_{begin time}.mp4` for the classroom channel, the screen
+// recordings are marked like the video page does
+pub(super) fn lecture_file_name(subject: &str, begin_time: &str, channel: usize) -> String {
+    let channel = match channel {
+        0 => String::new(),
+        1 => "_录屏".to_owned(),
+        n => format!("_录屏_{}", n),
+    };
+    sanitize_file_name(&format!("{}_{}{}.mp4", subject, begin_time, channel))
+}
+
+// Channels of a lecture numbered from 0 by the channel number the video site
+// gives them, the classroom comes first. The order of the list isn't reliable.
+pub(super) fn lecture_channels(info: &VideoInfo) -> Vec<(usize, &VideoPlayInfo)> {
+    let mut plays: Vec<_> = info.video_play_response_vo_list.iter().collect();
+    plays.sort_by_key(|play| play.cdvi_channel_num);
+    plays.into_iter().enumerate().collect()
+}
+
+// Names follow the configured template, `index` is the position of the
+// lecture in its course starting from 1
+fn lecture_files(
+    template: &str,
+    subject: &str,
+    begin_time: &str,
+    index: usize,
+    info: VideoInfo,
+    names: &mut HashSet<String>,
+) -> Result<Vec<LectureFile>> {
+    let mut files = vec![];
+    for (channel, play) in lecture_channels(&info) {
+        let mut entry = library_entry(&info, subject, begin_time, channel);
+        let mut save_name = if template.is_empty() {
+            lecture_file_name(subject, begin_time, channel)
+        } else {
+            render_video_name(template, &entry, index)?
+        };
+        // two lectures starting at the same minute shouldn't overwrite each other
+        if names.contains(&save_name) {
+            save_name = if template.is_empty() {
+                lecture_file_name(subject, &format!("{}_{}", begin_time, play.id), channel)
+            } else {
+                let (stem, extension) = save_name.rsplit_once('.').unwrap_or((&save_name, "mp4"));
+                format!("{}_{}.{}", stem, play.id, extension)
+            };
+        }
+        names.insert(save_name.clone());
+        entry.path = save_name.clone();
+        files.push(LectureFile {
+            save_name,
+            play: play.clone(),
+            entry,
+        });
+    }
+    Ok(files)
+}
+
+impl App {
+    // Downloads both channels of every lecture of a course one after another.
+    // Files which already exist are skipped and a failed lecture doesn't stop
+    // the others; the batch can be stopped with `cancel_video_batch`.
+    pub async fn download_video_course<F: Fn(VideoBatchProgress) + Send + Sync + 'static>(
+        &self,
+        batch_id: &str,
+        course: &VideoCourse,
+        progress_handler: F,
+    ) -> Result<VideoBatchReport> {
+        let template = self.config.read().await.video_name_template.clone();
+        let mut report = VideoBatchReport::default();
+        let mut names = HashSet::new();
+        let mut files = vec![];
+        for (index, video) in course.response_vo_list.iter().enumerate() {
+            let begin_time = format_lecture_time_ms(video.cour_begin_time);
+            match self.get_video_info(video.id).await {
+                Ok(info) => files.extend(lecture_files(
+                    &template,
+                    &course.subj_name,
+                    &begin_time,
+                    index + 1,
+                    info,
+                    &mut names,
+                )?),
+                // every other lecture would fail the same way
+                Err(e @ AppError::VideoSessionUnrecoverable(_)) => return Err(e),
+                Err(e) => report.failed.push(VideoBatchFailure {
+                    name: lecture_file_name(&course.subj_name, &begin_time, 0),
+                    error: e.to_string(),
+                }),
+            }
+        }
+        self.download_lectures(batch_id, files, report, progress_handler)
+            .await
+    }
+
+    pub async fn download_canvas_videos<F: Fn(VideoBatchProgress) + Send + Sync + 'static>(
+        &self,
+        batch_id: &str,
+        videos: &[CanvasVideo],
+        progress_handler: F,
+    ) -> Result<VideoBatchReport> {
+        let template = self.config.read().await.video_name_template.clone();
+        let mut report = VideoBatchReport::default();
+        let mut names = HashSet::new();
+        let mut files = vec![];
+        for (index, video) in videos.iter().enumerate() {
+            let begin_time = format_lecture_time(&video.course_begin_time);
+            match self.client.get_canvas_video_info(&video.video_id).await {
+                Ok(info) => {
+                    let subject = if info.subj_name.is_empty() {
+                        video.video_name.clone()
+                    } else {
+                        info.subj_name.clone()
+                    };
+                    files.extend(lecture_files(
+                        &template,
+                        &subject,
+                        &begin_time,
+                        index + 1,
+                        info,
+                        &mut names,
+                    )?);
+                }
+                Err(e) => report.failed.push(VideoBatchFailure {
+                    name: lecture_file_name(&video.video_name, &begin_time, 0),
+                    error: e.to_string(),
+                }),
+            }
+        }
+        self.download_lectures(batch_id, files, report, progress_handler)
+            .await
+    }
+
+    async fn download_lectures<F: Fn(VideoBatchProgress) + Send + Sync + 'static>(
+        &self,
+        batch_id: &str,
+        files: Vec<LectureFile>,
+        mut report: VideoBatchReport,
+        progress_handler: F,
+    ) -> Result<VideoBatchReport> {
+        let batch_token = CancellationToken::new();
+        {
+            let mut batches = self.video_batches.lock().unwrap();
+            if batches.contains_key(batch_id) {
+                return Err(AppError::VideoDownloadError(format!(
+                    "batch {} is already running",
+                    batch_id
+                )));
+            }
+            batches.insert(batch_id.to_owned(), batch_token.clone());
+        }
+        let result = self
+            .run_lecture_queue(batch_id, files, &mut report, &batch_token, progress_handler)
+            .await;
+        self.video_batches.lock().unwrap().remove(batch_id);
+        result?;
+        tracing::info!("Video batch {} finished: {:?}", batch_id, report);
+        Ok(report)
+    }
+
+    async fn run_lecture_queue<F: Fn(VideoBatchProgress) + Send + Sync + 'static>(
+        &self,
+        batch_id: &str,
+        files: Vec<LectureFile>,
+        report: &mut VideoBatchReport,
+        batch_token: &CancellationToken,
+        progress_handler: F,
+    ) -> Result<()> {
+        let save_dir = self.config.read().await.save_path.clone();
+        let progress_handler = Arc::new(progress_handler);
+        let mut progress = VideoBatchProgress {
+            batch_id: batch_id.to_owned(),
+            completed: report.failed.len() as u64,
+            total: (files.len() + report.failed.len()) as u64,
+            ..Default::default()
+        };
+        progress_handler(progress.clone());
+
+        for file in files {
+            if batch_token.is_cancelled() {
+                return Err(AppError::VideoDownloadCancelled(batch_id.to_owned()));
+            }
+            // downloads are only renamed to their final name once complete
+            if Path::new(&save_dir).join(&file.save_name).exists() {
+                self.record_lecture_file(&file).await;
+                report.skipped.push(file.save_name);
+            } else {
+                progress.current_name = file.save_name.clone();
+                progress.current = None;
+                let handler = progress_handler.clone();
+                let file_progress = progress.clone();
+                let result = self
+                    .download_video_with_token(
+                        &file.play,
+                        &file.save_name,
+                        batch_token.child_token(),
+                        move |payload| {
+                            handler(VideoBatchProgress {
+                                current: Some(payload),
+                                ..file_progress.clone()
+                            })
+                        },
+                    )
+                    .await;
+                match result {
+                    Ok(_) => {
+                        self.record_lecture_file(&file).await;
+                        report.downloaded.push(file.save_name);
+                    }
+                    Err(_) if batch_token.is_cancelled() => {
+                        return Err(AppError::VideoDownloadCancelled(batch_id.to_owned()))
+                    }
+                    Err(e) => {
+                        tracing::error!("Failed to download {}: {:?}", file.save_name, e);
+                        report.failed.push(VideoBatchFailure {
+                            name: file.save_name,
+                            error: e.to_string(),
+                        });
+                    }
+                }
+            }
+            progress.completed += 1;
+            progress.current_name.clear();
+            progress.current = None;
+            progress_handler(progress.clone());
+        }
+        Ok(())
+    }
+
+    // The library is only a record, a download doesn't fail because of it
+    async fn record_lecture_file(&self, file: &LectureFile) {
+        if let Err(e) = self.add_to_video_library(file.entry.clone()).await {
+            tracing::error!(
+                "Failed to record {} in the library: {:?}",
+                file.save_name,
+                e
+            );
+        }
+    }
+
+    // Downloads one channel of a lecture under the configured template, or under
+    // `save_name` without one, and records it in the library. A single lecture
+    // has no position in its course, so `{index}` is 1. Returns the name the
+    // video was saved as.
+    pub async fn download_lecture_video<F: Fn(ProgressPayload) + Send + 'static>(
+        &self,
+        info: &VideoInfo,
+        play: &VideoPlayInfo,
+        save_name: &str,
+        progress_handler: F,
+    ) -> Result<String> {
+        let template = self.config.read().await.video_name_template.clone();
+        let channel = lecture_channels(info)
+            .into_iter()
+            .find(|(_, channel_play)| channel_play.id == play.id)
+            .map(|(channel, _)| channel)
+            .unwrap_or_default();
+        let subject = if info.subj_name.is_empty() {
+            &info.vide_name
+        } else {
+            &info.subj_name
+        };
+        let begin_time = format_lecture_time(&info.vide_begin_time);
+        let mut entry = library_entry(info, subject, &begin_time, channel);
+        let save_name = if template.is_empty() {
+            save_name.to_owned()
+        } else {
+            render_video_name(&template, &entry, 1)?
+        };
+        entry.path = save_name.clone();
+        let file = LectureFile {
+            save_name,
+            play: play.clone(),
+            entry,
+        };
+        self.download_video(play, &file.save_name, progress_handler)
+            .await?;
+        self.record_lecture_file(&file).await;
+        Ok(file.save_name)
+    }
+
+    // Downloads both channels of a lecture, unless they are downloaded already,
+    // and lays the screen channel over the classroom one. Returns the path of
+    // the merged video.
+    pub async fn merge_lecture_channels<F: Fn(LectureMergeProgress) + Send + Sync + 'static>(
+        &self,
+        params: &LectureMergeParams,
+        progress_handler: F,
+    ) -> Result<String> {
+        if !App::is_ffmpeg_installed() {
+            return Err(AppError::FfmpegError("ffmpeg is not installed".to_owned()));
+        }
+        let save_dir = self.config.read().await.save_path.clone();
+        // ffmpeg overwrites the output and the sources may be deleted, none of
+        // them may be outside the save directory
+        let main_path = video_save_path(&save_dir, &params.main_save_name)?;
+        let sub_path = video_save_path(&save_dir, &params.sub_save_name)?;
+        let output_path = video_save_path(&save_dir, &params.output_name)?;
+        let job_params = FfmpegJobParams {
+            kind: FfmpegJobKind::Overlay {
+                input_path: main_path.to_string_lossy().to_string(),
+                sub_path: sub_path.to_string_lossy().to_string(),
+                options: params.overlay.clone(),
+            },
+            output_path: output_path.to_string_lossy().to_string(),
+        };
+        // a bad merge fails before anything is downloaded
+        job_args(&job_params)?;
+        let progress_handler = Arc::new(progress_handler);
+        let report = {
+            let progress_handler = progress_handler.clone();
+            let output_name = params.output_name.clone();
+            move |stage, percentage| {
+                progress_handler(LectureMergeProgress {
+                    output_name: output_name.clone(),
+                    stage,
+                    percentage,
+                })
+            }
+        };
+
+        let channels = [
+            (
+                LectureMergeStage::DownloadMain,
+                &params.main_video,
+                &params.main_save_name,
+                &main_path,
+            ),
+            (
+                LectureMergeStage::DownloadSub,
+                &params.sub_video,
+                &params.sub_save_name,
+                &sub_path,
+            ),
+        ];
+        for (stage, play, save_name, path) in channels {
+            if path.exists() {
+                report(stage, 100.0);
+                continue;
+            }
+            let report = report.clone();
+            self.download_video(play, save_name, move |payload| {
+                let percentage = if payload.total > 0 {
+                    payload.processed as f64 / payload.total as f64 * 100.0
+                } else {
+                    0.0
+                };
+                report(stage, percentage);
+            })
+            .await?;
+        }
+
+        let output_path = job_params.output_path.clone();
+        report(LectureMergeStage::Merge, 0.0);
+        let (id, cancel_token) = self.create_ffmpeg_job(job_params)?;
+        let last_line = Mutex::new(String::new());
+        let result = self
+            .execute_ffmpeg_job(
+                &id,
+                cancel_token,
+                |line| *last_line.lock().unwrap() = line.to_owned(),
+                |progress| report(LectureMergeStage::Merge, progress.percentage),
+            )
+            .await
+            .and_then(|exit_code| match exit_code {
+                0 => Ok(()),
+                _ => Err(AppError::FfmpegError(format!(
+                    "exit code {}: {}",
+                    exit_code,
+                    last_line.into_inner().unwrap()
+                ))),
+            });
+        if let Err(e) = result {
+            // a failed or cancelled merge leaves an unplayable file behind
+            let _ = fs::remove_file(&output_path);
+            return Err(e);
+        }
+
+        if params.delete_sources {
+            fs::remove_file(&main_path)?;
+            fs::remove_file(&sub_path)?;
+        }
+        report(LectureMergeStage::Done, 100.0);
+        tracing::info!("Merged lecture channels into {}", output_path);
+        Ok(output_path)
+    }
+
+    // Stops a running batch, the current file keeps its progress for resuming
+    pub fn cancel_video_batch(&self, batch_id: &str) -> bool {
+        match self.video_batches.lock().unwrap().get(batch_id) {
+            Some(batch_token) => {
+                batch_token.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lecture_file_names() -> Result<()> {
+        assert_eq!(
+            format_lecture_time("2024-03-01 08:00:00"),
+            "2024-03-01_08-00"
+        );
+        let info = VideoInfo {
+            video_play_response_vo_list: vec![
+                VideoPlayInfo {
+                    id: 1,
+                    ..Default::default()
+                },
+                VideoPlayInfo {
+                    id: 2,
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let mut names = HashSet::new();
+        let files = lecture_files("", "C/C++", "2024-03-01_08-00", 1, info.clone(), &mut names)?;
+        let names_of = |files: &[LectureFile]| {
+            files
+                .iter()
+                .map(|file| file.save_name.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            names_of(&files),
+            [
+                "C_C++_2024-03-01_08-00.mp4",
+                "C_C++_2024-03-01_08-00_录屏.mp4"
+            ]
+        );
+
+        // a second lecture at the same time gets the ids of its plays appended
+        let files = lecture_files("", "C/C++", "2024-03-01_08-00", 1, info.clone(), &mut names)?;
+        assert_eq!(
+            names_of(&files),
+            [
+                "C_C++_2024-03-01_08-00_1.mp4",
+                "C_C++_2024-03-01_08-00_2_录屏.mp4"
+            ]
+        );
+
+        let template = "{course}/{date}_{index}_{channel}";
+        let mut names = HashSet::new();
+        let files = lecture_files(
+            template,
+            "C/C++",
+            "2024-03-01_08-00",
+            3,
+            info.clone(),
+            &mut names,
+        )?;
+        assert_eq!(
+            names_of(&files),
+            [
+                "C_C++/2024-03-01_03_教室.mp4",
+                "C_C++/2024-03-01_03_录屏.mp4"
+            ]
+        );
+        assert_eq!(files[1].entry.path, "C_C++/2024-03-01_03_录屏.mp4");
+        assert_eq!(files[1].entry.begin_time, "2024-03-01 08:00:00");
+        let files = lecture_files(template, "C/C++", "2024-03-01_08-00", 3, info, &mut names)?;
+        assert_eq!(files[0].save_name, "C_C++/2024-03-01_03_教室_1.mp4");
+
+        // channels are numbered by the video site, not by their order in the list
+        let info = VideoInfo {
+            video_play_response_vo_list: vec![
+                VideoPlayInfo {
+                    id: 4,
+                    cdvi_channel_num: 2,
+                    ..Default::default()
+                },
+                VideoPlayInfo {
+                    id: 3,
+                    cdvi_channel_num: 1,
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+        let files = lecture_files("", "Math", "2024-03-01_08-00", 1, info, &mut HashSet::new())?;
+        assert_eq!(files[0].play.id, 3);
+        assert_eq!(files[1].save_name, "Math_2024-03-01_08-00_录屏.mp4");
+        Ok(())
+    }
+}
@@ -0,0 +1,401 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use chrono::NaiveDateTime;
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use super::App;
+use crate::{
+    error::{AppError, Result},
+    model::{VideoInfo, VideoLibraryEntry},
+    utils::sanitize_file_name,
+};
+
+const VIDEO_LIBRARY_FILE_NAME: &str = "video_library";
+const VIDEO_EXTENSIONS: [&str; 6] = ["mp4", "m4v", "mkv", "mov", "ts", "webm"];
+const TEMPLATE_VARIABLES: [&str; 8] = [
+    "course",
+    "teacher",
+    "classroom",
+    "date",
+    "time",
+    "index",
+    "channel",
+    "id",
+];
+const BEGIN_TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+lazy_static! {
+    static ref VARIABLE_RE: Regex = Regex::new(r"\{(\w*)\}").unwrap();
+}
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+struct VideoLibrary {
+    #[serde(default)]
+    videos: Vec<VideoLibraryEntry>,
+}
+
+// `教室` for the classroom, `录屏`, `录屏_2`... for the screen recordings
+fn channel_name(channel: usize) -> String {
+    match channel {
+        0 => "教室".to_owned(),
+        1 => "录屏".to_owned(),
+        n => format!("录屏_{}", n),
+    }
+}
+
+fn parse_channel_name(name: &str) -> usize {
+    match name {
+        "教室" => 0,
+        "录屏" => 1,
+        name => name
+            .strip_prefix("录屏_")
+            .and_then(|n| n.parse().ok())
+            .unwrap_or_default(),
+    }
+}
+
+// Metadata of one channel of a lecture, `begin_time` is formatted like the
+// lecture names, `2024-03-01_08-00`
+pub(super) fn library_entry(
+    info: &VideoInfo,
+    course: &str,
+    begin_time: &str,
+    channel: usize,
+) -> VideoLibraryEntry {
+    let begin_time = NaiveDateTime::parse_from_str(begin_time, "%Y-%m-%d_%H-%M")
+        .map(|time| time.format(BEGIN_TIME_FORMAT).to_string())
+        .unwrap_or_else(|_| info.vide_begin_time.clone());
+    VideoLibraryEntry {
+        path: String::new(),
+        video_id: info.id,
+        channel,
+        course: course.to_owned(),
+        teacher: info.user_name.clone(),
+        classroom: info.clro_name.clone(),
+        begin_time,
+        end_time: info.vide_end_time.clone(),
+        size: 0,
+    }
+}
+
+// Checks the variables and the directories of a template, a template without
+// an extension gets `.mp4`
+fn normalize_template(template: &str) -> Result<String> {
+    let mut template = template.trim().replace('\\', "/");
+    for captures in VARIABLE_RE.captures_iter(&template) {
+        let name = &captures[1];
+        if !TEMPLATE_VARIABLES.contains(&name) {
+            return Err(AppError::VideoNameTemplate(format!(
+                "unknown variable {{{}}}",
+                name
+            )));
+        }
+    }
+    if template.starts_with('/')
+        || template
+            .split('/')
+            .any(|component| matches!(component.trim(), "" | "." | ".."))
+    {
+        return Err(AppError::VideoNameTemplate(format!(
+            "{} is not a relative path",
+            template
+        )));
+    }
+    let file_name = template.rsplit('/').next().unwrap_or_default();
+    if !VARIABLE_RE.replace_all(file_name, "").contains('.') {
+        template += ".mp4";
+    }
+    Ok(template)
+}
+
+// Each directory and the file name are sanitized, `/` in values doesn't
+// create directories
+pub(super) fn render_video_name(
+    template: &str,
+    entry: &VideoLibraryEntry,
+    index: usize,
+) -> Result<String> {
+    let template = normalize_template(template)?;
+    let begin_time = NaiveDateTime::parse_from_str(&entry.begin_time, BEGIN_TIME_FORMAT).ok();
+    let rendered = VARIABLE_RE.replace_all(&template, |captures: &regex::Captures| {
+        let value = match &captures[1] {
+            "course" => entry.course.clone(),
+            "teacher" => entry.teacher.clone(),
+            "classroom" => entry.classroom.clone(),
+            "date" => match begin_time {
+                Some(time) => time.format("%Y-%m-%d").to_string(),
+                None => entry.begin_time.clone(),
+            },
+            "time" => begin_time
+                .map(|time| time.format("%H-%M").to_string())
+                .unwrap_or_default(),
+            "index" => format!("{:02}", index),
+            "channel" => channel_name(entry.channel),
+            _ => entry.video_id.to_string(),
+        };
+        value.replace(['/', '\\'], "_")
+    });
+    Ok(rendered
+        .split('/')
+        .map(sanitize_file_name)
+        .collect::<Vec<_>>()
+        .join("/"))
+}
+
+fn template_regex(template: &str) -> Option<Regex> {
+    let mut pattern = "^".to_owned();
+    let mut seen = vec![];
+    let mut last = 0;
+    for captures in VARIABLE_RE.captures_iter(template) {
+        let whole = captures.get(0).unwrap();
+        pattern += &regex::escape(&template[last..whole.start()]);
+        last = whole.end();
+        let name = captures.get(1).unwrap().as_str();
+        let value_pattern = match name {
+            "date" => r"\d{4}-\d{2}-\d{2}",
+            "time" => r"\d{2}-\d{2}",
+            "index" | "id" => r"\d+",
+            "channel" => r"教室|录屏(?:_\d+)?",
+            _ => r"[^/]*?",
+        };
+        // a variable used twice is only captured once
+        if seen.contains(&name) {
+            pattern += &format!("(?:{})", value_pattern);
+        } else {
+            pattern += &format!("(?P<{}>{})", name, value_pattern);
+            seen.push(name);
+        }
+    }
+    pattern += &regex::escape(&template[last..]);
+    pattern += "$";
+    Regex::new(&pattern).ok()
+}
+
+// Recovers what the name of a video tells, for files the library doesn't know.
+// Without a template the built-in lecture names are parsed.
+fn parse_video_name(template: &str, path: &str) -> VideoLibraryEntry {
+    let mut entry = VideoLibraryEntry {
+        path: path.to_owned(),
+        ..Default::default()
+    };
+    let regex = if template.is_empty() {
+        Regex::new(r"^(?P<course>.+)_(?P<date>\d{4}-\d{2}-\d{2})_(?P<time>\d{2}-\d{2})(?:_\d+)?(?:_(?P<channel>录屏(?:_\d+)?))?\.mp4$").ok()
+    } else {
+        normalize_template(template)
+            .ok()
+            .and_then(|template| template_regex(&template))
+    };
+    let Some(captures) = regex.and_then(|regex| regex.captures(path)) else {
+        return entry;
+    };
+    let value = |name: &str| {
+        captures
+            .name(name)
+            .map(|value| value.as_str().to_owned())
+            .unwrap_or_default()
+    };
+    entry.course = value("course");
+    entry.teacher = value("teacher");
+    entry.classroom = value("classroom");
+    entry.video_id = value("id").parse().unwrap_or_default();
+    entry.channel = parse_channel_name(&value("channel"));
+    let (date, time) = (value("date"), value("time"));
+    if !date.is_empty() {
+        let time = if time.is_empty() { "00-00" } else { &time };
+        entry.begin_time = format!("{} {}:00", date, time.replace('-', ":"));
+    }
+    entry
+}
+
+fn collect_videos(dir: &Path, videos: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            // segments of unfinished HLS downloads
+            if !path.to_string_lossy().ends_with(".segments") {
+                collect_videos(&path, videos);
+            }
+        } else if path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| VIDEO_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+        {
+            videos.push(path);
+        }
+    }
+}
+
+fn relative_path(save_dir: &Path, path: &Path) -> Option<String> {
+    let relative = path.strip_prefix(save_dir).ok()?;
+    Some(
+        relative
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/"),
+    )
+}
+
+impl App {
+    async fn load_video_library(&self) -> Result<VideoLibrary> {
+        let path = App::get_account_data_path(
+            &*self.current_account.read().await,
+            VIDEO_LIBRARY_FILE_NAME,
+        );
+        match fs::read(&path) {
+            Ok(content) => Ok(serde_json::from_slice(&content)?),
+            Err(_) => Ok(VideoLibrary::default()),
+        }
+    }
+
+    async fn save_video_library(&self, library: &VideoLibrary) -> Result<()> {
+        let path = App::get_account_data_path(
+            &*self.current_account.read().await,
+            VIDEO_LIBRARY_FILE_NAME,
+        );
+        fs::write(path, serde_json::to_vec(library)?)?;
+        Ok(())
+    }
+
+    // Records a downloaded video, replacing what was known about its path
+    pub(super) async fn add_to_video_library(&self, mut entry: VideoLibraryEntry) -> Result<()> {
+        let save_dir = self.config.read().await.save_path.clone();
+        let _guard = self.video_library_lock.lock().await;
+        entry.size = fs::metadata(Path::new(&save_dir).join(&entry.path))
+            .map(|metadata| metadata.len())
+            .unwrap_or_default();
+        let mut library = self.load_video_library().await?;
+        library.videos.retain(|video| video.path != entry.path);
+        library.videos.push(entry);
+        self.save_video_library(&library).await
+    }
+
+    // Where a channel of a lecture was downloaded to, if it still exists
+    pub(super) async fn find_library_video(
+        &self,
+        video_id: i64,
+        channel: usize,
+    ) -> Option<PathBuf> {
+        let save_dir = self.config.read().await.save_path.clone();
+        let library = self.load_video_library().await.ok()?;
+        library
+            .videos
+            .iter()
+            .filter(|video| video.video_id == video_id && video.channel == channel)
+            .map(|video| Path::new(&save_dir).join(&video.path))
+            .find(|path| path.is_file())
+    }
+
+    pub async fn get_video_library(&self) -> Result<Vec<VideoLibraryEntry>> {
+        Ok(self.load_video_library().await?.videos)
+    }
+
+    // Rebuilds the library from the videos in the save directory. Videos it
+    // knew keep their metadata, new ones get what their names tell with the
+    // current template, and videos which are gone are dropped.
+    pub async fn rescan_video_library(&self) -> Result<Vec<VideoLibraryEntry>> {
+        let (save_dir, template) = {
+            let config = self.config.read().await;
+            (config.save_path.clone(), config.video_name_template.clone())
+        };
+        let save_dir = PathBuf::from(save_dir);
+        let _guard = self.video_library_lock.lock().await;
+        // a corrupt library is what a rescan is there to fix
+        let library = self.load_video_library().await.unwrap_or_else(|e| {
+            tracing::warn!("Rebuilding unreadable video library: {:?}", e);
+            VideoLibrary::default()
+        });
+        let mut known: HashMap<String, VideoLibraryEntry> = library
+            .videos
+            .into_iter()
+            .map(|video| (video.path.clone(), video))
+            .collect();
+
+        let mut paths = vec![];
+        collect_videos(&save_dir, &mut paths);
+        let mut videos = vec![];
+        for path in paths {
+            let Some(relative) = relative_path(&save_dir, &path) else {
+                continue;
+            };
+            let mut entry = known
+                .remove(&relative)
+                .unwrap_or_else(|| parse_video_name(&template, &relative));
+            entry.size = fs::metadata(&path)
+                .map(|metadata| metadata.len())
+                .unwrap_or_default();
+            videos.push(entry);
+        }
+        videos.sort_by(|a, b| a.path.cmp(&b.path));
+        tracing::info!(
+            "Rescanned video library: {} videos, {} removed",
+            videos.len(),
+            known.len()
+        );
+        let library = VideoLibrary { videos };
+        self.save_video_library(&library).await?;
+        Ok(library.videos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry() -> VideoLibraryEntry {
+        VideoLibraryEntry {
+            video_id: 42,
+            channel: 1,
+            course: "C/C++".to_owned(),
+            teacher: "张三".to_owned(),
+            classroom: "东上院 101".to_owned(),
+            begin_time: "2024-03-01 08:00:00".to_owned(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_render_video_name() -> Result<()> {
+        assert_eq!(
+            render_video_name("{course}/{date}_{index}_{channel}.mp4", &entry(), 3)?,
+            "C_C++/2024-03-01_03_录屏.mp4"
+        );
+        assert_eq!(
+            render_video_name("{teacher}\\{course} {time}: {id}", &entry(), 1)?,
+            "张三/C_C++ 08-00_ 42.mp4"
+        );
+        assert!(render_video_name("{course}/{unknown}.mp4", &entry(), 1).is_err());
+        assert!(render_video_name("../{course}.mp4", &entry(), 1).is_err());
+        assert!(render_video_name("/{course}.mp4", &entry(), 1).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_video_name() {
+        let template = "{course}/{date}_{index}_{channel}/{course}_{id}";
+        let path = render_video_name(template, &entry(), 3).unwrap();
+        let parsed = parse_video_name(template, &path);
+        assert_eq!(parsed.course, "C_C++");
+        assert_eq!(parsed.channel, 1);
+        assert_eq!(parsed.video_id, 42);
+        assert_eq!(parsed.begin_time, "2024-03-01 00:00:00");
+
+        let parsed = parse_video_name("", "C_C++_2024-03-01_08-00_7_录屏_2.mp4");
+        assert_eq!(parsed.course, "C_C++");
+        assert_eq!(parsed.channel, 2);
+        assert_eq!(parsed.begin_time, "2024-03-01 08:00:00");
+
+        let parsed = parse_video_name("", "other/video.mp4");
+        assert_eq!(parsed.path, "other/video.mp4");
+        assert!(parsed.course.is_empty());
+    }
+}
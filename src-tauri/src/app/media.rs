@@ -0,0 +1,594 @@
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    io::SeekFrom,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+
+use bytes::Bytes;
+use reqwest::Url;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use uuid::Uuid;
+use warp::{
+    http::{
+        header::{
+            ACCEPT_RANGES, ACCESS_CONTROL_ALLOW_ORIGIN, CONTENT_LENGTH, CONTENT_RANGE,
+            CONTENT_TYPE, VARY,
+        },
+        HeaderValue, StatusCode,
+    },
+    hyper::{Body, Response},
+    Filter, Rejection,
+};
+
+use super::{
+    lecture::{format_lecture_time_ms, lecture_channels, lecture_file_name},
+    App,
+};
+use crate::{
+    client::{hls::is_hls_url, Client},
+    error::{AppError, Result},
+    model::{LecturePlayerManifest, PlayerChannel, VideoInfo},
+};
+
+const MEDIA_CHUNK_SIZE: usize = 256 * 1024;
+// Pages of the app itself, on Windows, on the other platforms and in development.
+// Other pages in a browser must not read the manifest or the lectures.
+const ALLOWED_ORIGINS: [&str; 3] = [
+    "https://tauri.localhost",
+    "tauri://localhost",
+    "http://localhost:1420",
+];
+const PLAYLIST_CONTENT_TYPE: &str = "application/vnd.apple.mpegurl";
+
+#[derive(Debug, Clone, PartialEq)]
+enum MediaSource {
+    // fetched from the video site for every request
+    Remote(String),
+    Local(PathBuf),
+}
+
+// A lecture registered with `prepare_lecture_player`
+pub(super) struct LectureStream {
+    video_id: i64,
+    manifest: LecturePlayerManifest,
+    // by channel
+    sources: Vec<MediaSource>,
+    // urls referenced by the HLS playlists of remote channels, served as
+    // `/media/{id}/hls/{index}`
+    hls_resources: Vec<String>,
+}
+
+// Only registered lectures are served, so the server can't be used to read
+// other files or to reach other sites with the video site's cookies. Streams
+// are keyed by a random id, other pages can't guess their urls from the public
+// video ids and have the app fetch them with the user's cookies.
+pub(super) type MediaStreams = Arc<RwLock<HashMap<String, LectureStream>>>;
+
+// Parses `bytes=start-end`, `bytes=start-` and `bytes=-suffix`, None if the
+// range can't be served. Only the first range of a multi-range request is used.
+fn parse_range(range: &str, size: u64) -> Option<(u64, u64)> {
+    let range = range.trim().strip_prefix("bytes=")?;
+    let range = range.split(',').next()?.trim();
+    let (start, end) = range.split_once('-')?;
+    let (start, end) = match (start.trim(), end.trim()) {
+        ("", suffix) => {
+            let suffix: u64 = suffix.parse().ok()?;
+            if suffix == 0 {
+                return None;
+            }
+            (size.saturating_sub(suffix), size.checked_sub(1)?)
+        }
+        (start, "") => (start.parse().ok()?, size.checked_sub(1)?),
+        (start, end) => {
+            let end: u64 = end.parse().ok()?;
+            (start.parse().ok()?, end.min(size.checked_sub(1)?))
+        }
+    };
+    (start <= end).then_some((start, end))
+}
+
+fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("mp4") | Some("m4v") => "video/mp4",
+        Some("webm") => "video/webm",
+        Some("mkv") => "video/x-matroska",
+        Some("ts") => "video/mp2t",
+        _ => "application/octet-stream",
+    }
+}
+
+// Points every uri of an HLS playlist, resolved against the playlist's url, at
+// the local server. `register` returns the local path of a remote url.
+fn rewrite_playlist<F: FnMut(String) -> String>(
+    content: &str,
+    base: &Url,
+    mut register: F,
+) -> String {
+    let mut local_uri = |uri: &str| match base.join(uri) {
+        Ok(url) => register(url.to_string()),
+        Err(_) => uri.to_owned(),
+    };
+    let mut lines = vec![];
+    for line in content.lines() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            lines.push(line.to_owned());
+        } else if !trimmed.starts_with('#') {
+            lines.push(local_uri(trimmed));
+        } else if let Some(start) = trimmed.find("URI=\"") {
+            // keys, init sections and alternative renditions
+            let value_start = start + "URI=\"".len();
+            let value_end = trimmed[value_start..]
+                .find('"')
+                .map_or(trimmed.len(), |end| value_start + end);
+            lines.push(format!(
+                "{}{}{}",
+                &trimmed[..value_start],
+                local_uri(&trimmed[value_start..value_end]),
+                &trimmed[value_end..]
+            ));
+        } else {
+            lines.push(line.to_owned());
+        }
+    }
+    lines.join("\n") + "\n"
+}
+
+fn status_response(status: StatusCode) -> Response<Body> {
+    let mut response = Response::new(Body::empty());
+    *response.status_mut() = status;
+    response
+}
+
+async fn serve_local(path: &Path, range: Option<&str>) -> Result<Response<Body>> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let size = file.metadata().await?.len();
+    let (start, length, content_range) = match range {
+        Some(range) => match parse_range(range, size) {
+            Some((start, end)) => (
+                start,
+                end - start + 1,
+                Some(format!("bytes {}-{}/{}", start, end, size)),
+            ),
+            None => {
+                let mut response = status_response(StatusCode::RANGE_NOT_SATISFIABLE);
+                response.headers_mut().insert(
+                    CONTENT_RANGE,
+                    HeaderValue::from_str(&format!("bytes */{}", size)).unwrap(),
+                );
+                return Ok(response);
+            }
+        },
+        None => (0, size, None),
+    };
+    file.seek(SeekFrom::Start(start)).await?;
+
+    let (mut sender, body) = Body::channel();
+    tokio::spawn(async move {
+        let mut file = file.take(length);
+        let mut buf = vec![0; MEDIA_CHUNK_SIZE];
+        // stops when the player closes the connection, e.g. after seeking
+        while let Ok(n @ 1..) = file.read(&mut buf).await {
+            if sender
+                .send_data(Bytes::copy_from_slice(&buf[..n]))
+                .await
+                .is_err()
+            {
+                break;
+            }
+        }
+    });
+
+    let mut response = Response::new(body);
+    let headers = response.headers_mut();
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static(content_type(path)));
+    headers.insert(CONTENT_LENGTH, HeaderValue::from(length));
+    headers.insert(ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    if let Some(content_range) = content_range {
+        headers.insert(
+            CONTENT_RANGE,
+            HeaderValue::from_str(&content_range).unwrap(),
+        );
+        *response.status_mut() = StatusCode::PARTIAL_CONTENT;
+    }
+    Ok(response)
+}
+
+// The range is handled by the video site, its status and headers are passed on
+async fn serve_remote(client: &Client, url: &str, range: Option<&str>) -> Result<Response<Body>> {
+    let mut upstream = client.get_video_stream(url, range).await?;
+    let mut response = status_response(upstream.status());
+    for name in [CONTENT_TYPE, CONTENT_LENGTH, CONTENT_RANGE, ACCEPT_RANGES] {
+        if let Some(value) = upstream.headers().get(&name) {
+            response.headers_mut().insert(name, value.clone());
+        }
+    }
+
+    let (mut sender, body) = Body::channel();
+    tokio::spawn(async move {
+        while let Ok(Some(chunk)) = upstream.chunk().await {
+            if sender.send_data(chunk).await.is_err() {
+                break;
+            }
+        }
+    });
+    *response.body_mut() = body;
+    Ok(response)
+}
+
+// Segments and keys of a playlist would be fetched from the video site by the
+// player itself, without its cookies and referer, so they are proxied too
+async fn serve_playlist(
+    client: &Client,
+    streams: &MediaStreams,
+    id: &str,
+    url: &str,
+) -> Result<Response<Body>> {
+    let upstream = client.get_video_stream(url, None).await?;
+    if !upstream.status().is_success() {
+        return Ok(status_response(upstream.status()));
+    }
+    let content = upstream.text().await?;
+    let base = Url::parse(url).map_err(|e| AppError::MediaServer(e.to_string()))?;
+    let playlist = {
+        let mut streams = streams.write().unwrap();
+        let Some(stream) = streams.get_mut(id) else {
+            return Ok(status_response(StatusCode::NOT_FOUND));
+        };
+        rewrite_playlist(&content, &base, |url| {
+            let index = match stream.hls_resources.iter().position(|known| *known == url) {
+                Some(index) => index,
+                None => {
+                    stream.hls_resources.push(url);
+                    stream.hls_resources.len() - 1
+                }
+            };
+            format!("/media/{}/hls/{}", id, index)
+        })
+    };
+    let mut response = Response::new(Body::from(playlist));
+    response.headers_mut().insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static(PLAYLIST_CONTENT_TYPE),
+    );
+    Ok(response)
+}
+
+async fn serve_remote_source(
+    client: &Client,
+    streams: &MediaStreams,
+    id: &str,
+    url: &str,
+    range: Option<&str>,
+) -> Result<Response<Body>> {
+    if is_hls_url(url) {
+        serve_playlist(client, streams, id, url).await
+    } else {
+        serve_remote(client, url, range).await
+    }
+}
+
+async fn serve_channel(
+    client: Arc<Client>,
+    streams: MediaStreams,
+    id: String,
+    channel: usize,
+    range: Option<String>,
+) -> Response<Body> {
+    let source = streams
+        .read()
+        .unwrap()
+        .get(&id)
+        .and_then(|stream| stream.sources.get(channel).cloned());
+    let result = match &source {
+        Some(MediaSource::Local(path)) => serve_local(path, range.as_deref()).await,
+        Some(MediaSource::Remote(url)) => {
+            serve_remote_source(&client, &streams, &id, url, range.as_deref()).await
+        }
+        None => Ok(status_response(StatusCode::NOT_FOUND)),
+    };
+    result.unwrap_or_else(|e| {
+        tracing::error!("Failed to serve channel {} of {}: {}", channel, id, e);
+        status_response(StatusCode::BAD_GATEWAY)
+    })
+}
+
+// Only urls listed in a playlist served before can be requested
+async fn serve_hls_resource(
+    client: Arc<Client>,
+    streams: MediaStreams,
+    id: String,
+    index: usize,
+    range: Option<String>,
+) -> Response<Body> {
+    let url = streams
+        .read()
+        .unwrap()
+        .get(&id)
+        .and_then(|stream| stream.hls_resources.get(index).cloned());
+    let result = match &url {
+        Some(url) => serve_remote_source(&client, &streams, &id, url, range.as_deref()).await,
+        None => Ok(status_response(StatusCode::NOT_FOUND)),
+    };
+    result.unwrap_or_else(|e| {
+        tracing::error!("Failed to serve HLS resource {} of {}: {}", index, id, e);
+        status_response(StatusCode::BAD_GATEWAY)
+    })
+}
+
+// The player page may fetch the manifest, but no other site may
+fn allow_app_origin(response: &mut Response<Body>, origin: Option<&str>) {
+    let headers = response.headers_mut();
+    headers.insert(VARY, HeaderValue::from_static("Origin"));
+    if let Some(origin) = origin.filter(|origin| ALLOWED_ORIGINS.contains(origin)) {
+        headers.insert(
+            ACCESS_CONTROL_ALLOW_ORIGIN,
+            HeaderValue::from_str(origin).unwrap(),
+        );
+    }
+}
+
+fn serve_manifest(streams: &MediaStreams, id: &str) -> Response<Body> {
+    let manifest = streams
+        .read()
+        .unwrap()
+        .get(id)
+        .map(|stream| serde_json::to_vec(&stream.manifest));
+    match manifest {
+        Some(Ok(manifest)) => {
+            let mut response = Response::new(Body::from(manifest));
+            response
+                .headers_mut()
+                .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+            response
+        }
+        _ => status_response(StatusCode::NOT_FOUND),
+    }
+}
+
+// `/media/{id}/manifest.json`, `/media/{id}/{channel}` and the resources of HLS
+// channels `/media/{id}/hls/{index}` on the local server
+pub(super) fn media_routes(
+    client: Arc<Client>,
+    streams: MediaStreams,
+) -> impl Filter<Extract = (Response<Body>,), Error = Rejection> + Clone {
+    let manifest = {
+        let streams = streams.clone();
+        warp::get()
+            .and(warp::path!("media" / String / "manifest.json"))
+            .map(move |id: String| serve_manifest(&streams, &id))
+    };
+    let channel = {
+        let client = client.clone();
+        let streams = streams.clone();
+        warp::get()
+            .and(warp::path!("media" / String / usize))
+            .and(warp::header::optional::<String>("range"))
+            .and_then(move |id: String, channel: usize, range: Option<String>| {
+                let client = client.clone();
+                let streams = streams.clone();
+                async move {
+                    Ok::<_, Infallible>(serve_channel(client, streams, id, channel, range).await)
+                }
+            })
+    };
+    let hls_resource = warp::get()
+        .and(warp::path!("media" / String / "hls" / usize))
+        .and(warp::header::optional::<String>("range"))
+        .and_then(move |id: String, index: usize, range: Option<String>| {
+            let client = client.clone();
+            let streams = streams.clone();
+            async move {
+                Ok::<_, Infallible>(serve_hls_resource(client, streams, id, index, range).await)
+            }
+        });
+    manifest
+        .or(channel)
+        .unify()
+        .or(hls_resource)
+        .unify()
+        .and(warp::header::optional::<String>("origin"))
+        .map(|mut response: Response<Body>, origin: Option<String>| {
+            allow_app_origin(&mut response, origin.as_deref());
+            response
+        })
+}
+
+impl App {
+    // Registers the channels of a lecture on the local server and returns the
+    // url of its player manifest. Channels in the video library or saved with
+    // the built-in lecture names are served from disk, the others are
+    // streamed from the video site.
+    pub async fn prepare_lecture_player(&self, info: &VideoInfo) -> Result<String> {
+        let Some(proxy_port) = self.proxy_port().await? else {
+            return Err(AppError::MediaServer(
+                "local server is not ready".to_owned(),
+            ));
+        };
+        let save_dir = self.config.read().await.save_path.clone();
+        let subject = if info.subj_name.is_empty() {
+            &info.vide_name
+        } else {
+            &info.subj_name
+        };
+        let begin_time = format_lecture_time_ms(info.vide_begin_time_ms);
+        let stream_id = Uuid::new_v4().to_string();
+        let base_url = format!("http://localhost:{}/media/{}", proxy_port, stream_id);
+
+        let mut sources = vec![];
+        let mut channels = vec![];
+        for (channel, play) in lecture_channels(info) {
+            let path = match self.find_library_video(info.id, channel).await {
+                Some(path) => path,
+                None => Path::new(&save_dir).join(lecture_file_name(subject, &begin_time, channel)),
+            };
+            let local = path.is_file();
+            sources.push(if local {
+                MediaSource::Local(path)
+            } else {
+                MediaSource::Remote(play.rtmp_url_hdv.clone())
+            });
+            channels.push(PlayerChannel {
+                channel,
+                url: format!("{}/{}", base_url, channel),
+                local,
+            });
+        }
+        let manifest = LecturePlayerManifest {
+            id: info.id.to_string(),
+            title: info.vide_name.clone(),
+            subject: subject.clone(),
+            begin_time: info.vide_begin_time.clone(),
+            channels,
+        };
+        let mut streams = self.media_streams.write().unwrap();
+        // the player of a lecture opened again replaces the previous one
+        streams.retain(|_, stream| stream.video_id != info.id);
+        streams.insert(
+            stream_id,
+            LectureStream {
+                video_id: info.id,
+                manifest,
+                sources,
+                hls_resources: vec![],
+            },
+        );
+        Ok(format!("{}/manifest.json", base_url))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(parse_range("bytes=0-99", 1000), Some((0, 99)));
+        assert_eq!(parse_range("bytes=500-", 1000), Some((500, 999)));
+        assert_eq!(parse_range("bytes=-100", 1000), Some((900, 999)));
+        assert_eq!(parse_range("bytes=900-5000", 1000), Some((900, 999)));
+        assert_eq!(parse_range("bytes=0-0, 10-20", 1000), Some((0, 0)));
+        assert_eq!(parse_range("bytes=1000-", 1000), None);
+        assert_eq!(parse_range("bytes=20-10", 1000), None);
+        assert_eq!(parse_range("bytes=-0", 1000), None);
+        assert_eq!(parse_range("bytes=0-", 0), None);
+        assert_eq!(parse_range("items=0-10", 1000), None);
+    }
+
+    #[test]
+    fn test_rewrite_playlist() {
+        let content = "#EXTM3U\n\
+            #EXT-X-KEY:METHOD=AES-128,URI=\"key.bin\",IV=0x1\n\
+            #EXT-X-MAP:URI=\"init.mp4\"\n\
+            #EXTINF:10,\n\
+            seg0.ts\n\
+            #EXTINF:10,\n\
+            https://cdn.example.com/seg1.ts\n\
+            #EXTINF:10,\n\
+            seg0.ts\n";
+        let base = Url::parse("https://example.com/hls/index.m3u8").unwrap();
+        let mut resources: Vec<String> = vec![];
+        let playlist = rewrite_playlist(content, &base, |url| {
+            let index = resources
+                .iter()
+                .position(|known| *known == url)
+                .unwrap_or_else(|| {
+                    resources.push(url);
+                    resources.len() - 1
+                });
+            format!("/media/1/hls/{}", index)
+        });
+        assert_eq!(
+            playlist,
+            "#EXTM3U\n\
+            #EXT-X-KEY:METHOD=AES-128,URI=\"/media/1/hls/0\",IV=0x1\n\
+            #EXT-X-MAP:URI=\"/media/1/hls/1\"\n\
+            #EXTINF:10,\n\
+            /media/1/hls/2\n\
+            #EXTINF:10,\n\
+            /media/1/hls/3\n\
+            #EXTINF:10,\n\
+            /media/1/hls/2\n"
+        );
+        assert_eq!(
+            resources,
+            vec![
+                "https://example.com/hls/key.bin",
+                "https://example.com/hls/init.mp4",
+                "https://example.com/hls/seg0.ts",
+                "https://cdn.example.com/seg1.ts",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_media_routes() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("media_{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir)?;
+        let path = dir.join("lecture.mp4");
+        std::fs::write(&path, (0..=255u8).collect::<Vec<_>>())?;
+
+        let streams = MediaStreams::default();
+        streams.write().unwrap().insert(
+            "1".to_owned(),
+            LectureStream {
+                video_id: 1,
+                manifest: LecturePlayerManifest {
+                    id: "1".to_owned(),
+                    ..Default::default()
+                },
+                sources: vec![MediaSource::Local(path)],
+                hls_resources: vec![],
+            },
+        );
+        let routes = media_routes(Arc::new(Client::new()), streams);
+
+        let response = warp::test::request()
+            .path("/media/1/0")
+            .header("range", "bytes=10-19")
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(response.headers()[CONTENT_RANGE], "bytes 10-19/256");
+        assert_eq!(response.body().as_ref(), (10..20u8).collect::<Vec<_>>());
+
+        let response = warp::test::request()
+            .path("/media/1/0")
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.body().len(), 256);
+
+        let response = warp::test::request()
+            .path("/media/1/manifest.json")
+            .header("origin", "tauri://localhost")
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[ACCESS_CONTROL_ALLOW_ORIGIN],
+            "tauri://localhost"
+        );
+        let response = warp::test::request()
+            .path("/media/1/manifest.json")
+            .header("origin", "https://example.com")
+            .reply(&routes)
+            .await;
+        assert!(!response.headers().contains_key(ACCESS_CONTROL_ALLOW_ORIGIN));
+        let response = warp::test::request()
+            .path("/media/1/hls/0")
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let response = warp::test::request()
+            .path("/media/2/0")
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
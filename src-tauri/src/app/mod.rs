@@ -1,24 +1,69 @@
 use cache::Cache;
-use std::sync::Arc;
+use ffmpeg::FfmpegJobs;
+use media::MediaStreams;
+use oauth::OAuthCallback;
+use search::SearchIndex;
+use std::{collections::HashMap, sync::Arc};
 use tokio::{sync::RwLock, task::JoinHandle};
+use tokio_util::sync::CancellationToken;
 
 use crate::{
     client::Client,
     model::{Account, AppConfig},
 };
+pub mod announcement;
 pub mod annual;
+pub mod auth;
 pub mod basic;
 pub mod cache;
+pub mod config;
 mod constants;
+pub mod conversation;
+pub mod discussion;
+pub mod ffmpeg;
+pub mod group;
 pub mod jbox;
+pub mod lecture;
+pub mod library;
+pub mod media;
+pub mod module;
+pub mod oauth;
+pub mod profile;
+pub mod quiz;
+pub mod search;
+pub mod secret;
+pub mod section;
+pub mod session;
+pub mod slides;
+pub mod subtitle;
 pub mod video;
 
 pub struct App {
     client: Arc<Client>,
     current_account: RwLock<Account>,
     config: RwLock<AppConfig>,
-    handle: RwLock<Option<JoinHandle<()>>>,
+    // the local server and the port it was started on
+    handle: RwLock<Option<(JoinHandle<()>, u16)>>,
     cache: Cache,
+    // loaded lazily from the current account's index file
+    search_index: RwLock<Option<SearchIndex>>,
+    // set when the config file couldn't be read at startup
+    config_error: std::sync::RwLock<Option<String>>,
+    // login waiting for the OAuth redirect on the proxy server
+    oauth_callback: OAuthCallback,
+    // running video downloads by video id
+    video_downloads: std::sync::Mutex<HashMap<String, CancellationToken>>,
+    // running lecture batches by batch id
+    video_batches: std::sync::Mutex<HashMap<String, CancellationToken>>,
+    ffmpeg_jobs: FfmpegJobs,
+    // running subtitle generations by video path
+    subtitle_jobs: std::sync::Mutex<HashMap<String, CancellationToken>>,
+    // lectures the local server may stream, by random stream id
+    media_streams: MediaStreams,
+    // only one renewal of the video site session at a time
+    video_session_lock: tokio::sync::Mutex<()>,
+    // the video library file is read, changed and written by one task at a time
+    video_library_lock: tokio::sync::Mutex<()>,
 }
 
 #[cfg(test)]
@@ -0,0 +1,257 @@
+use std::{fs, path::Path};
+
+use super::{
+    constants::MODULES_FOLDER_NAME,
+    discussion::{render_html_document, ResourceLocalizer},
+    App,
+};
+use crate::{
+    error::Result,
+    model::{Course, Module, ModuleItem, Page},
+    utils::{escape_html, sanitize_file_name},
+};
+
+// One line of the offline index
+#[derive(Debug, Default)]
+struct ExportedItem {
+    title: String,
+    indent: i64,
+    // relative path for downloaded content, remote url otherwise
+    link: Option<String>,
+    is_local: bool,
+    is_header: bool,
+}
+
+#[derive(Debug, Default)]
+struct ExportedModule {
+    name: String,
+    items: Vec<ExportedItem>,
+}
+
+// Relative links may contain characters with special meaning in urls
+fn encode_relative_path(path: &str) -> String {
+    path.replace('%', "%25")
+        .replace('#', "%23")
+        .replace(' ', "%20")
+}
+
+fn render_module_index(course_name: &str, modules: &[ExportedModule]) -> String {
+    let mut body = format!("<h1>{}</h1>\n", escape_html(course_name));
+    for module in modules {
+        body += &format!("<h2>{}</h2>\n<ul>\n", escape_html(&module.name));
+        for item in &module.items {
+            let style = format!("margin-left: {}em", item.indent * 2);
+            let content = if item.is_header {
+                format!("<strong>{}</strong>", escape_html(&item.title))
+            } else {
+                match &item.link {
+                    Some(link) if item.is_local => format!(
+                        "<a href=\"{}\">{}</a>",
+                        escape_html(&encode_relative_path(link)),
+                        escape_html(&item.title)
+                    ),
+                    Some(link) => format!(
+                        "<a href=\"{}\">{}</a> <span class=\"meta\">(在线)</span>",
+                        escape_html(link),
+                        escape_html(&item.title)
+                    ),
+                    None => escape_html(&item.title),
+                }
+            };
+            body += &format!("<li style=\"{}\">{}</li>\n", style, content);
+        }
+        body += "</ul>\n";
+    }
+    render_html_document(course_name, &body)
+}
+
+impl App {
+    // Modules with their items, fetching items separately when canvas omits them
+    pub async fn list_modules(&self, course_id: i64) -> Result<Vec<Module>> {
+        let token = self.config.read().await.token.clone();
+        let mut modules = self.client.list_modules(course_id, &token).await?;
+        for module in modules.iter_mut() {
+            if module.items.is_none() {
+                let items = self
+                    .client
+                    .list_module_items(course_id, module.id, &token)
+                    .await?;
+                module.items = Some(items);
+            }
+        }
+        modules.sort_by_key(|module| module.position);
+        Ok(modules)
+    }
+
+    pub async fn list_pages(&self, course_id: i64) -> Result<Vec<Page>> {
+        let token = self.config.read().await.token.clone();
+        self.client.list_pages(course_id, &token).await
+    }
+
+    pub async fn get_page(&self, course_id: i64, page_url: &str) -> Result<Page> {
+        let token = self.config.read().await.token.clone();
+        self.client.get_page(course_id, page_url, &token).await
+    }
+
+    // Download a file item into module_dir, return its file name
+    async fn export_module_file(&self, item: &ModuleItem, module_dir: &Path) -> Result<String> {
+        let token = self.config.read().await.token.clone();
+        let mut file = self
+            .client
+            .get_file(item.content_id.unwrap_or_default(), &token)
+            .await?;
+        file.display_name = sanitize_file_name(&file.display_name);
+        if !module_dir.join(&file.display_name).exists() {
+            self.client
+                .download_file(
+                    &file,
+                    &token,
+                    module_dir.to_str().unwrap_or_default(),
+                    |_| {},
+                )
+                .await?;
+        }
+        Ok(file.display_name)
+    }
+
+    // Save a page as standalone html with its images and files, return its file name
+    async fn export_module_page(
+        &self,
+        course_id: i64,
+        item: &ModuleItem,
+        module_dir: &Path,
+    ) -> Result<String> {
+        let token = self.config.read().await.token.clone();
+        let base_url = self.client.get_base_url().await;
+        let page = self
+            .client
+            .get_page(
+                course_id,
+                item.page_url.as_deref().unwrap_or_default(),
+                &token,
+            )
+            .await?;
+        let mut localizer = ResourceLocalizer::new(self, token, base_url, module_dir);
+        let body = localizer
+            .localize_html(page.body.as_deref().unwrap_or_default())
+            .await;
+        let body = format!("<h1>{}</h1>\n{}\n", escape_html(&page.title), body);
+        let file_name = format!("{}.html", sanitize_file_name(&page.title));
+        fs::write(
+            module_dir.join(&file_name),
+            render_html_document(&page.title, &body),
+        )?;
+        Ok(file_name)
+    }
+
+    async fn export_module_item(
+        &self,
+        course_id: i64,
+        item: &ModuleItem,
+        module_dir: &Path,
+        module_dir_name: &str,
+    ) -> ExportedItem {
+        let mut exported = ExportedItem {
+            title: item.title.clone(),
+            indent: item.indent,
+            ..Default::default()
+        };
+        let result = match item.item_type.as_str() {
+            "SubHeader" => {
+                exported.is_header = true;
+                return exported;
+            }
+            "File" => Some(self.export_module_file(item, module_dir).await),
+            "Page" => Some(self.export_module_page(course_id, item, module_dir).await),
+            _ => None,
+        };
+        match result {
+            Some(Ok(file_name)) => {
+                exported.link = Some(format!("{}/{}", module_dir_name, file_name));
+                exported.is_local = true;
+            }
+            Some(Err(e)) => {
+                tracing::warn!("Failed to export module item {}: {:?}", item.title, e);
+                exported.link = item.html_url.clone();
+            }
+            None => exported.link = item.external_url.clone().or(item.html_url.clone()),
+        }
+        exported
+    }
+
+    // Export modules into {course}/模块 with one folder per module and an offline
+    // index.html, return the path of the index
+    pub async fn export_course_modules(&self, course: &Course) -> Result<String> {
+        let modules = self.list_modules(course.id).await?;
+        let save_path = self.config.read().await.save_path.clone();
+        let export_dir = Path::new(&save_path)
+            .join(self.client.get_course_identifier(course))
+            .join(MODULES_FOLDER_NAME);
+        fs::create_dir_all(&export_dir)?;
+
+        let mut exported_modules = vec![];
+        for module in &modules {
+            let module_dir_name =
+                sanitize_file_name(&format!("{:02}_{}", module.position, module.name));
+            let module_dir = export_dir.join(&module_dir_name);
+            fs::create_dir_all(&module_dir)?;
+            let mut exported = ExportedModule {
+                name: module.name.clone(),
+                items: vec![],
+            };
+            for item in module.items.iter().flatten() {
+                let item = self
+                    .export_module_item(course.id, item, &module_dir, &module_dir_name)
+                    .await;
+                exported.items.push(item);
+            }
+            exported_modules.push(exported);
+        }
+
+        let index_path = export_dir.join("index.html");
+        fs::write(
+            &index_path,
+            render_module_index(&course.name, &exported_modules),
+        )?;
+        tracing::info!("Modules of {} exported to {:?}", course.name, export_dir);
+        Ok(index_path.to_str().unwrap_or_default().to_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_module_index() {
+        let modules = vec![ExportedModule {
+            name: "Week 1".to_owned(),
+            items: vec![
+                ExportedItem {
+                    title: "Slides".to_owned(),
+                    is_header: true,
+                    ..Default::default()
+                },
+                ExportedItem {
+                    title: "Lecture 1.pdf".to_owned(),
+                    indent: 1,
+                    link: Some("01_Week 1/Lecture #1.pdf".to_owned()),
+                    is_local: true,
+                    ..Default::default()
+                },
+                ExportedItem {
+                    title: "Homework <1>".to_owned(),
+                    link: Some("https://oc.sjtu.edu.cn/courses/1/assignments/2".to_owned()),
+                    ..Default::default()
+                },
+            ],
+        }];
+        let html = render_module_index("Course", &modules);
+        assert!(html.contains("<h2>Week 1</h2>"));
+        assert!(html.contains("<strong>Slides</strong>"));
+        assert!(html.contains(
+            "<li style=\"margin-left: 2em\"><a href=\"01_Week%201/Lecture%20%231.pdf\">Lecture 1.pdf</a></li>"
+        ));
+        assert!(html.contains("Homework &lt;1&gt;</a> <span class=\"meta\">(在线)</span>"));
+    }
+}
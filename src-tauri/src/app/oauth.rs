@@ -0,0 +1,233 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::sync::oneshot;
+use uuid::Uuid;
+
+use super::{discussion::render_html_document, App};
+use crate::{
+    client::oauth::{expires_at, OAuthSession},
+    error::{AppError, Result},
+    model::{AppConfig, User},
+    utils::escape_html,
+};
+
+pub(super) const OAUTH_CALLBACK_PATH: &str = "oauth_callback";
+const OAUTH_LOGIN_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+// A login waiting for canvas to redirect the browser back with a code
+pub(super) struct PendingOAuth {
+    state: String,
+    sender: oneshot::Sender<std::result::Result<String, String>>,
+}
+
+pub(super) type OAuthCallback = Arc<Mutex<Option<PendingOAuth>>>;
+
+// Handles the redirect on the local server and returns the page shown in the browser
+pub(super) fn handle_oauth_callback(
+    callback: &OAuthCallback,
+    query: &HashMap<String, String>,
+) -> String {
+    let mut pending = callback.lock().unwrap();
+    let is_expected = pending
+        .as_ref()
+        .is_some_and(|pending| query.get("state") == Some(&pending.state));
+    let message = if !is_expected {
+        "登录请求已过期，请在应用中重新登录".to_owned()
+    } else {
+        let result = match query.get("code") {
+            Some(code) => Ok(code.clone()),
+            None => Err(query
+                .get("error_description")
+                .or(query.get("error"))
+                .cloned()
+                .unwrap_or_else(|| "missing authorization code".to_owned())),
+        };
+        let message = match &result {
+            Ok(_) => "登录成功，可以关闭此页面".to_owned(),
+            Err(e) => format!("登录失败：{}", e),
+        };
+        let _ = pending.take().unwrap().sender.send(result);
+        message
+    };
+    render_html_document(
+        "SJTU Canvas Helper",
+        &format!("<p>{}</p>", escape_html(&message)),
+    )
+}
+
+// A token entered by hand replaces the OAuth login, keeping its refresh token
+// would bring the old token back on the next refresh
+pub(super) fn forget_replaced_oauth_login(current: &AppConfig, config: &mut AppConfig) {
+    if config.token != current.token
+        && !config.canvas_refresh_token.is_empty()
+        && config.canvas_refresh_token == current.canvas_refresh_token
+    {
+        tracing::info!("Canvas token was changed by hand, dropping the OAuth refresh token");
+        config.canvas_refresh_token.clear();
+        config.canvas_token_expires_at = 0;
+    }
+}
+
+pub(super) fn oauth_session(config: &AppConfig) -> Option<OAuthSession> {
+    if config.canvas_refresh_token.is_empty() {
+        return None;
+    }
+    Some(OAuthSession {
+        client_id: config.canvas_client_id.clone(),
+        client_secret: config.canvas_client_secret.clone(),
+        access_token: config.token.clone(),
+        refresh_token: config.canvas_refresh_token.clone(),
+        expires_at: config.canvas_token_expires_at,
+        replaced_token: None,
+    })
+}
+
+impl App {
+    // Point the client at the OAuth tokens of the config, keeping track of a
+    // token replaced by a refresh which requests in flight may still use
+    pub(super) fn sync_oauth_session(&self, config: &AppConfig) {
+        let session = oauth_session(config).map(|mut session| {
+            if let Some(current) = self.client.get_oauth_session() {
+                if current.access_token == session.access_token {
+                    session.replaced_token = current.replaced_token;
+                }
+            }
+            session
+        });
+        self.client.set_oauth_session(session);
+    }
+
+    pub(super) async fn store_oauth_session(&self, session: OAuthSession) -> Result<()> {
+        let mut config = self.get_config().await;
+        config.token = session.access_token;
+        config.canvas_refresh_token = session.refresh_token;
+        config.canvas_token_expires_at = session.expires_at;
+        self.save_config(config).await
+    }
+
+    // Authorization code flow with the developer key of the config. The browser
+    // is sent back to the local proxy server, which hands the code over here.
+    pub async fn login_with_oauth(&self) -> Result<User> {
+        let config = self.get_config().await;
+        if config.canvas_client_id.is_empty() || config.canvas_client_secret.is_empty() {
+            return Err(AppError::OAuth(
+                "client id and secret of the developer key are not set".to_owned(),
+            ));
+        }
+        let Some(proxy_port) = self.proxy_port().await? else {
+            return Err(AppError::OAuth(
+                "local server for the redirect is not ready".to_owned(),
+            ));
+        };
+        let redirect_uri = format!("http://localhost:{}/{}", proxy_port, OAUTH_CALLBACK_PATH);
+        let state = Uuid::new_v4().to_string();
+        let (sender, receiver) = oneshot::channel();
+        // replacing an earlier login drops its sender, which cancels it
+        *self.oauth_callback.lock().unwrap() = Some(PendingOAuth {
+            state: state.clone(),
+            sender,
+        });
+        let url = self
+            .client
+            .get_oauth_authorize_url(&config.canvas_client_id, &redirect_uri, &state)
+            .await?;
+        self.open_path(&url)?;
+
+        let code = match tokio::time::timeout(OAUTH_LOGIN_TIMEOUT, receiver).await {
+            Ok(Ok(Ok(code))) => code,
+            Ok(Ok(Err(e))) => return Err(AppError::OAuth(e)),
+            Ok(Err(_)) => return Err(AppError::OAuth("login was cancelled".to_owned())),
+            Err(_) => {
+                let mut pending = self.oauth_callback.lock().unwrap();
+                if pending
+                    .as_ref()
+                    .is_some_and(|pending| pending.state == state)
+                {
+                    *pending = None;
+                }
+                return Err(AppError::OAuth(
+                    "timed out waiting for the browser".to_owned(),
+                ));
+            }
+        };
+
+        let token = self
+            .client
+            .exchange_oauth_code(
+                &config.canvas_client_id,
+                &config.canvas_client_secret,
+                &redirect_uri,
+                &code,
+            )
+            .await?;
+        let mut config = self.get_config().await;
+        config.token = token.access_token;
+        config.canvas_refresh_token = token.refresh_token.unwrap_or_default();
+        config.canvas_token_expires_at = expires_at(token.expires_in);
+        self.save_config(config).await?;
+        tracing::info!("Logged in with OAuth");
+        self.get_me().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_forget_replaced_oauth_login() {
+        let current = AppConfig {
+            token: "oauth".to_owned(),
+            canvas_refresh_token: "refresh".to_owned(),
+            canvas_token_expires_at: 100,
+            ..Default::default()
+        };
+        // a refresh or a new login replaces both tokens
+        let mut config = AppConfig {
+            token: "refreshed".to_owned(),
+            canvas_refresh_token: "new refresh".to_owned(),
+            ..current.clone()
+        };
+        forget_replaced_oauth_login(&current, &mut config);
+        assert_eq!(config.canvas_refresh_token, "new refresh");
+
+        let mut config = AppConfig {
+            token: "manual".to_owned(),
+            ..current.clone()
+        };
+        forget_replaced_oauth_login(&current, &mut config);
+        assert!(config.canvas_refresh_token.is_empty());
+        assert_eq!(config.canvas_token_expires_at, 0);
+        assert!(oauth_session(&config).is_none());
+    }
+
+    #[test]
+    fn test_handle_oauth_callback() {
+        let callback: OAuthCallback = Default::default();
+        let (sender, mut receiver) = oneshot::channel();
+        *callback.lock().unwrap() = Some(PendingOAuth {
+            state: "state".to_owned(),
+            sender,
+        });
+
+        // forged or stale redirects are ignored
+        let query = HashMap::from([
+            ("state".to_owned(), "other".to_owned()),
+            ("code".to_owned(), "evil".to_owned()),
+        ]);
+        assert!(handle_oauth_callback(&callback, &query).contains("已过期"));
+        assert!(receiver.try_recv().is_err());
+
+        let query = HashMap::from([
+            ("state".to_owned(), "state".to_owned()),
+            ("code".to_owned(), "code".to_owned()),
+        ]);
+        assert!(handle_oauth_callback(&callback, &query).contains("登录成功"));
+        assert_eq!(receiver.try_recv().unwrap(), Ok("code".to_owned()));
+        assert!(callback.lock().unwrap().is_none());
+    }
+}
@@ -0,0 +1,312 @@
+use std::{collections::HashSet, fs};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::{
+    config::migrate_config,
+    secret::{EncryptedFile, Secrets},
+    App,
+};
+use crate::{
+    error::{AppError, Result},
+    model::{
+        Account, ProfileAccountPreview, ProfileBundlePreview, ProfileConflictStrategy,
+        ProfileImportReport, ProfileImportedAccount,
+    },
+};
+
+const PROFILE_BUNDLE_FORMAT: &str = "sjtu-canvas-helper-profile";
+const PROFILE_BUNDLE_VERSION: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BundledAccount {
+    account: Account,
+    // kept as json so configs of older app versions go through the config migrations
+    config: Value,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BundledSecrets {
+    account: Account,
+    secrets: Secrets,
+}
+
+// Every account of a machine in one file. Configs never carry credentials,
+// those are only bundled on request and encrypted with an export passphrase.
+#[derive(Debug, Serialize, Deserialize)]
+struct ProfileBundle {
+    format: String,
+    version: u32,
+    exported_at: String,
+    current_account: Account,
+    accounts: Vec<BundledAccount>,
+    #[serde(default)]
+    secrets: Option<EncryptedFile>,
+}
+
+impl ProfileBundle {
+    fn read(path: &str) -> Result<Self> {
+        let invalid = |message: String| AppError::InvalidProfileBundle(message);
+        let bundle: ProfileBundle =
+            serde_json::from_slice(&fs::read(path)?).map_err(|e| invalid(e.to_string()))?;
+        if bundle.format != PROFILE_BUNDLE_FORMAT {
+            return Err(invalid(format!("{} is not a profile bundle", path)));
+        }
+        if bundle.version > PROFILE_BUNDLE_VERSION {
+            return Err(invalid(format!(
+                "bundle version {} is newer than supported version {}",
+                bundle.version, PROFILE_BUNDLE_VERSION
+            )));
+        }
+        for bundled in &bundle.accounts {
+            check_account_name(&bundled.account)?;
+        }
+        Ok(bundle)
+    }
+
+    fn open_secrets(&self, passphrase: &str) -> Result<Vec<BundledSecrets>> {
+        match &self.secrets {
+            Some(secrets) => Ok(serde_json::from_slice(
+                &secrets.open_with_passphrase(passphrase)?,
+            )?),
+            None => Ok(vec![]),
+        }
+    }
+}
+
+// Account names end up in file names
+fn check_account_name(account: &Account) -> Result<()> {
+    if let Account::Custom(name) = account {
+        if name.is_empty() || name.contains(['/', '\\']) || name.contains("..") {
+            return Err(AppError::InvalidProfileBundle(format!(
+                "invalid account name {:?}",
+                name
+            )));
+        }
+    }
+    Ok(())
+}
+
+// The account a bundled account is imported as, None if it's skipped
+fn resolve_import_target<F: Fn(&Account) -> bool>(
+    account: &Account,
+    strategy: ProfileConflictStrategy,
+    is_taken: F,
+) -> Option<Account> {
+    if !is_taken(account) {
+        return Some(account.clone());
+    }
+    match strategy {
+        ProfileConflictStrategy::Skip => None,
+        ProfileConflictStrategy::Overwrite => Some(account.clone()),
+        ProfileConflictStrategy::Rename => {
+            let base = match account {
+                Account::Default => "default",
+                Account::Custom(name) => name,
+            };
+            (1..)
+                .map(|i| match i {
+                    1 => Account::Custom(format!("{}_imported", base)),
+                    i => Account::Custom(format!("{}_imported_{}", base, i)),
+                })
+                .find(|candidate| !is_taken(candidate))
+        }
+    }
+}
+
+impl App {
+    // Writes all accounts and their configs into one file. Credentials are
+    // only included when a passphrase to encrypt them is given.
+    pub async fn export_profile_bundle(&self, path: &str, passphrase: Option<&str>) -> Result<()> {
+        let account_info = App::read_account_info()?;
+        let mut accounts = vec![];
+        let mut bundled_secrets = vec![];
+        for account in &account_info.all_accounts {
+            let mut config = match App::load_config(account) {
+                Ok(config) => config,
+                // the default account has no config until it's first saved
+                Err(AppError::IO(_)) if *account == Account::Default => Default::default(),
+                Err(e) => return Err(e),
+            };
+            let secrets = Secrets::take_from(&mut config);
+            if !secrets.is_empty() {
+                bundled_secrets.push(BundledSecrets {
+                    account: account.clone(),
+                    secrets,
+                });
+            }
+            accounts.push(BundledAccount {
+                account: account.clone(),
+                config: serde_json::to_value(&config)?,
+            });
+        }
+
+        let secrets = match passphrase {
+            Some(passphrase) => Some(EncryptedFile::seal_with_passphrase(
+                passphrase,
+                &serde_json::to_vec(&bundled_secrets)?,
+            )?),
+            None => None,
+        };
+        let bundle = ProfileBundle {
+            format: PROFILE_BUNDLE_FORMAT.to_owned(),
+            version: PROFILE_BUNDLE_VERSION,
+            exported_at: chrono::Local::now().to_rfc3339(),
+            current_account: account_info.current_account,
+            accounts,
+            secrets,
+        };
+        fs::write(path, serde_json::to_vec_pretty(&bundle)?)?;
+        tracing::info!("Profile exported to {}", path);
+        Ok(())
+    }
+
+    pub fn preview_profile_bundle(path: &str) -> Result<ProfileBundlePreview> {
+        let bundle = ProfileBundle::read(path)?;
+        let mut accounts = vec![];
+        for bundled in bundle.accounts {
+            accounts.push(ProfileAccountPreview {
+                exists: App::account_exists(&bundled.account)?,
+                account: bundled.account,
+            });
+        }
+        Ok(ProfileBundlePreview {
+            exported_at: bundle.exported_at,
+            current_account: bundle.current_account,
+            includes_secrets: bundle.secrets.is_some(),
+            accounts,
+        })
+    }
+
+    // Imports every account of a bundle, resolving name conflicts with the
+    // strategy. Bundled credentials are only imported with the passphrase,
+    // otherwise overwritten accounts keep the credentials they had.
+    pub async fn import_profile_bundle(
+        &self,
+        path: &str,
+        strategy: ProfileConflictStrategy,
+        passphrase: Option<&str>,
+    ) -> Result<ProfileImportReport> {
+        let bundle = ProfileBundle::read(path)?;
+        let bundled_secrets = match passphrase {
+            Some(passphrase) => bundle.open_secrets(passphrase)?,
+            None => vec![],
+        };
+
+        let mut report = ProfileImportReport::default();
+        let mut targets = HashSet::new();
+        for bundled in bundle.accounts {
+            let target = resolve_import_target(&bundled.account, strategy, |account| {
+                targets.contains(account) || App::account_exists(account).unwrap_or(true)
+            });
+            let Some(target) = target else {
+                report.skipped.push(bundled.account);
+                continue;
+            };
+
+            let source = format!("{} in {}", serde_json::to_string(&bundled.account)?, path);
+            let (mut config, _) = migrate_config(&serde_json::to_vec(&bundled.config)?, &source)?;
+            let secrets = bundled_secrets
+                .iter()
+                .find(|bundled_secrets| bundled_secrets.account == bundled.account);
+            match secrets {
+                Some(bundled_secrets) => bundled_secrets.secrets.apply_to(&mut config),
+                None if App::account_exists(&target)? => {
+                    let mut existing = App::load_config(&target)?;
+                    Secrets::take_from(&mut existing).apply_to(&mut config);
+                }
+                None => (),
+            }
+
+            if target != Account::Default && !App::account_exists(&target)? {
+                App::create_account(&target)?;
+            }
+            App::write_config_file(&target, &config)?;
+            targets.insert(target.clone());
+            report.imported.push(ProfileImportedAccount {
+                from: bundled.account,
+                to: target,
+            });
+        }
+
+        if targets.contains(&self.get_current_account().await) {
+            self.reload_config().await?;
+        }
+        tracing::info!("Profile imported from {}: {:?}", path, report);
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_import_target() {
+        let existing = [
+            Account::Default,
+            Account::Custom("ta".to_owned()),
+            Account::Custom("ta_imported".to_owned()),
+        ];
+        let is_taken = |account: &Account| existing.contains(account);
+        let new_account = Account::Custom("new".to_owned());
+        let ta = Account::Custom("ta".to_owned());
+
+        for strategy in [
+            ProfileConflictStrategy::Skip,
+            ProfileConflictStrategy::Overwrite,
+            ProfileConflictStrategy::Rename,
+        ] {
+            let target = resolve_import_target(&new_account, strategy, is_taken);
+            assert_eq!(target.as_ref(), Some(&new_account));
+        }
+        assert_eq!(
+            resolve_import_target(&ta, ProfileConflictStrategy::Skip, is_taken),
+            None
+        );
+        assert_eq!(
+            resolve_import_target(&ta, ProfileConflictStrategy::Overwrite, is_taken),
+            Some(ta.clone())
+        );
+        assert_eq!(
+            resolve_import_target(&ta, ProfileConflictStrategy::Rename, is_taken),
+            Some(Account::Custom("ta_imported_2".to_owned()))
+        );
+        assert_eq!(
+            resolve_import_target(&Account::Default, ProfileConflictStrategy::Rename, is_taken),
+            Some(Account::Custom("default_imported".to_owned()))
+        );
+    }
+
+    #[test]
+    fn test_bundle_secrets() -> Result<()> {
+        let bundled = vec![BundledSecrets {
+            account: Account::Default,
+            secrets: Secrets {
+                token: "canvas-token".to_owned(),
+                ..Default::default()
+            },
+        }];
+        let bundle = ProfileBundle {
+            format: PROFILE_BUNDLE_FORMAT.to_owned(),
+            version: PROFILE_BUNDLE_VERSION,
+            exported_at: Default::default(),
+            current_account: Account::Default,
+            accounts: vec![],
+            secrets: Some(EncryptedFile::seal_with_passphrase(
+                "passphrase",
+                &serde_json::to_vec(&bundled)?,
+            )?),
+        };
+        let content = serde_json::to_string(&bundle)?;
+        assert!(!content.contains("canvas-token"));
+
+        let bundle: ProfileBundle = serde_json::from_str(&content)?;
+        let secrets = bundle.open_secrets("passphrase")?;
+        assert_eq!(secrets[0].secrets.token, "canvas-token");
+        assert!(bundle.open_secrets("wrong").is_err());
+        assert!(check_account_name(&Account::Custom("../evil".to_owned())).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,267 @@
+use std::{collections::HashMap, path::Path};
+
+use xlsxwriter::Workbook;
+
+use super::App;
+use crate::{
+    error::{AppError, Result},
+    model::{
+        Quiz, QuizQuestion, QuizQuestionResult, QuizResult, QuizStatistics, QuizSubmission,
+        QuizSubmissionAttempt, User,
+    },
+    utils::html_to_text,
+};
+
+// The most recent attempt which carries question-level data
+fn pick_latest_attempt(history: Vec<QuizSubmissionAttempt>) -> Option<QuizSubmissionAttempt> {
+    history
+        .into_iter()
+        .filter(|attempt| attempt.submission_data.is_some())
+        .max_by_key(|attempt| attempt.attempt.unwrap_or_default())
+}
+
+fn attach_question_info(results: &mut [QuizQuestionResult], questions: &[QuizQuestion]) {
+    let questions: HashMap<_, _> = questions
+        .iter()
+        .map(|question| (question.id, question))
+        .collect();
+    for result in results.iter_mut() {
+        if let Some(question) = questions.get(&result.question_id) {
+            result.question_name = Some(question.question_name.clone());
+            result.points_possible = question.points_possible;
+        }
+    }
+}
+
+fn format_optional<T: ToString>(value: &Option<T>) -> String {
+    value.as_ref().map(|v| v.to_string()).unwrap_or_default()
+}
+
+impl App {
+    pub async fn list_quizzes(&self, course_id: i64) -> Result<Vec<Quiz>> {
+        let token = self.config.read().await.token.clone();
+        self.client.list_quizzes(course_id, &token).await
+    }
+
+    pub async fn get_my_quiz_result(&self, course_id: i64, quiz: &Quiz) -> Result<QuizResult> {
+        // ungraded surveys have no assignment to carry the submission history
+        let Some(assignment_id) = quiz.assignment_id else {
+            return Err(AppError::QuizResultUnavailable(format!(
+                "quiz \"{}\" is not graded",
+                quiz.title
+            )));
+        };
+        let token = self.config.read().await.token.clone();
+        let history = self
+            .client
+            .get_quiz_submission_history(course_id, assignment_id, &token)
+            .await?;
+        let Some(attempt) = pick_latest_attempt(history.submission_history) else {
+            return Err(AppError::QuizResultUnavailable(format!(
+                "no finished attempt of quiz \"{}\"",
+                quiz.title
+            )));
+        };
+
+        let mut questions = attempt.submission_data.unwrap_or_default();
+        // students usually can't list questions, results are still useful without names
+        match self
+            .client
+            .list_quiz_questions(course_id, quiz.id, &token)
+            .await
+        {
+            Ok(quiz_questions) => attach_question_info(&mut questions, &quiz_questions),
+            Err(e) => tracing::info!("Quiz questions of {} unavailable: {:?}", quiz.id, e),
+        }
+        Ok(QuizResult {
+            quiz_id: quiz.id,
+            attempt: attempt.attempt,
+            score: attempt.score,
+            questions,
+        })
+    }
+
+    pub async fn list_quiz_submissions(
+        &self,
+        course_id: i64,
+        quiz_id: i64,
+    ) -> Result<Vec<QuizSubmission>> {
+        let token = self.config.read().await.token.clone();
+        self.client
+            .list_quiz_submissions(course_id, quiz_id, &token)
+            .await
+    }
+
+    pub async fn get_quiz_statistics(
+        &self,
+        course_id: i64,
+        quiz_id: i64,
+    ) -> Result<Option<QuizStatistics>> {
+        let token = self.config.read().await.token.clone();
+        self.client
+            .get_quiz_statistics(course_id, quiz_id, &token)
+            .await
+    }
+
+    // Export all submissions and per-question statistics of a quiz into save_path/save_name
+    pub async fn export_quiz_submissions(
+        &self,
+        course_id: i64,
+        quiz_id: i64,
+        save_name: &str,
+    ) -> Result<()> {
+        let token = self.config.read().await.token.clone();
+        let submissions = self
+            .client
+            .list_quiz_submissions(course_id, quiz_id, &token)
+            .await?;
+        let statistics = self
+            .client
+            .get_quiz_statistics(course_id, quiz_id, &token)
+            .await?
+            .unwrap_or_default();
+        let students = self.client.list_course_students(course_id, &token).await?;
+        let students: HashMap<i64, &User> = students
+            .iter()
+            .map(|student| (student.id, student))
+            .collect();
+
+        let save_path = self.config.read().await.save_path.clone();
+        let path = Path::new(&save_path).join(save_name);
+        let workbook = Workbook::new(path.to_str().unwrap())?;
+
+        let mut sheet = workbook.add_worksheet(Some("submissions"))?;
+        let headers = [
+            "user_id",
+            "name",
+            "login_id",
+            "attempt",
+            "score",
+            "kept_score",
+            "started_at",
+            "finished_at",
+            "time_spent",
+            "workflow_state",
+        ];
+        for (col, header) in headers.iter().enumerate() {
+            sheet.write_string(0, col as u16, header, None)?;
+        }
+        for (row, submission) in submissions.iter().enumerate() {
+            let row = row as u32 + 1;
+            let student = submission
+                .user_id
+                .and_then(|user_id| students.get(&user_id));
+            sheet.write_string(row, 0, &format_optional(&submission.user_id), None)?;
+            sheet.write_string(
+                row,
+                1,
+                &student.map(|s| s.name.clone()).unwrap_or_default(),
+                None,
+            )?;
+            sheet.write_string(
+                row,
+                2,
+                &student.map(|s| s.login_id.clone()).unwrap_or_default(),
+                None,
+            )?;
+            sheet.write_string(row, 3, &format_optional(&submission.attempt), None)?;
+            sheet.write_string(row, 4, &format_optional(&submission.score), None)?;
+            sheet.write_string(row, 5, &format_optional(&submission.kept_score), None)?;
+            sheet.write_string(row, 6, &format_optional(&submission.started_at), None)?;
+            sheet.write_string(row, 7, &format_optional(&submission.finished_at), None)?;
+            sheet.write_string(row, 8, &format_optional(&submission.time_spent), None)?;
+            sheet.write_string(row, 9, &submission.workflow_state, None)?;
+        }
+
+        let mut sheet = workbook.add_worksheet(Some("questions"))?;
+        let headers = [
+            "position",
+            "question_id",
+            "question_type",
+            "question_text",
+            "responses",
+            "answered",
+            "correct",
+            "partially_correct",
+            "incorrect",
+        ];
+        for (col, header) in headers.iter().enumerate() {
+            sheet.write_string(0, col as u16, header, None)?;
+        }
+        let mut questions = statistics.question_statistics;
+        questions.sort_by_key(|question| question.position.unwrap_or_default());
+        for (row, question) in questions.iter().enumerate() {
+            let row = row as u32 + 1;
+            sheet.write_string(row, 0, &format_optional(&question.position), None)?;
+            sheet.write_string(row, 1, &question.id, None)?;
+            sheet.write_string(row, 2, &question.question_type, None)?;
+            sheet.write_string(row, 3, &html_to_text(&question.question_text), None)?;
+            sheet.write_string(row, 4, &question.responses.to_string(), None)?;
+            sheet.write_string(
+                row,
+                5,
+                &format_optional(&question.answered_student_count),
+                None,
+            )?;
+            sheet.write_string(
+                row,
+                6,
+                &format_optional(&question.correct_student_count),
+                None,
+            )?;
+            sheet.write_string(
+                row,
+                7,
+                &format_optional(&question.partially_correct_student_count),
+                None,
+            )?;
+            sheet.write_string(
+                row,
+                8,
+                &format_optional(&question.incorrect_student_count),
+                None,
+            )?;
+        }
+        workbook.close()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::QuizSubmissionHistory;
+
+    #[test]
+    fn test_pick_latest_attempt() -> Result<()> {
+        let json = r#"{"submission_history": [
+            {"attempt": 1, "score": 3.0, "submission_data": [
+                {"question_id": 11, "correct": true, "points": 2.0, "text": "A"},
+                {"question_id": 12, "correct": "partial", "points": 1.0, "text": ""}
+            ]},
+            {"attempt": 2, "score": 4.0, "submission_data": [
+                {"question_id": 11, "correct": false, "points": 0.0, "text": "B"},
+                {"question_id": 12, "correct": "undefined", "points": 0.0, "text": ""}
+            ]},
+            {"attempt": 3, "score": null}
+        ]}"#;
+        let history: QuizSubmissionHistory = serde_json::from_str(json)?;
+        let attempt = pick_latest_attempt(history.submission_history).unwrap();
+        assert_eq!(attempt.attempt, Some(2));
+
+        let mut results = attempt.submission_data.unwrap();
+        assert_eq!(results[0].correct, "incorrect");
+        assert_eq!(results[1].correct, "undefined");
+
+        let questions = vec![QuizQuestion {
+            id: 11,
+            question_name: "Question 1".to_owned(),
+            points_possible: Some(2.0),
+            ..Default::default()
+        }];
+        attach_question_info(&mut results, &questions);
+        assert_eq!(results[0].question_name.as_deref(), Some("Question 1"));
+        assert_eq!(results[1].question_name, None);
+        Ok(())
+    }
+}
@@ -0,0 +1,699 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::Path,
+};
+
+use chrono::{DateTime, FixedOffset, NaiveDate};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    process::Command,
+    sync::{RwLockReadGuard, RwLockWriteGuard},
+};
+
+use super::App;
+use crate::{
+    error::{AppError, Result},
+    model::{
+        Course, DiscussionTopic, Reply, SearchDocument, SearchDocumentKind, SearchFilter,
+        SearchHit, SearchIndexStatus,
+    },
+    utils::html_to_text,
+};
+
+// BM25 parameters
+const K1: f64 = 1.2;
+const B: f64 = 0.75;
+// A term in the title counts as this many occurrences in the body
+const TITLE_WEIGHT: u32 = 3;
+// Extracted text longer than this (in chars) is truncated before indexing
+const MAX_CONTENT_CHARS: usize = 200_000;
+const SNIPPET_CONTEXT_CHARS: usize = 60;
+const SEARCH_INDEX_FILE_NAME: &str = "search_index";
+
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct SearchIndex {
+    documents: HashMap<String, SearchDocument>,
+    // term -> document id -> weighted term frequency
+    postings: HashMap<String, HashMap<String, u32>>,
+    doc_lengths: HashMap<String, u32>,
+    // course id -> time the course was last indexed
+    indexed_courses: HashMap<i64, String>,
+}
+
+impl SearchIndex {
+    pub fn insert(&mut self, document: SearchDocument) {
+        self.remove(&document.id);
+        let mut term_freqs: HashMap<String, u32> = HashMap::new();
+        for term in tokenize(&document.title) {
+            *term_freqs.entry(term).or_default() += TITLE_WEIGHT;
+        }
+        for term in tokenize(&document.content) {
+            *term_freqs.entry(term).or_default() += 1;
+        }
+        let length = term_freqs.values().sum();
+        for (term, freq) in term_freqs {
+            self.postings
+                .entry(term)
+                .or_default()
+                .insert(document.id.clone(), freq);
+        }
+        self.doc_lengths.insert(document.id.clone(), length);
+        self.documents.insert(document.id.clone(), document);
+    }
+
+    pub fn remove(&mut self, id: &str) {
+        if self.documents.remove(id).is_none() {
+            return;
+        }
+        self.doc_lengths.remove(id);
+        self.postings.retain(|_, docs| {
+            docs.remove(id);
+            !docs.is_empty()
+        });
+    }
+
+    pub fn remove_course(&mut self, course_id: i64) {
+        let ids: HashSet<String> = self
+            .documents
+            .values()
+            .filter(|document| document.course_id == course_id)
+            .map(|document| document.id.clone())
+            .collect();
+        self.documents.retain(|id, _| !ids.contains(id));
+        self.doc_lengths.retain(|id, _| !ids.contains(id));
+        self.postings.retain(|_, docs| {
+            docs.retain(|id, _| !ids.contains(id));
+            !docs.is_empty()
+        });
+        self.indexed_courses.remove(&course_id);
+    }
+
+    pub fn mark_course_indexed(&mut self, course_id: i64) {
+        self.indexed_courses
+            .insert(course_id, chrono::Local::now().to_rfc3339());
+    }
+
+    pub fn status(&self) -> SearchIndexStatus {
+        SearchIndexStatus {
+            document_count: self.documents.len(),
+            indexed_courses: self.indexed_courses.clone(),
+        }
+    }
+
+    pub fn search(&self, query: &str, filter: &SearchFilter, limit: usize) -> Vec<SearchHit> {
+        let mut terms = tokenize(query);
+        terms.sort();
+        terms.dedup();
+        if terms.is_empty() || self.documents.is_empty() {
+            return vec![];
+        }
+
+        let total_docs = self.documents.len() as f64;
+        let avg_length = self
+            .doc_lengths
+            .values()
+            .map(|len| *len as f64)
+            .sum::<f64>()
+            / total_docs;
+        let mut scores: HashMap<&str, f64> = HashMap::new();
+        for term in &terms {
+            let Some(docs) = self.postings.get(term) else {
+                continue;
+            };
+            let df = docs.len() as f64;
+            let idf = (1.0 + (total_docs - df + 0.5) / (df + 0.5)).ln();
+            for (id, freq) in docs {
+                let tf = *freq as f64;
+                let length = *self.doc_lengths.get(id).unwrap_or(&0) as f64;
+                let norm = K1 * (1.0 - B + B * length / avg_length.max(1.0));
+                *scores.entry(id).or_default() += idf * tf * (K1 + 1.0) / (tf + norm);
+            }
+        }
+
+        let query_lower = query.trim().to_lowercase();
+        let mut hits: Vec<SearchHit> = scores
+            .into_iter()
+            .filter_map(|(id, score)| {
+                let document = self.documents.get(id)?;
+                if !matches_filter(document, filter) {
+                    return None;
+                }
+                // exact phrase in title is the strongest signal
+                let score = if document.title.to_lowercase().contains(&query_lower) {
+                    score * 1.5
+                } else {
+                    score
+                };
+                Some(SearchHit {
+                    snippet: make_snippet(&document.content, &terms),
+                    document: document.clone(),
+                    score,
+                })
+            })
+            .collect();
+        hits.sort_by(|a, b| {
+            b.score
+                .total_cmp(&a.score)
+                .then_with(|| a.document.id.cmp(&b.document.id))
+        });
+        hits.truncate(limit);
+        hits
+    }
+}
+
+fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{4e00}'..='\u{9fff}'
+        | '\u{3400}'..='\u{4dbf}'
+        | '\u{f900}'..='\u{faff}'
+        | '\u{3040}'..='\u{30ff}'
+        | '\u{ac00}'..='\u{d7af}')
+}
+
+// Lowercased alphanumeric words, CJK runs are split into unigrams and bigrams
+// since they are not separated by spaces
+fn tokenize(text: &str) -> Vec<String> {
+    fn flush_cjk(run: &mut Vec<char>, tokens: &mut Vec<String>) {
+        for (i, c) in run.iter().enumerate() {
+            tokens.push(c.to_string());
+            if let Some(next) = run.get(i + 1) {
+                tokens.push(format!("{}{}", c, next));
+            }
+        }
+        run.clear();
+    }
+
+    let mut tokens = vec![];
+    let mut word = String::new();
+    let mut cjk_run = vec![];
+    for c in text.chars() {
+        if is_cjk(c) {
+            if !word.is_empty() {
+                tokens.push(std::mem::take(&mut word));
+            }
+            cjk_run.push(c);
+        } else if c.is_alphanumeric() {
+            flush_cjk(&mut cjk_run, &mut tokens);
+            word.extend(c.to_lowercase());
+        } else {
+            flush_cjk(&mut cjk_run, &mut tokens);
+            if !word.is_empty() {
+                tokens.push(std::mem::take(&mut word));
+            }
+        }
+    }
+    flush_cjk(&mut cjk_run, &mut tokens);
+    if !word.is_empty() {
+        tokens.push(word);
+    }
+    tokens
+}
+
+fn parse_date(date: &str) -> Option<DateTime<FixedOffset>> {
+    if let Ok(date) = DateTime::parse_from_rfc3339(date) {
+        return Some(date);
+    }
+    let date = NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()?;
+    Some(date.and_hms_opt(0, 0, 0)?.and_utc().fixed_offset())
+}
+
+fn matches_filter(document: &SearchDocument, filter: &SearchFilter) -> bool {
+    if !filter.course_ids.is_empty() && !filter.course_ids.contains(&document.course_id) {
+        return false;
+    }
+    if !filter.kinds.is_empty() && !filter.kinds.contains(&document.kind) {
+        return false;
+    }
+    if filter.start_date.is_none() && filter.end_date.is_none() {
+        return true;
+    }
+    let Some(date) = document.date.as_deref().and_then(parse_date) else {
+        return false;
+    };
+    if let Some(start) = filter.start_date.as_deref().and_then(parse_date) {
+        if date < start {
+            return false;
+        }
+    }
+    if let Some(end) = filter.end_date.as_deref().and_then(parse_date) {
+        if date > end {
+            return false;
+        }
+    }
+    true
+}
+
+// A window of the content around the first matched term
+fn make_snippet(content: &str, terms: &[String]) -> String {
+    let chars: Vec<char> = content.chars().collect();
+    let lowered: Vec<char> = chars
+        .iter()
+        .map(|c| c.to_lowercase().next().unwrap_or(*c))
+        .collect();
+    let position = terms
+        .iter()
+        .filter_map(|term| {
+            let term: Vec<char> = term.chars().collect();
+            lowered
+                .windows(term.len())
+                .position(|window| window == term)
+        })
+        .min();
+
+    let (start, end) = match position {
+        Some(position) => (
+            position.saturating_sub(SNIPPET_CONTEXT_CHARS),
+            (position + 2 * SNIPPET_CONTEXT_CHARS).min(chars.len()),
+        ),
+        None => (0, (2 * SNIPPET_CONTEXT_CHARS).min(chars.len())),
+    };
+    let mut snippet: String = chars[start..end].iter().collect();
+    if start > 0 {
+        snippet = format!("...{}", snippet);
+    }
+    if end < chars.len() {
+        snippet += "...";
+    }
+    snippet
+}
+
+fn truncate_content(mut content: String) -> String {
+    if let Some((idx, _)) = content.char_indices().nth(MAX_CONTENT_CHARS) {
+        content.truncate(idx);
+    }
+    content
+}
+
+fn notebook_to_text(raw: &str) -> Option<String> {
+    let notebook: serde_json::Value = serde_json::from_str(raw).ok()?;
+    let mut text = String::new();
+    for cell in notebook.get("cells")?.as_array()? {
+        match cell.get("source") {
+            Some(serde_json::Value::Array(lines)) => {
+                for line in lines.iter().filter_map(|line| line.as_str()) {
+                    text += line;
+                }
+            }
+            Some(serde_json::Value::String(source)) => text += source,
+            _ => continue,
+        }
+        text.push('\n');
+    }
+    Some(text)
+}
+
+// Text of a downloaded file, None for binary formats we can't read
+async fn extract_file_text(path: &Path) -> Option<String> {
+    let extension = path.extension()?.to_str()?.to_lowercase();
+    let text = match extension.as_str() {
+        "txt" | "md" | "csv" | "json" | "tex" | "py" | "c" | "h" | "cpp" | "hpp" | "cc"
+        | "java" | "rs" | "go" | "js" | "ts" | "m" | "r" | "sql" | "sh" | "v" | "sv" | "vhd"
+        | "s" | "asm" | "yaml" | "yml" | "xml" | "srt" | "vtt" => {
+            String::from_utf8_lossy(&fs::read(path).ok()?).into_owned()
+        }
+        "html" | "htm" => html_to_text(&String::from_utf8_lossy(&fs::read(path).ok()?)),
+        "ipynb" => notebook_to_text(&String::from_utf8_lossy(&fs::read(path).ok()?))?,
+        // relies on poppler's pdftotext being installed, skipped otherwise
+        "pdf" => {
+            let output = Command::new("pdftotext")
+                .arg("-enc")
+                .arg("UTF-8")
+                .arg(path)
+                .arg("-")
+                .output()
+                .await
+                .ok()?;
+            if !output.status.success() {
+                return None;
+            }
+            String::from_utf8_lossy(&output.stdout).into_owned()
+        }
+        _ => return None,
+    };
+    Some(truncate_content(text))
+}
+
+fn collect_reply_text(replies: &[Reply], text: &mut String) {
+    for reply in replies {
+        if reply.deleted == Some(true) {
+            continue;
+        }
+        text.push('\n');
+        *text += &html_to_text(reply.message.as_deref().unwrap_or_default());
+        collect_reply_text(&reply.replies, text);
+    }
+}
+
+impl App {
+    // Takes the write lock on the index, loading it from disk on first use.
+    // Hold the guard across a load/modify/save so concurrent updates don't
+    // overwrite each other. A corrupt index starts over empty.
+    async fn lock_search_index(&self) -> RwLockWriteGuard<'_, Option<SearchIndex>> {
+        let mut guard = self.search_index.write().await;
+        if guard.is_none() {
+            let path = App::get_account_data_path(
+                &*self.current_account.read().await,
+                SEARCH_INDEX_FILE_NAME,
+            );
+            let index = match fs::read(&path) {
+                Ok(content) => serde_json::from_slice(&content).unwrap_or_else(|e| {
+                    tracing::warn!("Ignoring corrupt search index: {:?}", e);
+                    SearchIndex::default()
+                }),
+                Err(_) => SearchIndex::default(),
+            };
+            *guard = Some(index);
+        }
+        guard
+    }
+
+    async fn read_search_index(&self) -> RwLockReadGuard<'_, Option<SearchIndex>> {
+        let guard = self.search_index.read().await;
+        if guard.is_some() {
+            return guard;
+        }
+        drop(guard);
+        self.lock_search_index().await.downgrade()
+    }
+
+    async fn save_search_index(&self, index: &SearchIndex) -> Result<()> {
+        let path =
+            App::get_account_data_path(&*self.current_account.read().await, SEARCH_INDEX_FILE_NAME);
+        fs::write(path, serde_json::to_vec(index)?)?;
+        Ok(())
+    }
+
+    async fn collect_course_files(
+        &self,
+        course: &Course,
+        token: &str,
+        save_path: &str,
+    ) -> Result<Vec<SearchDocument>> {
+        let base_url = self.client.get_base_url().await;
+        let folders_and_files = self.client.get_folders_and_files(course.id, token).await?;
+        let mut documents = vec![];
+        for file in &folders_and_files.files {
+            let local_path = folders_and_files
+                .folders_map
+                .get(&file.folder_id)
+                .and_then(|folder| {
+                    self.client
+                        .get_course_file_path(course, folder, file, save_path)
+                })
+                .filter(|path| path.exists());
+            let content = match &local_path {
+                Some(path) => extract_file_text(path).await.unwrap_or_default(),
+                None => String::new(),
+            };
+            documents.push(SearchDocument {
+                id: format!("file:{}", file.id),
+                kind: SearchDocumentKind::File,
+                course_id: course.id,
+                course_name: course.name.clone(),
+                title: file.display_name.clone(),
+                content,
+                url: format!("{}/courses/{}/files/{}", base_url, course.id, file.id),
+                local_path: local_path.map(|path| path.to_string_lossy().into_owned()),
+                date: file.updated_at.clone(),
+            });
+        }
+        Ok(documents)
+    }
+
+    async fn collect_course_assignments(
+        &self,
+        course: &Course,
+        token: &str,
+    ) -> Result<Vec<SearchDocument>> {
+        let assignments = self
+            .client
+            .list_course_assignments(course.id, token)
+            .await?;
+        let documents = assignments
+            .into_iter()
+            .map(|assignment| SearchDocument {
+                id: format!("assignment:{}", assignment.id),
+                kind: SearchDocumentKind::Assignment,
+                course_id: course.id,
+                course_name: course.name.clone(),
+                title: assignment.name,
+                content: truncate_content(html_to_text(
+                    assignment.description.as_deref().unwrap_or_default(),
+                )),
+                url: assignment.html_url,
+                local_path: None,
+                date: assignment.due_at.or(assignment.unlock_at),
+            })
+            .collect();
+        Ok(documents)
+    }
+
+    async fn topic_to_document(
+        &self,
+        course: &Course,
+        topic: DiscussionTopic,
+        kind: SearchDocumentKind,
+        token: &str,
+    ) -> Result<SearchDocument> {
+        let mut content = html_to_text(&topic.message);
+        if topic.discussion_subentry_count > 0 {
+            let discussion = self
+                .client
+                .get_full_discussion(course.id, topic.id, token)
+                .await?;
+            for entry in &discussion.view {
+                if entry.deleted == Some(true) {
+                    continue;
+                }
+                content.push('\n');
+                content += &html_to_text(entry.message.as_deref().unwrap_or_default());
+                collect_reply_text(&entry.replies, &mut content);
+            }
+        }
+        let prefix = match kind {
+            SearchDocumentKind::Announcement => "announcement",
+            _ => "discussion",
+        };
+        Ok(SearchDocument {
+            id: format!("{}:{}", prefix, topic.id),
+            kind,
+            course_id: course.id,
+            course_name: course.name.clone(),
+            title: topic.title,
+            content: truncate_content(content),
+            url: topic.html_url,
+            local_path: None,
+            date: topic.posted_at.or(topic.created_at),
+        })
+    }
+
+    async fn collect_course_topics(
+        &self,
+        course: &Course,
+        token: &str,
+    ) -> Result<Vec<SearchDocument>> {
+        let mut documents = vec![];
+        // announcements are excluded from the discussion list by canvas
+        let topics = self.client.list_discussion_topics(course.id, token).await?;
+        for topic in topics {
+            let title = topic.title.clone();
+            match self
+                .topic_to_document(course, topic, SearchDocumentKind::Discussion, token)
+                .await
+            {
+                Ok(document) => documents.push(document),
+                Err(e) => tracing::error!("Failed to index topic {}: {}", title, e),
+            }
+        }
+        let announcements = self
+            .client
+            .list_announcement_topics(course.id, token)
+            .await?;
+        for topic in announcements {
+            let title = topic.title.clone();
+            match self
+                .topic_to_document(course, topic, SearchDocumentKind::Announcement, token)
+                .await
+            {
+                Ok(document) => documents.push(document),
+                Err(e) => tracing::error!("Failed to index topic {}: {}", title, e),
+            }
+        }
+        Ok(documents)
+    }
+
+    async fn collect_course_documents(&self, course: &Course) -> Result<Vec<SearchDocument>> {
+        let config = self.config.read().await.clone();
+        let mut documents = self
+            .collect_course_files(course, &config.token, &config.save_path)
+            .await?;
+        documents.extend(
+            self.collect_course_assignments(course, &config.token)
+                .await?,
+        );
+        documents.extend(self.collect_course_topics(course, &config.token).await?);
+        Ok(documents)
+    }
+
+    // Re-index the given courses, replacing anything indexed for them before.
+    // A course that fails to be fetched is logged and keeps its old documents.
+    pub async fn build_search_index(&self, course_ids: &[i64]) -> Result<SearchIndexStatus> {
+        let courses = self.list_courses().await?;
+        let mut collected = vec![];
+        for course_id in course_ids {
+            let Some(course) = courses.iter().find(|course| course.id == *course_id) else {
+                return Err(AppError::CourseNotFound(*course_id));
+            };
+            match self.collect_course_documents(course).await {
+                Ok(documents) => {
+                    tracing::info!(
+                        "Indexed {} documents for course {}",
+                        documents.len(),
+                        course.name
+                    );
+                    collected.push((course.id, documents));
+                }
+                Err(e) => tracing::error!("Failed to index course {}: {}", course.name, e),
+            }
+        }
+
+        let mut guard = self.lock_search_index().await;
+        let index = guard.get_or_insert_with(SearchIndex::default);
+        for (course_id, documents) in collected {
+            index.remove_course(course_id);
+            for document in documents {
+                index.insert(document);
+            }
+            index.mark_course_indexed(course_id);
+        }
+        self.save_search_index(index).await?;
+        Ok(index.status())
+    }
+
+    // For documents which don't come from a course, they stay until replaced
+    // or the index is cleared
+    pub(super) async fn index_document(&self, mut document: SearchDocument) -> Result<()> {
+        document.content = truncate_content(document.content);
+        let mut guard = self.lock_search_index().await;
+        let index = guard.get_or_insert_with(SearchIndex::default);
+        index.insert(document);
+        self.save_search_index(index).await
+    }
+
+    pub async fn get_search_index_status(&self) -> Result<SearchIndexStatus> {
+        let guard = self.read_search_index().await;
+        Ok(guard.as_ref().map(SearchIndex::status).unwrap_or_default())
+    }
+
+    pub async fn clear_search_index(&self) -> Result<()> {
+        let mut guard = self.search_index.write().await;
+        let index = SearchIndex::default();
+        self.save_search_index(&index).await?;
+        *guard = Some(index);
+        Ok(())
+    }
+
+    pub async fn search(
+        &self,
+        query: &str,
+        filter: &SearchFilter,
+        limit: usize,
+    ) -> Result<Vec<SearchHit>> {
+        let guard = self.read_search_index().await;
+        Ok(guard
+            .as_ref()
+            .map(|index| index.search(query, filter, limit))
+            .unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn document(id: &str, kind: SearchDocumentKind, title: &str, content: &str) -> SearchDocument {
+        SearchDocument {
+            id: id.to_owned(),
+            kind,
+            course_id: 1,
+            course_name: "Course".to_owned(),
+            title: title.to_owned(),
+            content: content.to_owned(),
+            url: String::new(),
+            local_path: None,
+            date: Some("2024-03-01T00:00:00Z".to_owned()),
+        }
+    }
+
+    #[test]
+    fn test_tokenize() {
+        assert_eq!(
+            tokenize("Homework-2, due 3/15!"),
+            vec!["homework", "2", "due", "3", "15"]
+        );
+        assert_eq!(
+            tokenize("线性代数HW"),
+            vec!["线", "线性", "性", "性代", "代", "代数", "数", "hw"]
+        );
+    }
+
+    #[test]
+    fn test_search_ranking_and_filter() {
+        let mut index = SearchIndex::default();
+        index.insert(document(
+            "file:1",
+            SearchDocumentKind::File,
+            "lecture1.pdf",
+            "introduction to matrix multiplication",
+        ));
+        index.insert(document(
+            "assignment:2",
+            SearchDocumentKind::Assignment,
+            "Matrix homework",
+            "compute the matrix inverse",
+        ));
+        let mut other = document(
+            "discussion:3",
+            SearchDocumentKind::Discussion,
+            "Office hours",
+            "questions about the midterm",
+        );
+        other.course_id = 2;
+        other.date = Some("2024-05-01T00:00:00Z".to_owned());
+        index.insert(other);
+
+        let hits = index.search("matrix", &Default::default(), 10);
+        let ids: Vec<_> = hits.iter().map(|hit| hit.document.id.as_str()).collect();
+        assert_eq!(ids, vec!["assignment:2", "file:1"]);
+
+        let filter = SearchFilter {
+            kinds: vec![SearchDocumentKind::File],
+            ..Default::default()
+        };
+        let hits = index.search("matrix", &filter, 10);
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].document.id, "file:1");
+
+        let filter = SearchFilter {
+            start_date: Some("2024-04-01".to_owned()),
+            ..Default::default()
+        };
+        assert!(index.search("matrix", &filter, 10).is_empty());
+        assert_eq!(index.search("midterm", &filter, 10).len(), 1);
+
+        index.remove_course(2);
+        assert!(index.search("midterm", &Default::default(), 10).is_empty());
+        assert_eq!(index.status().document_count, 2);
+    }
+
+    #[test]
+    fn test_make_snippet() {
+        let content = format!("{}Matrix inverse{}", "a".repeat(100), "b".repeat(200));
+        let snippet = make_snippet(&content, &["matrix".to_owned()]);
+        assert!(snippet.starts_with("..."));
+        assert!(snippet.ends_with("..."));
+        assert!(snippet.contains("Matrix inverse"));
+        assert_eq!(make_snippet("short", &["none".to_owned()]), "short");
+    }
+}
@@ -0,0 +1,612 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{Mutex, OnceLock, RwLock},
+};
+
+use aes_gcm::{
+    aead::{Aead, KeyInit},
+    Aes256Gcm, Nonce,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use super::App;
+use crate::{
+    error::{AppError, Result},
+    model::{Account, AppConfig, SecretStoreStatus},
+};
+
+const KEYRING_SERVICE: &str = "SJTU-Canvas-Helper";
+const SECRETS_FILE_NAME: &str = "secrets.enc.json";
+#[cfg(test)]
+const SECRET_BACKEND_ENV: &str = "SECRET_BACKEND";
+const PBKDF2_ROUNDS: u32 = 200_000;
+// AES-GCM uses 96 bit nonces
+const NONCE_LEN: usize = 12;
+const SECRET_FIELDS: [&str; 6] = [
+    "token",
+    "ja_auth_cookie",
+    "video_cookies",
+    "jbox_access_token",
+    "canvas_client_secret",
+    "canvas_refresh_token",
+];
+
+// Credentials of one account which must never be written to the plaintext config
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Secrets {
+    pub token: String,
+    pub ja_auth_cookie: String,
+    pub video_cookies: String,
+    pub jbox_access_token: String,
+    #[serde(default)]
+    pub canvas_client_secret: String,
+    #[serde(default)]
+    pub canvas_refresh_token: String,
+}
+
+impl Secrets {
+    // Moves the secrets out of the config, leaving empty strings behind
+    pub fn take_from(config: &mut AppConfig) -> Self {
+        Self {
+            token: std::mem::take(&mut config.token),
+            ja_auth_cookie: std::mem::take(&mut config.ja_auth_cookie),
+            video_cookies: std::mem::take(&mut config.video_cookies),
+            jbox_access_token: std::mem::take(&mut config.jbox_login_info.access_token),
+            canvas_client_secret: std::mem::take(&mut config.canvas_client_secret),
+            canvas_refresh_token: std::mem::take(&mut config.canvas_refresh_token),
+        }
+    }
+
+    pub fn apply_to(&self, config: &mut AppConfig) {
+        config.token = self.token.clone();
+        config.ja_auth_cookie = self.ja_auth_cookie.clone();
+        config.video_cookies = self.video_cookies.clone();
+        config.jbox_login_info.access_token = self.jbox_access_token.clone();
+        config.canvas_client_secret = self.canvas_client_secret.clone();
+        config.canvas_refresh_token = self.canvas_refresh_token.clone();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.token.is_empty()
+            && self.ja_auth_cookie.is_empty()
+            && self.video_cookies.is_empty()
+            && self.jbox_access_token.is_empty()
+            && self.canvas_client_secret.is_empty()
+            && self.canvas_refresh_token.is_empty()
+    }
+
+    fn fields(&self) -> [(&'static str, &String); 6] {
+        [
+            (SECRET_FIELDS[0], &self.token),
+            (SECRET_FIELDS[1], &self.ja_auth_cookie),
+            (SECRET_FIELDS[2], &self.video_cookies),
+            (SECRET_FIELDS[3], &self.jbox_access_token),
+            (SECRET_FIELDS[4], &self.canvas_client_secret),
+            (SECRET_FIELDS[5], &self.canvas_refresh_token),
+        ]
+    }
+
+    fn set_field(&mut self, field: &str, value: String) {
+        match field {
+            "token" => self.token = value,
+            "ja_auth_cookie" => self.ja_auth_cookie = value,
+            "video_cookies" => self.video_cookies = value,
+            "jbox_access_token" => self.jbox_access_token = value,
+            "canvas_client_secret" => self.canvas_client_secret = value,
+            "canvas_refresh_token" => self.canvas_refresh_token = value,
+            _ => (),
+        }
+    }
+}
+
+fn account_key(account: &Account) -> String {
+    match account {
+        Account::Default => "default".to_owned(),
+        Account::Custom(name) => format!("custom_{}", name),
+    }
+}
+
+pub trait SecretBackend: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn get(&self, key: &str) -> Result<Option<String>>;
+    fn set(&self, key: &str, value: &str) -> Result<()>;
+    fn delete(&self, key: &str) -> Result<()>;
+}
+
+// The platform keyring: Keychain, Windows Credential Manager or Secret Service
+pub struct KeyringBackend;
+
+impl KeyringBackend {
+    fn entry(key: &str) -> Result<keyring::Entry> {
+        keyring::Entry::new(KEYRING_SERVICE, key).map_err(|e| AppError::SecretStore(e.to_string()))
+    }
+
+    // None if there is no usable keyring on this machine
+    fn probe() -> Option<Self> {
+        let entry = Self::entry("probe").ok()?;
+        match entry.get_password() {
+            Ok(_) | Err(keyring::Error::NoEntry) => Some(Self),
+            Err(e) => {
+                tracing::warn!("OS keyring unavailable: {}", e);
+                None
+            }
+        }
+    }
+}
+
+impl SecretBackend for KeyringBackend {
+    fn name(&self) -> &'static str {
+        "keyring"
+    }
+
+    fn get(&self, key: &str) -> Result<Option<String>> {
+        match Self::entry(key)?.get_password() {
+            Ok(value) => Ok(Some(value)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(AppError::SecretStore(e.to_string())),
+        }
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<()> {
+        Self::entry(key)?
+            .set_password(value)
+            .map_err(|e| AppError::SecretStore(e.to_string()))
+    }
+
+    fn delete(&self, key: &str) -> Result<()> {
+        match Self::entry(key)?.delete_password() {
+            Ok(_) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(AppError::SecretStore(e.to_string())),
+        }
+    }
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> [u8; 32] {
+    let mut key = [0u8; 32];
+    pbkdf2::pbkdf2_hmac::<Sha256>(passphrase.as_bytes(), salt, PBKDF2_ROUNDS, &mut key);
+    key
+}
+
+fn cipher(key: &[u8; 32]) -> Result<Aes256Gcm> {
+    Aes256Gcm::new_from_slice(key).map_err(|e| AppError::SecretStore(e.to_string()))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(super) struct EncryptedFile {
+    version: u32,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+impl EncryptedFile {
+    // A new random nonce for every write, never reuse one with the same key
+    fn seal(key: &[u8; 32], salt: &[u8], plaintext: &[u8]) -> Result<Self> {
+        let nonce = rand::random::<[u8; NONCE_LEN]>();
+        let ciphertext = cipher(key)?
+            .encrypt(Nonce::from_slice(&nonce), plaintext)
+            .map_err(|e| AppError::SecretStore(e.to_string()))?;
+        Ok(Self {
+            version: 1,
+            salt: STANDARD.encode(salt),
+            nonce: STANDARD.encode(nonce),
+            ciphertext: STANDARD.encode(ciphertext),
+        })
+    }
+
+    fn open(&self, key: &[u8; 32]) -> Result<Vec<u8>> {
+        let nonce = STANDARD.decode(&self.nonce)?;
+        // Nonce::from_slice panics on any other length
+        if nonce.len() != NONCE_LEN {
+            return Err(AppError::SecretStore(format!(
+                "invalid nonce of {} bytes",
+                nonce.len()
+            )));
+        }
+        let ciphertext = STANDARD.decode(&self.ciphertext)?;
+        cipher(key)?
+            .decrypt(Nonce::from_slice(&nonce), ciphertext.as_ref())
+            .map_err(|_| AppError::SecretStore("wrong passphrase".to_owned()))
+    }
+
+    fn salt(&self) -> Result<Vec<u8>> {
+        Ok(STANDARD.decode(&self.salt)?)
+    }
+
+    // For one-off payloads such as exported profiles
+    pub(super) fn seal_with_passphrase(passphrase: &str, plaintext: &[u8]) -> Result<Self> {
+        check_passphrase(passphrase)?;
+        let salt = rand::random::<[u8; 16]>();
+        Self::seal(&derive_key(passphrase, &salt), &salt, plaintext)
+    }
+
+    pub(super) fn open_with_passphrase(&self, passphrase: &str) -> Result<Vec<u8>> {
+        self.open(&derive_key(passphrase, &self.salt()?))
+    }
+}
+
+fn check_passphrase(passphrase: &str) -> Result<()> {
+    if passphrase.is_empty() {
+        return Err(AppError::SecretStore(
+            "passphrase must not be empty".to_owned(),
+        ));
+    }
+    Ok(())
+}
+
+// All secrets in one file, encrypted with AES-256-GCM under a key derived from a passphrase
+pub struct EncryptedFileBackend {
+    path: PathBuf,
+    key: [u8; 32],
+    salt: Vec<u8>,
+    secrets: Mutex<HashMap<String, String>>,
+}
+
+impl EncryptedFileBackend {
+    // Opens the file if it exists, otherwise starts an empty store protected by this passphrase
+    pub fn open(path: &Path, passphrase: &str) -> Result<Self> {
+        check_passphrase(passphrase)?;
+        if !path.exists() {
+            let salt = rand::random::<[u8; 16]>().to_vec();
+            return Ok(Self {
+                path: path.to_owned(),
+                key: derive_key(passphrase, &salt),
+                salt,
+                secrets: Default::default(),
+            });
+        }
+
+        let file: EncryptedFile = serde_json::from_slice(&fs::read(path)?)?;
+        let salt = file.salt()?;
+        let key = derive_key(passphrase, &salt);
+        let plaintext = file.open(&key)?;
+        Ok(Self {
+            path: path.to_owned(),
+            key,
+            salt,
+            secrets: Mutex::new(serde_json::from_slice(&plaintext)?),
+        })
+    }
+
+    fn persist(&self, secrets: &HashMap<String, String>) -> Result<()> {
+        let file = EncryptedFile::seal(&self.key, &self.salt, &serde_json::to_vec(secrets)?)?;
+        fs::write(&self.path, serde_json::to_vec(&file)?)?;
+        Ok(())
+    }
+}
+
+impl SecretBackend for EncryptedFileBackend {
+    fn name(&self) -> &'static str {
+        "encrypted_file"
+    }
+
+    fn get(&self, key: &str) -> Result<Option<String>> {
+        Ok(self
+            .secrets
+            .lock()
+            .map_err(|_| AppError::MutexError)?
+            .get(key)
+            .cloned())
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<()> {
+        let mut secrets = self.secrets.lock().map_err(|_| AppError::MutexError)?;
+        secrets.insert(key.to_owned(), value.to_owned());
+        self.persist(&secrets)
+    }
+
+    fn delete(&self, key: &str) -> Result<()> {
+        let mut secrets = self.secrets.lock().map_err(|_| AppError::MutexError)?;
+        if secrets.remove(key).is_some() {
+            self.persist(&secrets)?;
+        }
+        Ok(())
+    }
+}
+
+// Keeps secrets in memory only, for tests
+#[cfg(test)]
+#[derive(Default)]
+pub struct MemoryBackend {
+    secrets: Mutex<HashMap<String, String>>,
+}
+
+#[cfg(test)]
+impl SecretBackend for MemoryBackend {
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+
+    fn get(&self, key: &str) -> Result<Option<String>> {
+        Ok(self
+            .secrets
+            .lock()
+            .map_err(|_| AppError::MutexError)?
+            .get(key)
+            .cloned())
+    }
+
+    fn set(&self, key: &str, value: &str) -> Result<()> {
+        self.secrets
+            .lock()
+            .map_err(|_| AppError::MutexError)?
+            .insert(key.to_owned(), value.to_owned());
+        Ok(())
+    }
+
+    fn delete(&self, key: &str) -> Result<()> {
+        self.secrets
+            .lock()
+            .map_err(|_| AppError::MutexError)?
+            .remove(key);
+        Ok(())
+    }
+}
+
+// Without a backend the store is locked: the keyring is unavailable and the
+// user hasn't entered the passphrase of the encrypted file yet. Secrets saved
+// meanwhile are only kept in memory, by account, until it's unlocked.
+#[derive(Default)]
+pub struct SecretStore {
+    backend: RwLock<Option<Box<dyn SecretBackend>>>,
+    pending: Mutex<HashMap<String, Secrets>>,
+}
+
+fn write_secrets(backend: &dyn SecretBackend, key: &str, secrets: &Secrets) -> Result<()> {
+    for (field, value) in secrets.fields() {
+        let entry_key = format!("{}:{}", key, field);
+        if value.is_empty() {
+            backend.delete(&entry_key)?;
+        } else {
+            backend.set(&entry_key, value)?;
+        }
+    }
+    Ok(())
+}
+
+impl SecretStore {
+    pub fn with_backend(backend: Box<dyn SecretBackend>) -> Self {
+        Self {
+            backend: RwLock::new(Some(backend)),
+            pending: Default::default(),
+        }
+    }
+
+    // SECRET_BACKEND=memory keeps test runs away from the real keyring
+    fn detect() -> Self {
+        #[cfg(test)]
+        if std::env::var(SECRET_BACKEND_ENV).is_ok_and(|backend| backend == "memory") {
+            return Self::with_backend(Box::<MemoryBackend>::default());
+        }
+        match KeyringBackend::probe() {
+            Some(backend) => Self::with_backend(Box::new(backend)),
+            None => Default::default(),
+        }
+    }
+
+    pub fn status(&self) -> SecretStoreStatus {
+        let backend = self.backend.read().unwrap();
+        SecretStoreStatus {
+            backend: backend.as_ref().map(|backend| backend.name().to_owned()),
+            unlocked: backend.is_some(),
+            encrypted_file_exists: App::secrets_file_path().exists(),
+            unsaved_secrets: self.pending.lock().is_ok_and(|pending| !pending.is_empty()),
+        }
+    }
+
+    // Unlocks the store, writing the secrets kept in memory into the backend
+    pub fn set_backend(&self, backend: Box<dyn SecretBackend>) -> Result<()> {
+        // locked in the same order as `save`
+        let mut guard = self.backend.write().unwrap();
+        let mut pending = self.pending.lock().map_err(|_| AppError::MutexError)?;
+        for (key, secrets) in pending.iter() {
+            write_secrets(backend.as_ref(), key, secrets)?;
+        }
+        pending.clear();
+        *guard = Some(backend);
+        Ok(())
+    }
+
+    // While the store is locked only secrets saved since start are known
+    pub fn load(&self, account: &Account) -> Result<Option<Secrets>> {
+        let key = account_key(account);
+        let guard = self.backend.read().unwrap();
+        let Some(backend) = guard.as_ref() else {
+            let pending = self.pending.lock().map_err(|_| AppError::MutexError)?;
+            return Ok(pending.get(&key).cloned());
+        };
+        let mut secrets = Secrets::default();
+        for field in SECRET_FIELDS {
+            if let Some(value) = backend.get(&format!("{}:{}", key, field))? {
+                secrets.set_field(field, value);
+            }
+        }
+        Ok(Some(secrets))
+    }
+
+    // Returns false if the store is locked and the secrets are only kept in
+    // memory until it's unlocked
+    pub fn save(&self, account: &Account, secrets: &Secrets) -> Result<bool> {
+        let key = account_key(account);
+        let guard = self.backend.read().unwrap();
+        let Some(backend) = guard.as_ref() else {
+            let mut pending = self.pending.lock().map_err(|_| AppError::MutexError)?;
+            if secrets.is_empty() {
+                pending.remove(&key);
+            } else {
+                pending.insert(key, secrets.clone());
+            }
+            return Ok(false);
+        };
+        write_secrets(backend.as_ref(), &key, secrets)?;
+        Ok(true)
+    }
+
+    pub fn delete(&self, account: &Account) -> Result<()> {
+        self.save(account, &Secrets::default())?;
+        Ok(())
+    }
+}
+
+// Shared by every loaded account session
+pub fn secret_store() -> &'static SecretStore {
+    static STORE: OnceLock<SecretStore> = OnceLock::new();
+    STORE.get_or_init(SecretStore::detect)
+}
+
+// Splits the config read from disk into the config to keep in memory and whether
+// the file still holds plaintext secrets that should be moved into the store
+pub fn resolve_secrets(
+    store: &SecretStore,
+    account: &Account,
+    mut config: AppConfig,
+) -> Result<(AppConfig, bool)> {
+    let plaintext = Secrets::take_from(&mut config);
+    match store.load(account)? {
+        // plaintext secrets only remain in the file if they were never migrated,
+        // or were edited by hand, so they win over stored ones
+        Some(stored) => {
+            let secrets = if plaintext.is_empty() {
+                stored
+            } else {
+                plaintext.clone()
+            };
+            secrets.apply_to(&mut config);
+            Ok((config, !plaintext.is_empty()))
+        }
+        None => {
+            plaintext.apply_to(&mut config);
+            Ok((config, false))
+        }
+    }
+}
+
+impl App {
+    pub(crate) fn secrets_file_path() -> PathBuf {
+        Path::new(&App::config_dir().unwrap()).join(SECRETS_FILE_NAME)
+    }
+
+    pub fn get_secret_store_status(&self) -> SecretStoreStatus {
+        secret_store().status()
+    }
+
+    // Use the passphrase-encrypted file when no keyring is available, creating it
+    // on first use. Secrets kept in memory while locked and plaintext secrets of
+    // the current account are moved into it.
+    pub async fn unlock_secret_store(&self, passphrase: &str) -> Result<SecretStoreStatus> {
+        let backend = EncryptedFileBackend::open(&App::secrets_file_path(), passphrase)?;
+        secret_store().set_backend(Box::new(backend))?;
+        self.reload_config().await?;
+        Ok(secret_store().status())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config_with_secrets() -> AppConfig {
+        let mut config = AppConfig {
+            token: "canvas-token".to_owned(),
+            video_cookies: "JSESSIONID=1".to_owned(),
+            save_path: "/tmp".to_owned(),
+            ..Default::default()
+        };
+        config.jbox_login_info.access_token = "jbox-token".to_owned();
+        config
+    }
+
+    #[test]
+    fn test_take_and_apply_secrets() {
+        let mut config = config_with_secrets();
+        let secrets = Secrets::take_from(&mut config);
+        assert!(config.token.is_empty() && config.jbox_login_info.access_token.is_empty());
+        assert_eq!(config.save_path, "/tmp");
+        assert_eq!(secrets.token, "canvas-token");
+        secrets.apply_to(&mut config);
+        assert_eq!(config, config_with_secrets());
+    }
+
+    #[test]
+    fn test_resolve_secrets_migration() -> Result<()> {
+        let store = SecretStore::with_backend(Box::new(MemoryBackend::default()));
+        let account = Account::Custom("ta".to_owned());
+
+        // plaintext config is kept in memory and flagged for migration
+        let (config, migrate) = resolve_secrets(&store, &account, config_with_secrets())?;
+        assert!(migrate);
+        assert_eq!(config, config_with_secrets());
+
+        let mut stripped = config.clone();
+        assert!(store.save(&account, &Secrets::take_from(&mut stripped))?);
+        let (config, migrate) = resolve_secrets(&store, &account, stripped.clone())?;
+        assert!(!migrate);
+        assert_eq!(config, config_with_secrets());
+
+        // other accounts don't see these secrets
+        let (config, _) = resolve_secrets(&store, &Account::Default, stripped)?;
+        assert!(config.token.is_empty());
+
+        // a locked store leaves plaintext configs alone
+        let (config, migrate) =
+            resolve_secrets(&SecretStore::default(), &account, config_with_secrets())?;
+        assert!(!migrate);
+        assert_eq!(config.token, "canvas-token");
+        Ok(())
+    }
+
+    #[test]
+    fn test_locked_store_keeps_secrets_in_memory() -> Result<()> {
+        let store = SecretStore::default();
+        let account = Account::Custom("ta".to_owned());
+        let mut stripped = config_with_secrets();
+        let secrets = Secrets::take_from(&mut stripped);
+        assert!(!store.save(&account, &secrets)?);
+        assert!(store.status().unsaved_secrets);
+        let (config, _) = resolve_secrets(&store, &account, stripped.clone())?;
+        assert_eq!(config, config_with_secrets());
+
+        // unlocking moves them into the backend
+        store.set_backend(Box::<MemoryBackend>::default())?;
+        assert!(!store.status().unsaved_secrets);
+        assert_eq!(store.load(&account)?, Some(secrets));
+        Ok(())
+    }
+
+    #[test]
+    fn test_encrypted_file_backend() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("secrets_{}.json", uuid::Uuid::new_v4()));
+        let backend = EncryptedFileBackend::open(&path, "correct horse")?;
+        backend.set("default:token", "canvas-token")?;
+        let content = fs::read_to_string(&path)?;
+        assert!(!content.contains("canvas-token"));
+
+        let reopened = EncryptedFileBackend::open(&path, "correct horse")?;
+        assert_eq!(
+            reopened.get("default:token")?.as_deref(),
+            Some("canvas-token")
+        );
+        assert!(EncryptedFileBackend::open(&path, "wrong").is_err());
+        fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_malformed_encrypted_file() -> Result<()> {
+        let mut file = EncryptedFile::seal_with_passphrase("correct horse", b"{}")?;
+        assert_eq!(file.open_with_passphrase("correct horse")?, b"{}");
+
+        file.nonce = STANDARD.encode([0u8; 4]);
+        assert!(matches!(
+            file.open_with_passphrase("correct horse"),
+            Err(AppError::SecretStore(_))
+        ));
+        file.nonce = "not base64!".to_owned();
+        assert!(file.open_with_passphrase("correct horse").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,132 @@
+use std::collections::HashSet;
+
+use super::App;
+use crate::{
+    error::Result,
+    model::{AssignmentOverride, Enrollment, Section, Submission, User},
+};
+
+// Students of the chosen sections, each student only once even if enrolled in several
+fn collect_section_students(sections: &[Section], section_ids: &[i64]) -> Vec<User> {
+    let mut seen = HashSet::new();
+    let mut students = vec![];
+    for section in sections {
+        if !section_ids.contains(&section.id) {
+            continue;
+        }
+        for student in section.students.iter().flatten() {
+            if seen.insert(student.id) {
+                students.push(student.clone());
+            }
+        }
+    }
+    students
+}
+
+impl App {
+    pub async fn list_course_sections(&self, course_id: i64) -> Result<Vec<Section>> {
+        let token = self.config.read().await.token.clone();
+        self.client.list_course_sections(course_id, &token).await
+    }
+
+    pub async fn list_section_enrollments(&self, section_id: i64) -> Result<Vec<Enrollment>> {
+        let token = self.config.read().await.token.clone();
+        self.client
+            .list_section_enrollments(section_id, &token)
+            .await
+    }
+
+    pub async fn list_section_students(
+        &self,
+        course_id: i64,
+        section_ids: &[i64],
+    ) -> Result<Vec<User>> {
+        let sections = self.list_course_sections(course_id).await?;
+        Ok(collect_section_students(&sections, section_ids))
+    }
+
+    pub async fn list_section_assignment_submissions(
+        &self,
+        section_ids: &[i64],
+        assignment_id: i64,
+    ) -> Result<Vec<Submission>> {
+        let token = self.config.read().await.token.clone();
+        let mut seen = HashSet::new();
+        let mut submissions = vec![];
+        for section_id in section_ids {
+            let section_submissions = self
+                .client
+                .list_section_assignment_submissions(*section_id, assignment_id, &token)
+                .await?;
+            submissions.extend(
+                section_submissions
+                    .into_iter()
+                    .filter(|submission| seen.insert(submission.id)),
+            );
+        }
+        Ok(submissions)
+    }
+
+    pub async fn add_assignment_section_override(
+        &self,
+        course_id: i64,
+        assignment_id: i64,
+        section_id: i64,
+        due_at: Option<&str>,
+        unlock_at: Option<&str>,
+        lock_at: Option<&str>,
+    ) -> Result<AssignmentOverride> {
+        let token = self.config.read().await.token.clone();
+        self.client
+            .add_assignment_section_override(
+                course_id,
+                assignment_id,
+                section_id,
+                due_at,
+                unlock_at,
+                lock_at,
+                &token,
+            )
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn student(id: i64) -> User {
+        User {
+            id,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_collect_section_students() {
+        let sections = vec![
+            Section {
+                id: 1,
+                students: Some(vec![student(10), student(11)]),
+                ..Default::default()
+            },
+            Section {
+                id: 2,
+                students: Some(vec![student(11), student(12)]),
+                ..Default::default()
+            },
+            Section {
+                id: 3,
+                students: None,
+                ..Default::default()
+            },
+        ];
+        let ids = |students: Vec<User>| students.iter().map(|s| s.id).collect::<Vec<_>>();
+        assert_eq!(
+            ids(collect_section_students(&sections, &[1, 2, 3])),
+            vec![10, 11, 12]
+        );
+        assert_eq!(ids(collect_section_students(&sections, &[2])), vec![11, 12]);
+        assert!(collect_section_students(&sections, &[3]).is_empty());
+    }
+}
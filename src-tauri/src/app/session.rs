@@ -0,0 +1,84 @@
+use std::{collections::HashMap, sync::Arc};
+
+use tokio::sync::RwLock;
+
+use super::{auth::ReloginHandler, App};
+use crate::{
+    error::Result,
+    model::{Account, AccountCourse, AccountError, MergedCourses},
+};
+
+// Accounts kept loaded next to the selected one, so e.g. a student account and
+// a TA account can be used at the same time
+#[derive(Default)]
+pub struct Sessions {
+    sessions: RwLock<HashMap<Account, Arc<App>>>,
+    relogin_handler: std::sync::RwLock<Option<ReloginHandler>>,
+}
+
+impl Sessions {
+    // Installed on every session loaded afterwards
+    pub fn set_relogin_handler(&self, handler: ReloginHandler) {
+        *self.relogin_handler.write().unwrap() = Some(handler);
+    }
+
+    pub async fn get_or_load(&self, account: &Account) -> Result<Arc<App>> {
+        if let Some(app) = self.sessions.read().await.get(account) {
+            return Ok(app.clone());
+        }
+        let mut sessions = self.sessions.write().await;
+        // another command may have loaded it while we waited for the lock
+        if let Some(app) = sessions.get(account) {
+            return Ok(app.clone());
+        }
+        let app = Arc::new(App::with_account(account).await?);
+        if let Some(handler) = self.relogin_handler.read().unwrap().clone() {
+            app.watch_auth(handler);
+        }
+        tracing::info!("Loaded session for account {:?}", account);
+        sessions.insert(account.clone(), app.clone());
+        Ok(app)
+    }
+
+    pub async fn unload(&self, account: &Account) -> bool {
+        self.sessions.write().await.remove(account).is_some()
+    }
+
+    pub async fn loaded_accounts(&self) -> Vec<Account> {
+        self.sessions.read().await.keys().cloned().collect()
+    }
+
+    // Courses of the selected app and every loaded session, tagged with their account.
+    // An account that fails is reported instead of hiding the others' courses.
+    pub async fn list_merged_courses(&self, current: &Arc<App>) -> MergedCourses {
+        let current_account = current.get_current_account().await;
+        let mut apps = vec![(current_account.clone(), current.clone())];
+        for (account, app) in self.sessions.read().await.iter() {
+            if *account != current_account {
+                apps.push((account.clone(), app.clone()));
+            }
+        }
+
+        let mut merged = MergedCourses::default();
+        for (account, app) in apps {
+            match app.list_courses().await {
+                Ok(courses) => {
+                    merged
+                        .courses
+                        .extend(courses.into_iter().map(|course| AccountCourse {
+                            account: account.clone(),
+                            course,
+                        }))
+                }
+                Err(e) => {
+                    tracing::error!("Failed to list courses of {:?}: {}", account, e);
+                    merged.errors.push(AccountError {
+                        account,
+                        error: e.to_string(),
+                    });
+                }
+            }
+        }
+        merged
+    }
+}
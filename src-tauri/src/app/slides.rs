@@ -0,0 +1,387 @@
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use image::{codecs::jpeg::JpegEncoder, imageops::FilterType, ColorType, GrayImage};
+
+use super::App;
+use crate::{
+    error::{AppError, Result},
+    model::{FfmpegJobKind, FfmpegJobParams, Slide, SlideExtractParams, SlideExtractResult},
+    utils::sanitize_file_name,
+};
+
+// Frames are compared on small grayscale thumbnails, which ignores encoding
+// noise and the mouse cursor
+const THUMBNAIL_WIDTH: u32 = 64;
+const THUMBNAIL_HEIGHT: u32 = 36;
+const PDF_PAGE_WIDTH: f64 = 960.0;
+const PDF_CAPTION_HEIGHT: f64 = 36.0;
+
+fn thumbnail(path: &Path) -> Result<GrayImage> {
+    let image = image::open(path)?;
+    Ok(image
+        .resize_exact(THUMBNAIL_WIDTH, THUMBNAIL_HEIGHT, FilterType::Triangle)
+        .to_luma8())
+}
+
+// Mean absolute difference of two thumbnails, 0 ~ 1
+fn frame_difference(a: &GrayImage, b: &GrayImage) -> f64 {
+    let total: u64 = a
+        .as_raw()
+        .iter()
+        .zip(b.as_raw())
+        .map(|(x, y)| x.abs_diff(*y) as u64)
+        .sum();
+    total as f64 / (a.as_raw().len().max(1) as f64 * 255.0)
+}
+
+// Picks the frames starting a new slide. A frame only counts when the next
+// one looks the same, so slide transitions and animations are skipped.
+fn pick_slides(frames: &[GrayImage], threshold: f64) -> Vec<usize> {
+    let mut picked: Vec<usize> = vec![];
+    for (i, frame) in frames.iter().enumerate() {
+        let is_new = match picked.last() {
+            Some(&last) => frame_difference(&frames[last], frame) > threshold,
+            None => true,
+        };
+        let is_stable = frames
+            .get(i + 1)
+            .is_none_or(|next| frame_difference(frame, next) <= threshold);
+        if is_new && (is_stable || picked.is_empty()) {
+            picked.push(i);
+        }
+    }
+    picked
+}
+
+fn format_timestamp(seconds: f64, separator: char) -> String {
+    let seconds = seconds as u64;
+    format!(
+        "{:02}{}{:02}{}{:02}",
+        seconds / 3600,
+        separator,
+        seconds / 60 % 60,
+        separator,
+        seconds % 60
+    )
+}
+
+fn escape_pdf_text(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('(', "\\(")
+        .replace(')', "\\)")
+}
+
+struct PdfPage {
+    jpeg: Vec<u8>,
+    width: u32,
+    height: u32,
+    caption: String,
+}
+
+// A minimal PDF with one page per slide, the slide is embedded as a JPEG and
+// captioned with its timestamp in Helvetica
+fn build_pdf(pages: &[PdfPage]) -> Vec<u8> {
+    let mut pdf = b"%PDF-1.4\n".to_vec();
+    let mut offsets = vec![];
+    let mut add_object = |pdf: &mut Vec<u8>, id: usize, body: &[u8]| {
+        offsets.push((id, pdf.len()));
+        pdf.extend(format!("{} 0 obj\n", id).as_bytes());
+        pdf.extend(body);
+        pdf.extend(b"\nendobj\n");
+    };
+
+    // 1: catalog, 2: pages, 3: font, then page, content and image for each slide
+    let page_ids: Vec<usize> = (0..pages.len()).map(|i| 4 + i * 3).collect();
+    add_object(&mut pdf, 1, b"<< /Type /Catalog /Pages 2 0 R >>");
+    let kids = page_ids
+        .iter()
+        .map(|id| format!("{} 0 R", id))
+        .collect::<Vec<_>>()
+        .join(" ");
+    add_object(
+        &mut pdf,
+        2,
+        format!("<< /Type /Pages /Kids [{}] /Count {} >>", kids, pages.len()).as_bytes(),
+    );
+    add_object(
+        &mut pdf,
+        3,
+        b"<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica >>",
+    );
+    for (page, page_id) in pages.iter().zip(page_ids) {
+        let image_height = PDF_PAGE_WIDTH * page.height as f64 / page.width.max(1) as f64;
+        let page_height = image_height + PDF_CAPTION_HEIGHT;
+        add_object(
+            &mut pdf,
+            page_id,
+            format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {:.2}] /Contents {} 0 R \
+                 /Resources << /Font << /F1 3 0 R >> /XObject << /Im0 {} 0 R >> >> >>",
+                PDF_PAGE_WIDTH,
+                page_height,
+                page_id + 1,
+                page_id + 2
+            )
+            .as_bytes(),
+        );
+        let content = format!(
+            "q {} 0 0 {:.2} 0 {} cm /Im0 Do Q BT /F1 14 Tf 16 12 Td ({}) Tj ET",
+            PDF_PAGE_WIDTH,
+            image_height,
+            PDF_CAPTION_HEIGHT,
+            escape_pdf_text(&page.caption)
+        );
+        add_object(
+            &mut pdf,
+            page_id + 1,
+            format!(
+                "<< /Length {} >>\nstream\n{}\nendstream",
+                content.len(),
+                content
+            )
+            .as_bytes(),
+        );
+        let mut image = format!(
+            "<< /Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace /DeviceRGB \
+             /BitsPerComponent 8 /Filter /DCTDecode /Length {} >>\nstream\n",
+            page.width,
+            page.height,
+            page.jpeg.len()
+        )
+        .into_bytes();
+        image.extend(&page.jpeg);
+        image.extend(b"\nendstream");
+        add_object(&mut pdf, page_id + 2, &image);
+    }
+
+    offsets.sort();
+    let xref_offset = pdf.len();
+    pdf.extend(format!("xref\n0 {}\n0000000000 65535 f \n", offsets.len() + 1).as_bytes());
+    for (_, offset) in &offsets {
+        pdf.extend(format!("{:010} 00000 n \n", offset).as_bytes());
+    }
+    pdf.extend(
+        format!(
+            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+            offsets.len() + 1,
+            xref_offset
+        )
+        .as_bytes(),
+    );
+    pdf
+}
+
+fn encode_jpeg(path: &Path) -> Result<PdfPage> {
+    let image = image::open(path)?.to_rgb8();
+    let mut jpeg = vec![];
+    JpegEncoder::new_with_quality(&mut jpeg, 85).encode(
+        image.as_raw(),
+        image.width(),
+        image.height(),
+        ColorType::Rgb8,
+    )?;
+    Ok(PdfPage {
+        jpeg,
+        width: image.width(),
+        height: image.height(),
+        caption: String::new(),
+    })
+}
+
+// Runs on a blocking thread, decoding every sampled frame takes a while
+fn export_slides(
+    frames_dir: &Path,
+    output_dir: &Path,
+    pdf_name: &str,
+    interval: f64,
+    threshold: f64,
+) -> Result<SlideExtractResult> {
+    let mut frame_paths: Vec<PathBuf> = fs::read_dir(frames_dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "png"))
+        .collect();
+    // ffmpeg numbers the frames with zero padding
+    frame_paths.sort();
+    if frame_paths.is_empty() {
+        return Err(AppError::SlideExtraction(
+            "no frames could be sampled from the video".to_owned(),
+        ));
+    }
+    let thumbnails = frame_paths
+        .iter()
+        .map(|path| thumbnail(path))
+        .collect::<Result<Vec<_>>>()?;
+
+    fs::create_dir_all(output_dir)?;
+    let mut slides = vec![];
+    let mut pages = vec![];
+    for (index, frame) in pick_slides(&thumbnails, threshold).into_iter().enumerate() {
+        // fps=1/interval puts the first frame at the start
+        let timestamp = frame as f64 * interval;
+        let image_path = output_dir.join(format!(
+            "slide_{:03}_{}.png",
+            index + 1,
+            format_timestamp(timestamp, '-')
+        ));
+        fs::copy(&frame_paths[frame], &image_path)?;
+        let mut page = encode_jpeg(&frame_paths[frame])?;
+        page.caption = format!(
+            "Slide {}    {}",
+            index + 1,
+            format_timestamp(timestamp, ':')
+        );
+        pages.push(page);
+        slides.push(Slide {
+            index: index + 1,
+            timestamp,
+            image_path: image_path.to_string_lossy().into_owned(),
+        });
+    }
+
+    let pdf_path = output_dir.join(pdf_name);
+    fs::File::create(&pdf_path)?.write_all(&build_pdf(&pages))?;
+    Ok(SlideExtractResult {
+        output_dir: output_dir.to_string_lossy().into_owned(),
+        pdf_path: pdf_path.to_string_lossy().into_owned(),
+        slides,
+        sampled_frames: frame_paths.len(),
+    })
+}
+
+impl App {
+    // Samples the screen channel of a recording and keeps a frame whenever the
+    // slide changes. The slides are saved as PNGs and collected into one PDF.
+    pub async fn extract_slides(&self, params: &SlideExtractParams) -> Result<SlideExtractResult> {
+        let video_path = Path::new(&params.video_path);
+        if !video_path.is_file() {
+            return Err(AppError::SlideExtraction(format!(
+                "{} is not a downloaded video",
+                params.video_path
+            )));
+        }
+        if !(0.0..=1.0).contains(&params.threshold) {
+            return Err(AppError::SlideExtraction(format!(
+                "unsupported threshold {}",
+                params.threshold
+            )));
+        }
+        let video_name = video_path
+            .file_stem()
+            .map(|stem| sanitize_file_name(&stem.to_string_lossy()))
+            .unwrap_or_default();
+        let output_dir = if params.output_dir.is_empty() {
+            video_path.with_file_name(format!("{}_slides", video_name))
+        } else {
+            PathBuf::from(&params.output_dir)
+        };
+
+        let frames_dir = std::env::temp_dir().join(format!("slides_{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&frames_dir)?;
+        let result = self
+            .sample_frames(&params.video_path, &frames_dir, params.interval)
+            .await;
+        let result = match result {
+            Ok(()) => {
+                let frames_dir = frames_dir.clone();
+                let pdf_name = format!("{}.pdf", video_name);
+                let (interval, threshold) = (params.interval, params.threshold);
+                tokio::task::spawn_blocking(move || {
+                    export_slides(&frames_dir, &output_dir, &pdf_name, interval, threshold)
+                })
+                .await
+                .map_err(AppError::from)
+                .and_then(|result| result)
+            }
+            Err(e) => Err(e),
+        };
+        let _ = fs::remove_dir_all(&frames_dir);
+        let result = result?;
+        tracing::info!(
+            "Extracted {} slides from {} sampled frames of {}",
+            result.slides.len(),
+            result.sampled_frames,
+            params.video_path
+        );
+        Ok(result)
+    }
+
+    async fn sample_frames(
+        &self,
+        video_path: &str,
+        frames_dir: &Path,
+        interval: f64,
+    ) -> Result<()> {
+        let (id, cancel_token) = self.create_ffmpeg_job(FfmpegJobParams {
+            kind: FfmpegJobKind::SampleFrames {
+                input_path: video_path.to_owned(),
+                interval,
+            },
+            output_path: frames_dir.join("%06d.png").to_string_lossy().into_owned(),
+        })?;
+        let exit_code = self
+            .execute_ffmpeg_job(&id, cancel_token, |_| {}, |_| {})
+            .await?;
+        if exit_code != 0 {
+            return Err(AppError::FfmpegError(format!(
+                "failed to sample frames of {}",
+                video_path
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(value: u8) -> GrayImage {
+        GrayImage::from_pixel(THUMBNAIL_WIDTH, THUMBNAIL_HEIGHT, image::Luma([value]))
+    }
+
+    #[test]
+    fn test_pick_slides() {
+        assert_eq!(frame_difference(&frame(0), &frame(255)), 1.0);
+        assert_eq!(frame_difference(&frame(10), &frame(10)), 0.0);
+
+        // frame 2 is caught in the transition to the second slide
+        let frames = [
+            frame(0),
+            frame(2),
+            frame(100),
+            frame(200),
+            frame(201),
+            frame(200),
+            frame(30),
+        ];
+        assert_eq!(pick_slides(&frames, 0.05), [0, 3, 6]);
+        assert!(pick_slides(&[], 0.05).is_empty());
+    }
+
+    #[test]
+    fn test_build_pdf() {
+        assert_eq!(format_timestamp(3725.5, ':'), "01:02:05");
+        let pages = [PdfPage {
+            jpeg: vec![0xff, 0xd8, 0xff, 0xd9],
+            width: 1920,
+            height: 1080,
+            caption: "Slide 1 (intro)".to_owned(),
+        }];
+        let pdf = String::from_utf8_lossy(&build_pdf(&pages)).into_owned();
+        assert!(pdf.starts_with("%PDF-1.4\n"));
+        assert!(pdf.contains("/MediaBox [0 0 960 576.00]"));
+        assert!(pdf.contains("(Slide 1 \\(intro\\)) Tj"));
+        assert!(pdf.ends_with("%%EOF\n"));
+
+        // every xref entry points at its object
+        let xref = pdf.rfind("xref\n").unwrap();
+        for (id, line) in pdf[xref..].lines().skip(3).take(6).enumerate() {
+            let offset: usize = line[..10].parse().unwrap();
+            assert!(pdf[offset..].starts_with(&format!("{} 0 obj", id + 1)));
+        }
+    }
+}
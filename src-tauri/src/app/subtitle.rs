@@ -0,0 +1,363 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Stdio,
+    time::Duration,
+};
+
+use tokio::process::Command;
+use tokio_util::sync::CancellationToken;
+
+use super::{ffmpeg::probe_duration, App};
+use crate::{
+    error::{AppError, Result},
+    model::{
+        FfmpegJobKind, FfmpegJobParams, SearchDocument, SearchDocumentKind, SubtitleParams,
+        SubtitleResult,
+    },
+};
+
+// Local engines run slower than real time on weak machines, a run taking
+// longer than this many times the audio is considered stuck
+const STT_TIMEOUT_FACTOR: f64 = 4.0;
+const STT_MIN_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Debug, Clone, PartialEq)]
+struct SubtitleCue {
+    start_ms: u64,
+    end_ms: u64,
+    text: String,
+}
+
+// `00:01:02,500` in srt, `00:01:02.500` in vtt, hours may be left out in vtt
+fn parse_cue_time(time: &str) -> Option<u64> {
+    let (clock, millis) = time.trim().split_once([',', '.'])?;
+    let parts: Vec<u64> = clock
+        .split(':')
+        .map(|part| part.parse().ok())
+        .collect::<Option<_>>()?;
+    let seconds = match parts[..] {
+        [hours, minutes, seconds] => hours * 3600 + minutes * 60 + seconds,
+        [minutes, seconds] => minutes * 60 + seconds,
+        _ => return None,
+    };
+    Some(seconds * 1000 + millis.parse::<u64>().ok()?)
+}
+
+fn format_cue_time(ms: u64, separator: char) -> String {
+    format!(
+        "{:02}:{:02}:{:02}{}{:03}",
+        ms / 3_600_000,
+        ms / 60_000 % 60,
+        ms / 1000 % 60,
+        separator,
+        ms % 1000
+    )
+}
+
+// Reads the cues of an srt file, the numbering is ignored
+fn parse_srt(content: &str) -> Vec<SubtitleCue> {
+    let content = content.trim_start_matches('\u{feff}').replace("\r\n", "\n");
+    content
+        .split("\n\n")
+        .filter_map(|block| {
+            let mut lines = block.lines().skip_while(|line| !line.contains("-->"));
+            let (start, end) = lines.next()?.split_once("-->")?;
+            // vtt style settings may follow the end time
+            let end = end.split_whitespace().next()?;
+            let text = lines.collect::<Vec<_>>().join("\n");
+            Some(SubtitleCue {
+                start_ms: parse_cue_time(start)?,
+                end_ms: parse_cue_time(end)?,
+                text: text.trim().to_owned(),
+            })
+        })
+        .filter(|cue| !cue.text.is_empty())
+        .collect()
+}
+
+fn to_srt(cues: &[SubtitleCue]) -> String {
+    cues.iter()
+        .enumerate()
+        .map(|(i, cue)| {
+            format!(
+                "{}\n{} --> {}\n{}\n",
+                i + 1,
+                format_cue_time(cue.start_ms, ','),
+                format_cue_time(cue.end_ms, ','),
+                cue.text
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn to_vtt(cues: &[SubtitleCue]) -> String {
+    let mut vtt = "WEBVTT\n".to_owned();
+    for cue in cues {
+        vtt += &format!(
+            "\n{} --> {}\n{}\n",
+            format_cue_time(cue.start_ms, '.'),
+            format_cue_time(cue.end_ms, '.'),
+            cue.text
+        );
+    }
+    vtt
+}
+
+// Splits the configured command into program and arguments, double quotes
+// group words so paths with spaces can be given
+fn build_stt_command(template: &str, input: &str, output: &str) -> Vec<String> {
+    let mut args = vec![];
+    let mut current = String::new();
+    let mut in_quotes = false;
+    let mut has_word = false;
+    for c in template.chars() {
+        match c {
+            '"' => {
+                in_quotes = !in_quotes;
+                has_word = true;
+            }
+            c if c.is_whitespace() && !in_quotes => {
+                if has_word {
+                    args.push(std::mem::take(&mut current));
+                    has_word = false;
+                }
+            }
+            c => {
+                current.push(c);
+                has_word = true;
+            }
+        }
+    }
+    if has_word {
+        args.push(current);
+    }
+    args.into_iter()
+        .map(|arg| arg.replace("{input}", input).replace("{output}", output))
+        .collect()
+}
+
+impl App {
+    // Runs under the same limit as ffmpeg jobs. The engine is killed once the
+    // token is cancelled or it takes far longer than the audio lasts.
+    async fn run_stt_command(
+        &self,
+        audio_path: &str,
+        output_base: &str,
+        cancel_token: &CancellationToken,
+    ) -> Result<()> {
+        let template = self.config.read().await.stt_command.clone();
+        let args = build_stt_command(&template, audio_path, output_base);
+        let Some((program, args)) = args.split_first() else {
+            return Err(AppError::SpeechToText(
+                "speech-to-text command is not configured".to_owned(),
+            ));
+        };
+        let timeout = probe_duration(audio_path)
+            .await
+            .map(|duration| Duration::from_secs_f64(duration * STT_TIMEOUT_FACTOR))
+            .unwrap_or_default()
+            .max(STT_MIN_TIMEOUT);
+        let _permit = self.acquire_ffmpeg_permit(cancel_token).await?;
+        tracing::info!("Running {} {:?}", program, args);
+        let command = Command::new(program)
+            .args(args)
+            .stdin(Stdio::null())
+            .kill_on_drop(true)
+            .output();
+        let output = tokio::select! {
+            _ = cancel_token.cancelled() => return Err(AppError::FfmpegCancelled),
+            output = tokio::time::timeout(timeout, command) => output
+                .map_err(|_| AppError::SpeechToText(format!(
+                    "{} didn't finish within {} minutes",
+                    program,
+                    timeout.as_secs() / 60
+                )))?
+                .map_err(|e| AppError::SpeechToText(format!("failed to run {}: {}", program, e)))?,
+        };
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let last_line = stderr.lines().last().unwrap_or_default();
+            return Err(AppError::SpeechToText(format!(
+                "{} exited with {}: {}",
+                program, output.status, last_line
+            )));
+        }
+        Ok(())
+    }
+
+    // Extracts the audio of a downloaded recording, transcribes it with the
+    // configured local engine and writes `.srt` and `.vtt` subtitles next to
+    // the video. The transcript is added to the search index. Can be stopped
+    // with `cancel_subtitle_generation`.
+    pub async fn generate_subtitles(&self, params: &SubtitleParams) -> Result<SubtitleResult> {
+        let video_path = Path::new(&params.video_path);
+        if !video_path.is_file() {
+            return Err(AppError::SpeechToText(format!(
+                "{} is not a downloaded video",
+                params.video_path
+            )));
+        }
+        let cancel_token = CancellationToken::new();
+        {
+            let mut jobs = self.subtitle_jobs.lock().unwrap();
+            if jobs.contains_key(&params.video_path) {
+                return Err(AppError::SpeechToText(format!(
+                    "subtitles of {} are already being generated",
+                    params.video_path
+                )));
+            }
+            jobs.insert(params.video_path.clone(), cancel_token.clone());
+        }
+        let work_dir = std::env::temp_dir().join(format!("stt_{}", uuid::Uuid::new_v4()));
+        let result = match fs::create_dir_all(&work_dir) {
+            Ok(()) => {
+                self.transcribe_video(&params.video_path, &work_dir, &cancel_token)
+                    .await
+            }
+            Err(e) => Err(e.into()),
+        };
+        let _ = fs::remove_dir_all(&work_dir);
+        self.subtitle_jobs
+            .lock()
+            .unwrap()
+            .remove(&params.video_path);
+        let cues = result?;
+
+        let srt_path = video_path.with_extension("srt");
+        let vtt_path = video_path.with_extension("vtt");
+        fs::write(&srt_path, to_srt(&cues))?;
+        fs::write(&vtt_path, to_vtt(&cues))?;
+
+        let file_name = video_path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let content = cues
+            .iter()
+            .map(|cue| cue.text.as_str())
+            .collect::<Vec<_>>()
+            .join("\n");
+        self.index_document(SearchDocument {
+            id: format!("subtitle:{}", params.video_path),
+            kind: SearchDocumentKind::Subtitle,
+            course_id: 0,
+            course_name: params.course_name.clone(),
+            title: file_name,
+            content,
+            url: String::new(),
+            local_path: Some(params.video_path.clone()),
+            date: None,
+        })
+        .await?;
+
+        tracing::info!(
+            "Generated {} subtitle cues for {}",
+            cues.len(),
+            params.video_path
+        );
+        Ok(SubtitleResult {
+            srt_path: srt_path.to_string_lossy().into_owned(),
+            vtt_path: vtt_path.to_string_lossy().into_owned(),
+            cue_count: cues.len(),
+        })
+    }
+
+    pub fn cancel_subtitle_generation(&self, video_path: &str) -> bool {
+        match self.subtitle_jobs.lock().unwrap().get(video_path) {
+            Some(cancel_token) => {
+                cancel_token.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    async fn transcribe_video(
+        &self,
+        video_path: &str,
+        work_dir: &Path,
+        cancel_token: &CancellationToken,
+    ) -> Result<Vec<SubtitleCue>> {
+        let audio_path = work_dir.join("audio.wav");
+        let audio_path = audio_path.to_string_lossy().into_owned();
+        let (id, job_token) = self.create_ffmpeg_job_with_token(
+            FfmpegJobParams {
+                kind: FfmpegJobKind::ExtractAudio {
+                    input_path: video_path.to_owned(),
+                    for_speech: true,
+                },
+                output_path: audio_path.clone(),
+            },
+            cancel_token.child_token(),
+        )?;
+        let exit_code = self
+            .execute_ffmpeg_job(&id, job_token, |_| {}, |_| {})
+            .await?;
+        if exit_code != 0 {
+            return Err(AppError::FfmpegError(format!(
+                "failed to extract audio of {}",
+                video_path
+            )));
+        }
+
+        let output_base: PathBuf = work_dir.join("transcript");
+        let output_base = output_base.to_string_lossy().into_owned();
+        self.run_stt_command(&audio_path, &output_base, cancel_token)
+            .await?;
+        let srt_path = format!("{}.srt", output_base);
+        let content = fs::read_to_string(&srt_path).map_err(|_| {
+            AppError::SpeechToText(format!(
+                "the speech-to-text command didn't write {}",
+                srt_path
+            ))
+        })?;
+        Ok(parse_srt(&content))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_srt() {
+        let content = "\u{feff}1\r\n00:00:01,000 --> 00:00:02,500\r\n同学们好\r\n\r\n2\r\n00:00:03,000 --> 00:01:04,020\r\nToday we talk\r\nabout Rust\r\n\r\n3\r\n00:01:05,000 --> 00:01:06,000\r\n\r\n";
+        let cues = parse_srt(content);
+        assert_eq!(cues.len(), 2);
+        assert_eq!(cues[0].start_ms, 1000);
+        assert_eq!(cues[0].text, "同学们好");
+        assert_eq!(cues[1].end_ms, 64_020);
+        assert_eq!(cues[1].text, "Today we talk\nabout Rust");
+
+        assert_eq!(parse_srt(&to_srt(&cues)), cues);
+        let vtt = to_vtt(&cues);
+        assert!(vtt.starts_with("WEBVTT\n\n00:00:01.000 --> 00:00:02.500\n同学们好\n"));
+    }
+
+    #[test]
+    fn test_build_stt_command() {
+        let args = build_stt_command(
+            "whisper-cli -m \"/models/ggml base.bin\" -l zh -osrt -f {input} -of {output}",
+            "/tmp/audio.wav",
+            "/tmp/out",
+        );
+        assert_eq!(
+            args,
+            [
+                "whisper-cli",
+                "-m",
+                "/models/ggml base.bin",
+                "-l",
+                "zh",
+                "-osrt",
+                "-f",
+                "/tmp/audio.wav",
+                "-of",
+                "/tmp/out"
+            ]
+        );
+        assert!(build_stt_command("  ", "a", "b").is_empty());
+    }
+}
@@ -1,10 +1,42 @@
-use std::path::Path;
+use std::{
+    fs,
+    path::{Component, Path, PathBuf},
+};
+
+use tokio_util::sync::CancellationToken;
 
 use super::App;
 use crate::{
     error::{AppError, Result},
-    model::{CanvasVideo, ProgressPayload, Subject, VideoCourse, VideoInfo, VideoPlayInfo},
+    model::{
+        AuthService, CanvasVideo, ProgressPayload, Subject, VideoCourse, VideoInfo, VideoPlayInfo,
+    },
 };
+// Downloads stay inside the save directory, whatever name they are given
+pub(super) fn video_save_path(save_dir: &str, save_name: &str) -> Result<PathBuf> {
+    let name = Path::new(save_name);
+    let is_relative = name.components().count() > 0
+        && name
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)));
+    if !is_relative {
+        return Err(AppError::VideoDownloadError(format!(
+            "{} is not a relative path",
+            save_name
+        )));
+    }
+    Ok(Path::new(save_dir).join(name))
+}
+
+fn session_unrecoverable(e: AppError) -> AppError {
+    match e {
+        AppError::VideoSignatureRejected => {
+            AppError::VideoSessionUnrecoverable("the renewed session was rejected too".to_owned())
+        }
+        e => e,
+    }
+}
+
 // Apis for course video
 impl App {
     pub async fn get_uuid(&self) -> Result<Option<String>> {
@@ -30,7 +62,8 @@ impl App {
             self.save_config(config).await?;
             Ok(())
         } else {
-            Err(AppError::LoginError)
+            // the video site didn't hand out a session for this cookie
+            Err(self.client.report_unauthorized(AuthService::Video))
         }
     }
 
@@ -43,9 +76,82 @@ impl App {
         self.client.get_subjects().await
     }
 
+    // A rejected signature renews the video session and tries again. A new
+    // consumer key may come with an expired session, so when it is rejected
+    // too the video site is logged in to again before giving up.
     pub async fn get_video_info(&self, video_id: i64) -> Result<VideoInfo> {
-        let consumer_key = &self.config.read().await.oauth_consumer_key;
-        self.client.get_video_info(video_id, consumer_key).await
+        let consumer_key = self.config.read().await.oauth_consumer_key.clone();
+        match self.client.get_video_info(video_id, &consumer_key).await {
+            Err(AppError::VideoSignatureRejected) => {
+                let (consumer_key, logged_in) =
+                    self.renew_video_session(&consumer_key, false).await?;
+                match self.client.get_video_info(video_id, &consumer_key).await {
+                    Err(AppError::VideoSignatureRejected) if !logged_in => {
+                        let (consumer_key, _) =
+                            self.renew_video_session(&consumer_key, true).await?;
+                        self.client
+                            .get_video_info(video_id, &consumer_key)
+                            .await
+                            .map_err(session_unrecoverable)
+                    }
+                    result => result.map_err(session_unrecoverable),
+                }
+            }
+            result => result,
+        }
+    }
+
+    // Scrapes the consumer key again, unless `log_in` is set, and when that
+    // doesn't give a new one logs in to the video site again with the stored
+    // jAccount cookie. Returns the consumer key to sign with and whether it
+    // logged in.
+    async fn renew_video_session(
+        &self,
+        rejected_key: &str,
+        log_in: bool,
+    ) -> Result<(String, bool)> {
+        let _guard = self.video_session_lock.lock().await;
+        let current_key = self.config.read().await.oauth_consumer_key.clone();
+        if !current_key.is_empty() && current_key != rejected_key {
+            // renewed by another request while waiting for the lock
+            return Ok((current_key, false));
+        }
+        tracing::info!("Video site rejected the consumer key, renewing the session");
+        if !log_in {
+            if let Ok(Some(consumer_key)) = self.client.get_oauth_consumer_key().await {
+                if consumer_key != rejected_key {
+                    let mut config = self.get_config().await;
+                    config.oauth_consumer_key = consumer_key.clone();
+                    self.save_config(config).await?;
+                    return Ok((consumer_key, false));
+                }
+            }
+        }
+
+        if self.config.read().await.ja_auth_cookie.is_empty() {
+            self.client.report_unauthorized(AuthService::Video);
+            return Err(AppError::VideoSessionUnrecoverable(
+                "no jAccount cookie is stored".to_owned(),
+            ));
+        }
+        match self.login_video_website().await {
+            Ok(()) => (),
+            // the failure was already reported to the user by the client
+            Err(AppError::Unauthorized(_)) => {
+                return Err(AppError::VideoSessionUnrecoverable(
+                    "the stored jAccount cookie was rejected".to_owned(),
+                ))
+            }
+            Err(e) => return Err(e),
+        }
+        let consumer_key = self.config.read().await.oauth_consumer_key.clone();
+        if consumer_key.is_empty() {
+            return Err(AppError::VideoSessionUnrecoverable(
+                "no consumer key was found after logging in".to_owned(),
+            ));
+        }
+        tracing::info!("Renewed the video site session");
+        Ok((consumer_key, true))
     }
 
     pub async fn get_canvas_video_info(&self, video_id: &str) -> Result<VideoInfo> {
@@ -62,14 +168,66 @@ impl App {
         save_name: &str,
         progress_handler: F,
     ) -> Result<()> {
-        let save_dir = self.config.read().await.save_path.clone();
-        let save_path = Path::new(&save_dir).join(save_name);
-        self.client
-            .clone()
-            .download_video(video, save_path.to_str().unwrap(), progress_handler)
+        self.download_video_with_token(video, save_name, CancellationToken::new(), progress_handler)
             .await
     }
 
+    // Like `download_video`, stopped by `cancel_token` as well as by
+    // `cancel_video_download`
+    pub(super) async fn download_video_with_token<F: Fn(ProgressPayload) + Send + 'static>(
+        &self,
+        video: &VideoPlayInfo,
+        save_name: &str,
+        cancel_token: CancellationToken,
+        progress_handler: F,
+    ) -> Result<()> {
+        let (save_dir, connections) = {
+            let config = self.config.read().await;
+            (config.save_path.clone(), config.video_download_connections)
+        };
+        let save_path = video_save_path(&save_dir, save_name)?;
+        // names from a template may put lectures into directories
+        if let Some(parent) = save_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let video_id = video.id.to_string();
+        {
+            let mut downloads = self.video_downloads.lock().unwrap();
+            if downloads.contains_key(&video_id) {
+                return Err(AppError::VideoDownloadError(format!(
+                    "video {} is already downloading",
+                    video_id
+                )));
+            }
+            downloads.insert(video_id.clone(), cancel_token.clone());
+        }
+        let result = self
+            .client
+            .clone()
+            .download_video(
+                video,
+                save_path.to_str().unwrap(),
+                connections,
+                cancel_token,
+                progress_handler,
+            )
+            .await;
+        self.video_downloads.lock().unwrap().remove(&video_id);
+        result
+    }
+
+    // Stops a running download, keeping what was downloaded so far so that
+    // downloading the video again resumes it. Returns false if it wasn't running.
+    pub fn cancel_video_download(&self, video_id: &str) -> bool {
+        match self.video_downloads.lock().unwrap().get(video_id) {
+            Some(cancel_token) => {
+                cancel_token.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
     pub async fn get_video_course(
         &self,
         subject_id: i64,
@@ -78,3 +236,21 @@ impl App {
         self.client.get_video_course(subject_id, tecl_id).await
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_video_save_path() {
+        assert_eq!(
+            video_save_path("/videos", "C++/2024-03-01.mp4").unwrap(),
+            Path::new("/videos/C++/2024-03-01.mp4")
+        );
+        assert!(video_save_path("/videos", "../lecture.mp4").is_err());
+        assert!(video_save_path("/videos", "C++/../../lecture.mp4").is_err());
+        assert!(video_save_path("/videos", "/tmp/lecture.mp4").is_err());
+        assert!(video_save_path("/videos", "./lecture.mp4").is_err());
+        assert!(video_save_path("/videos", "").is_err());
+    }
+}
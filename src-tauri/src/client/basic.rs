@@ -2,17 +2,28 @@ use super::{constants::BASE_URL, Client};
 use ::bytes::Bytes;
 use reqwest::{cookie, multipart};
 use serde::de::DeserializeOwned;
-use std::{cmp::min, collections::HashSet, fs, io::Write, ops::Deref, path::Path, sync::Arc};
+use std::{
+    cmp::min,
+    collections::HashSet,
+    fs,
+    io::Write,
+    ops::Deref,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 use tokio::{sync::RwLock, task::JoinSet};
 
 use crate::{
     client::constants::CHUNK_SIZE,
     error::{AppError, Result},
     model::{
-        Assignment, CalendarEvent, Colors, Course, DiscussionTopic, File, Folder, FoldersAndFiles,
-        FullDiscussion, ProgressPayload, RelationshipEdge, RelationshipNode, RelationshipNodeType,
-        RelationshipTopo, Submission, SubmissionUploadResult, SubmissionUploadSuccessResponse,
-        User, UserSubmissions,
+        Assignment, AssignmentOverride, CalendarEvent, Colors, Conversation, ConversationScope,
+        Course, DiscussionTopic, DiscussionView, Enrollment, File, Folder, FoldersAndFiles,
+        FullDiscussion, Group, GroupCategory, Module, ModuleItem, Page, Progress, ProgressPayload,
+        Quiz, QuizQuestion, QuizStatistics, QuizStatisticsResponse, QuizSubmission,
+        QuizSubmissionHistory, QuizSubmissionsPage, RelationshipEdge, RelationshipNode,
+        RelationshipNodeType, RelationshipTopo, Reply, Section, Submission, SubmissionUploadResult,
+        SubmissionUploadSuccessResponse, User, UserSubmissions,
     },
     utils::{self, get_file_name},
 };
@@ -32,7 +43,17 @@ impl Client {
             .unwrap();
         let base_url = RwLock::new(base_url.into());
         let token = RwLock::new("".to_owned());
-        Self { cli, jar, base_url, token}
+        Self {
+            cli,
+            jar,
+            base_url,
+            token,
+            auth_listener: Default::default(),
+            auth_failures: Default::default(),
+            oauth_session: Default::default(),
+            oauth_refresh_lock: Default::default(),
+            token_refresh_listener: Default::default(),
+        }
     }
 
     pub async fn set_base_url<S: Into<String>>(&self, base_url: S) -> bool {
@@ -45,6 +66,10 @@ impl Client {
         false
     }
 
+    pub async fn get_base_url(&self) -> String {
+        self.base_url.read().await.clone()
+    }
+
     pub async fn delete_submission_comment(
         &self,
         course_id: i64,
@@ -61,41 +86,58 @@ impl Client {
             student_id,
             comment_id
         );
-        self.cli
+        let response = self
+            .cli
             .delete(url)
-            .header("Authorization", format!("Bearer {}", token))
+            .header(
+                "Authorization",
+                format!("Bearer {}", self.canvas_token(token).await?),
+            )
             .send()
-            .await?
-            .error_for_status()?;
+            .await?;
+        self.check_canvas_auth(response)?.error_for_status()?;
         Ok(())
     }
 
+    // Grade several students in a single request, e.g. all members of a group.
+    // Canvas applies the grades in the background and returns the job to poll.
     pub async fn update_grade(
         &self,
         course_id: i64,
         assignment_id: i64,
-        student_id: i64,
+        student_ids: &[i64],
         grade: &str,
         comment: Option<&str>,
         token: &str,
-    ) -> Result<()> {
+    ) -> Result<Progress> {
         let url = format!(
             "{}/api/v1/courses/{}/assignments/{}/submissions/update_grades",
             self.base_url.read().await,
             course_id,
             assignment_id
         );
-        let form = match comment {
-            Some(comment) => vec![
-                (format!("grade_data[{}][posted_grade]", student_id), grade),
-                (format!("grade_data[{}][text_comment]", student_id), comment),
-            ],
-            None => vec![(format!("grade_data[{}][posted_grade]", student_id), grade)],
-        };
-        self.post_form_with_token(&url, None::<&str>, &form, token)
+        let mut form = vec![];
+        for student_id in student_ids {
+            form.push((format!("grade_data[{}][posted_grade]", student_id), grade));
+            if let Some(comment) = comment {
+                form.push((format!("grade_data[{}][text_comment]", student_id), comment));
+            }
+        }
+        let response = self
+            .post_form_with_token(&url, None::<&str>, &form, token)
             .await?
             .error_for_status()?;
-        Ok(())
+        let progress = utils::parse_json(&response.bytes().await?)?;
+        Ok(progress)
+    }
+
+    pub async fn get_progress(&self, progress_id: i64, token: &str) -> Result<Progress> {
+        let url = format!(
+            "{}/api/v1/progress/{}",
+            self.base_url.read().await,
+            progress_id
+        );
+        self.get_json_with_token(&url, None::<&str>, token).await
     }
 
     pub async fn modify_assignment_ddl(
@@ -156,6 +198,47 @@ impl Client {
         Ok(())
     }
 
+    #[allow(clippy::too_many_arguments)]
+    pub async fn add_assignment_section_override(
+        &self,
+        course_id: i64,
+        assignment_id: i64,
+        section_id: i64,
+        due_at: Option<&str>,
+        unlock_at: Option<&str>,
+        lock_at: Option<&str>,
+        token: &str,
+    ) -> Result<AssignmentOverride> {
+        let url = format!(
+            "{}/api/v1/courses/{}/assignments/{}/overrides",
+            self.base_url.read().await,
+            course_id,
+            assignment_id
+        );
+        let response = self
+            .post_form_with_token(
+                &url,
+                None::<&str>,
+                &[
+                    (
+                        "assignment_override[course_section_id]",
+                        section_id.to_string().deref(),
+                    ),
+                    ("assignment_override[due_at]", due_at.unwrap_or_default()),
+                    (
+                        "assignment_override[unlock_at]",
+                        unlock_at.unwrap_or_default(),
+                    ),
+                    ("assignment_override[lock_at]", lock_at.unwrap_or_default()),
+                ],
+                token,
+            )
+            .await?
+            .error_for_status()?;
+        let assignment_override = utils::parse_json(&response.bytes().await?)?;
+        Ok(assignment_override)
+    }
+
     pub async fn delete_assignment_ddl_override(
         &self,
         course_id: i64,
@@ -170,12 +253,16 @@ impl Client {
             assignment_id,
             override_id
         );
-        self.cli
+        let response = self
+            .cli
             .delete(url)
-            .header("Authorization", format!("Bearer {}", token))
+            .header(
+                "Authorization",
+                format!("Bearer {}", self.canvas_token(token).await?),
+            )
             .send()
-            .await?
-            .error_for_status()?;
+            .await?;
+        self.check_canvas_auth(response)?.error_for_status()?;
         Ok(())
     }
 
@@ -266,6 +353,16 @@ impl Client {
         token: &str,
         page: u64,
     ) -> Result<Vec<T>> {
+        self.list_items_with_page_raw(url, token, page).await
+    }
+
+    // For paginated apis which wrap their items in an object
+    pub async fn list_items_with_page_raw<T: DeserializeOwned>(
+        &self,
+        url: &str,
+        token: &str,
+        page: u64,
+    ) -> Result<T> {
         let items = self
             .get_json_with_token(
                 url,
@@ -280,11 +377,27 @@ impl Client {
     }
 
     pub async fn list_items<T: DeserializeOwned>(&self, url: &str, token: &str) -> Result<Vec<T>> {
+        self.list_items_with_query(url, &[], token).await
+    }
+
+    // The pagination parameters are appended to the given query
+    pub async fn list_items_with_query<T: DeserializeOwned>(
+        &self,
+        url: &str,
+        query: &[(&str, &str)],
+        token: &str,
+    ) -> Result<Vec<T>> {
         let mut all_items = vec![];
         let mut page = 1;
 
         loop {
-            let items = self.list_items_with_page(url, token, page).await?;
+            let page_str = page.to_string();
+            let mut page_query = query.to_vec();
+            page_query.push(("page", &page_str));
+            page_query.push(("per_page", "100"));
+            let items: Vec<T> = self
+                .get_json_with_token(url, Some(&page_query), token)
+                .await?;
             if items.is_empty() {
                 break;
             }
@@ -294,6 +407,19 @@ impl Client {
         Ok(all_items)
     }
 
+    pub async fn list_announcement_topics(
+        &self,
+        course_id: i64,
+        token: &str,
+    ) -> Result<Vec<DiscussionTopic>> {
+        let url = format!(
+            "{}/api/v1/courses/{}/discussion_topics?only_announcements=true",
+            self.base_url.read().await,
+            course_id
+        );
+        self.list_items(&url, token).await
+    }
+
     pub async fn list_discussion_topics(
         &self,
         course_id: i64,
@@ -322,6 +448,527 @@ impl Client {
         self.get_json_with_token(&url, None::<&str>, token).await
     }
 
+    pub async fn get_discussion_topic(
+        &self,
+        course_id: i64,
+        topic_id: i64,
+        token: &str,
+    ) -> Result<DiscussionTopic> {
+        let url = format!(
+            "{}/api/v1/courses/{}/discussion_topics/{}",
+            self.base_url.read().await,
+            course_id,
+            topic_id
+        );
+        self.get_json_with_token(&url, None::<&str>, token).await
+    }
+
+    fn build_discussion_entry_form(
+        message: &str,
+        attachment_path: Option<&str>,
+    ) -> Result<multipart::Form> {
+        let mut form = multipart::Form::new().text("message", message.to_owned());
+        if let Some(attachment_path) = attachment_path {
+            let content = fs::read(attachment_path)?;
+            let part = multipart::Part::bytes(content).file_name(get_file_name(attachment_path));
+            form = form.part("attachment", part);
+        }
+        Ok(form)
+    }
+
+    // Reference: https://canvas.instructure.com/doc/api/discussion_topics.html#method.discussion_topics_api.add_entry
+    pub async fn post_discussion_entry(
+        &self,
+        course_id: i64,
+        topic_id: i64,
+        message: &str,
+        attachment_path: Option<&str>,
+        token: &str,
+    ) -> Result<DiscussionView> {
+        let url = format!(
+            "{}/api/v1/courses/{}/discussion_topics/{}/entries",
+            self.base_url.read().await,
+            course_id,
+            topic_id
+        );
+        let form = Self::build_discussion_entry_form(message, attachment_path)?;
+        let resp = self
+            .post_multipart_with_token(&url, form, token)
+            .await?
+            .error_for_status()?;
+        let entry = utils::parse_json(&resp.bytes().await?)?;
+        Ok(entry)
+    }
+
+    // entry_id can be either a top level entry or a reply in threaded discussions
+    pub async fn post_discussion_reply(
+        &self,
+        course_id: i64,
+        topic_id: i64,
+        entry_id: i64,
+        message: &str,
+        attachment_path: Option<&str>,
+        token: &str,
+    ) -> Result<Reply> {
+        let url = format!(
+            "{}/api/v1/courses/{}/discussion_topics/{}/entries/{}/replies",
+            self.base_url.read().await,
+            course_id,
+            topic_id,
+            entry_id
+        );
+        let form = Self::build_discussion_entry_form(message, attachment_path)?;
+        let resp = self
+            .post_multipart_with_token(&url, form, token)
+            .await?
+            .error_for_status()?;
+        let reply = utils::parse_json(&resp.bytes().await?)?;
+        Ok(reply)
+    }
+
+    pub async fn rate_discussion_entry(
+        &self,
+        course_id: i64,
+        topic_id: i64,
+        entry_id: i64,
+        liked: bool,
+        token: &str,
+    ) -> Result<()> {
+        let url = format!(
+            "{}/api/v1/courses/{}/discussion_topics/{}/entries/{}/rating",
+            self.base_url.read().await,
+            course_id,
+            topic_id,
+            entry_id
+        );
+        let rating = if liked { "1" } else { "0" };
+        self.post_form_with_token(&url, None::<&str>, &[("rating", rating)], token)
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    pub async fn mark_discussion_entry_read(
+        &self,
+        course_id: i64,
+        topic_id: i64,
+        entry_id: i64,
+        read: bool,
+        token: &str,
+    ) -> Result<()> {
+        let url = format!(
+            "{}/api/v1/courses/{}/discussion_topics/{}/entries/{}/read",
+            self.base_url.read().await,
+            course_id,
+            topic_id,
+            entry_id
+        );
+        let request = if read {
+            self.cli.put(url)
+        } else {
+            self.cli.delete(url)
+        };
+        let response = request
+            .header(
+                "Authorization",
+                format!("Bearer {}", self.canvas_token(token).await?),
+            )
+            .send()
+            .await?;
+        self.check_canvas_auth(response)?.error_for_status()?;
+        Ok(())
+    }
+
+    // Mark the topic itself and all of its entries as read
+    pub async fn mark_discussion_topic_read(
+        &self,
+        course_id: i64,
+        topic_id: i64,
+        token: &str,
+    ) -> Result<()> {
+        let url = format!(
+            "{}/api/v1/courses/{}/discussion_topics/{}/read_all",
+            self.base_url.read().await,
+            course_id,
+            topic_id
+        );
+        self.put_form_with_token(&url, None::<&str>, &[("forced_read_state", "false")], token)
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    // Mark only the topic message as (un)read, used for announcements without replies
+    pub async fn mark_discussion_topic_message_read(
+        &self,
+        course_id: i64,
+        topic_id: i64,
+        read: bool,
+        token: &str,
+    ) -> Result<()> {
+        let url = format!(
+            "{}/api/v1/courses/{}/discussion_topics/{}/read",
+            self.base_url.read().await,
+            course_id,
+            topic_id
+        );
+        let request = if read {
+            self.cli.put(url)
+        } else {
+            self.cli.delete(url)
+        };
+        let response = request
+            .header(
+                "Authorization",
+                format!("Bearer {}", self.canvas_token(token).await?),
+            )
+            .send()
+            .await?;
+        self.check_canvas_auth(response)?.error_for_status()?;
+        Ok(())
+    }
+
+    async fn list_announcements_inner(
+        &self,
+        token: &str,
+        context_codes: &[String],
+        start_date: Option<&str>,
+        end_date: Option<&str>,
+    ) -> Result<Vec<DiscussionTopic>> {
+        let mut query = context_codes
+            .iter()
+            .map(|context_code| ("context_codes[]", context_code.as_str()))
+            .collect::<Vec<_>>();
+        if let Some(start_date) = start_date {
+            query.push(("start_date", start_date));
+        }
+        if let Some(end_date) = end_date {
+            query.push(("end_date", end_date));
+        }
+        let url = format!("{}/api/v1/announcements", self.base_url.read().await);
+        self.list_items_with_query(&url, &query, token).await
+    }
+
+    // Canvas only returns the last 14 days of announcements if start_date is not given
+    pub async fn list_announcements(
+        &self,
+        token: &str,
+        context_codes: &[String],
+        start_date: Option<&str>,
+        end_date: Option<&str>,
+    ) -> Result<Vec<DiscussionTopic>> {
+        const BATCH_SIZE: usize = 10;
+        let mut all_announcements = vec![];
+        for context_codes_batch in context_codes.chunks(BATCH_SIZE) {
+            let announcements = self
+                .list_announcements_inner(token, context_codes_batch, start_date, end_date)
+                .await?;
+            all_announcements.extend(announcements);
+        }
+        Ok(all_announcements)
+    }
+
+    pub async fn list_modules(&self, course_id: i64, token: &str) -> Result<Vec<Module>> {
+        let url = format!(
+            "{}/api/v1/courses/{}/modules?include[]=items",
+            self.base_url.read().await,
+            course_id
+        );
+        self.list_items(&url, token).await
+    }
+
+    pub async fn list_module_items(
+        &self,
+        course_id: i64,
+        module_id: i64,
+        token: &str,
+    ) -> Result<Vec<ModuleItem>> {
+        let url = format!(
+            "{}/api/v1/courses/{}/modules/{}/items",
+            self.base_url.read().await,
+            course_id,
+            module_id
+        );
+        self.list_items(&url, token).await
+    }
+
+    pub async fn list_pages(&self, course_id: i64, token: &str) -> Result<Vec<Page>> {
+        let url = format!(
+            "{}/api/v1/courses/{}/pages",
+            self.base_url.read().await,
+            course_id
+        );
+        self.list_items(&url, token).await
+    }
+
+    pub async fn get_page(&self, course_id: i64, page_url: &str, token: &str) -> Result<Page> {
+        let url = format!(
+            "{}/api/v1/courses/{}/pages/{}",
+            self.base_url.read().await,
+            course_id,
+            page_url
+        );
+        let page = self.get_json_with_token(&url, None::<&str>, token).await?;
+        Ok(page)
+    }
+
+    pub async fn list_quizzes(&self, course_id: i64, token: &str) -> Result<Vec<Quiz>> {
+        let url = format!(
+            "{}/api/v1/courses/{}/quizzes",
+            self.base_url.read().await,
+            course_id
+        );
+        self.list_items(&url, token).await
+    }
+
+    // Only available to users who can manage the quiz
+    pub async fn list_quiz_questions(
+        &self,
+        course_id: i64,
+        quiz_id: i64,
+        token: &str,
+    ) -> Result<Vec<QuizQuestion>> {
+        let url = format!(
+            "{}/api/v1/courses/{}/quizzes/{}/questions",
+            self.base_url.read().await,
+            course_id,
+            quiz_id
+        );
+        self.list_items(&url, token).await
+    }
+
+    pub async fn list_quiz_submissions(
+        &self,
+        course_id: i64,
+        quiz_id: i64,
+        token: &str,
+    ) -> Result<Vec<QuizSubmission>> {
+        let url = format!(
+            "{}/api/v1/courses/{}/quizzes/{}/submissions",
+            self.base_url.read().await,
+            course_id,
+            quiz_id
+        );
+        // submissions are wrapped in an object, so list_items can't be used here
+        let mut all_submissions = vec![];
+        let mut page = 1;
+        loop {
+            let submissions_page: QuizSubmissionsPage =
+                self.list_items_with_page_raw(&url, token, page).await?;
+            if submissions_page.quiz_submissions.is_empty() {
+                break;
+            }
+            page += 1;
+            all_submissions.extend(submissions_page.quiz_submissions);
+        }
+        Ok(all_submissions)
+    }
+
+    pub async fn get_quiz_submission_history(
+        &self,
+        course_id: i64,
+        assignment_id: i64,
+        token: &str,
+    ) -> Result<QuizSubmissionHistory> {
+        let url = format!(
+            "{}/api/v1/courses/{}/assignments/{}/submissions/self?include[]=submission_history",
+            self.base_url.read().await,
+            course_id,
+            assignment_id
+        );
+        let history = self.get_json_with_token(&url, None::<&str>, token).await?;
+        Ok(history)
+    }
+
+    pub async fn get_quiz_statistics(
+        &self,
+        course_id: i64,
+        quiz_id: i64,
+        token: &str,
+    ) -> Result<Option<QuizStatistics>> {
+        let url = format!(
+            "{}/api/v1/courses/{}/quizzes/{}/statistics",
+            self.base_url.read().await,
+            course_id,
+            quiz_id
+        );
+        let response: QuizStatisticsResponse =
+            self.get_json_with_token(&url, None::<&str>, token).await?;
+        Ok(response.quiz_statistics.into_iter().next())
+    }
+
+    pub async fn list_conversations(
+        &self,
+        scope: ConversationScope,
+        page: u64,
+        token: &str,
+    ) -> Result<Vec<Conversation>> {
+        let url = format!(
+            "{}/api/v1/conversations?scope={}",
+            self.base_url.read().await,
+            scope.as_str()
+        );
+        self.list_items_with_page(&url, token, page).await
+    }
+
+    // Also marks the conversation as read
+    pub async fn get_conversation(
+        &self,
+        conversation_id: i64,
+        token: &str,
+    ) -> Result<Conversation> {
+        let url = format!(
+            "{}/api/v1/conversations/{}",
+            self.base_url.read().await,
+            conversation_id
+        );
+        let conversation = self.get_json_with_token(&url, None::<&str>, token).await?;
+        Ok(conversation)
+    }
+
+    // Without bulk every recipient gets a private thread, Canvas's default. Canvas
+    // only honours bulk_message on group conversations, so with bulk
+    // group_conversation is sent too and the private copies go out in one batch.
+    pub async fn create_conversation(
+        &self,
+        course_id: i64,
+        recipient_ids: &[i64],
+        subject: &str,
+        body: &str,
+        bulk: bool,
+        token: &str,
+    ) -> Result<Vec<Conversation>> {
+        let url = format!("{}/api/v1/conversations", self.base_url.read().await);
+        let mut form = vec![
+            ("subject".to_owned(), subject.to_owned()),
+            ("body".to_owned(), body.to_owned()),
+            ("context_code".to_owned(), format!("course_{}", course_id)),
+            ("force_new".to_owned(), "true".to_owned()),
+        ];
+        if bulk {
+            form.push(("group_conversation".to_owned(), "true".to_owned()));
+            form.push(("bulk_message".to_owned(), "true".to_owned()));
+        }
+        for recipient_id in recipient_ids {
+            form.push(("recipients[]".to_owned(), recipient_id.to_string()));
+        }
+        let response = self
+            .post_form_with_token(&url, None::<&str>, &form, token)
+            .await?
+            .error_for_status()?;
+        let conversations = utils::parse_json(&response.bytes().await?)?;
+        Ok(conversations)
+    }
+
+    pub async fn reply_conversation(
+        &self,
+        conversation_id: i64,
+        body: &str,
+        token: &str,
+    ) -> Result<Conversation> {
+        let url = format!(
+            "{}/api/v1/conversations/{}/add_message",
+            self.base_url.read().await,
+            conversation_id
+        );
+        let response = self
+            .post_form_with_token(&url, None::<&str>, &[("body", body)], token)
+            .await?
+            .error_for_status()?;
+        let conversation = utils::parse_json(&response.bytes().await?)?;
+        Ok(conversation)
+    }
+
+    pub async fn update_conversation_state(
+        &self,
+        conversation_id: i64,
+        workflow_state: &str,
+        token: &str,
+    ) -> Result<()> {
+        let url = format!(
+            "{}/api/v1/conversations/{}",
+            self.base_url.read().await,
+            conversation_id
+        );
+        self.put_form_with_token(
+            &url,
+            None::<&str>,
+            &[("conversation[workflow_state]", workflow_state)],
+            token,
+        )
+        .await?
+        .error_for_status()?;
+        Ok(())
+    }
+
+    pub async fn list_group_categories(
+        &self,
+        course_id: i64,
+        token: &str,
+    ) -> Result<Vec<GroupCategory>> {
+        let url = format!(
+            "{}/api/v1/courses/{}/group_categories",
+            self.base_url.read().await,
+            course_id
+        );
+        self.list_items(&url, token).await
+    }
+
+    pub async fn list_category_groups(&self, category_id: i64, token: &str) -> Result<Vec<Group>> {
+        let url = format!(
+            "{}/api/v1/group_categories/{}/groups",
+            self.base_url.read().await,
+            category_id
+        );
+        self.list_items(&url, token).await
+    }
+
+    pub async fn list_group_users(&self, group_id: i64, token: &str) -> Result<Vec<User>> {
+        let url = format!(
+            "{}/api/v1/groups/{}/users",
+            self.base_url.read().await,
+            group_id
+        );
+        self.list_items(&url, token).await
+    }
+
+    pub async fn list_course_sections(&self, course_id: i64, token: &str) -> Result<Vec<Section>> {
+        let url = format!(
+            "{}/api/v1/courses/{}/sections?include[]=students&include[]=total_students",
+            self.base_url.read().await,
+            course_id
+        );
+        self.list_items(&url, token).await
+    }
+
+    pub async fn list_section_enrollments(
+        &self,
+        section_id: i64,
+        token: &str,
+    ) -> Result<Vec<Enrollment>> {
+        let url = format!(
+            "{}/api/v1/sections/{}/enrollments",
+            self.base_url.read().await,
+            section_id
+        );
+        self.list_items(&url, token).await
+    }
+
+    pub async fn list_section_assignment_submissions(
+        &self,
+        section_id: i64,
+        assignment_id: i64,
+        token: &str,
+    ) -> Result<Vec<Submission>> {
+        let url = format!(
+            "{}/api/v1/sections/{}/assignments/{}/submissions?include[]=submission_comments",
+            self.base_url.read().await,
+            section_id,
+            assignment_id
+        );
+        self.list_items(&url, token).await
+    }
+
     pub async fn list_course_files(&self, course_id: i64, token: &str) -> Result<Vec<File>> {
         let url = format!(
             "{}/api/v1/courses/{}/files",
@@ -382,7 +1029,17 @@ impl Client {
         Ok(folder)
     }
 
-    async fn get_folders_and_files(&self, course_id: i64, token: &str) -> Result<FoldersAndFiles> {
+    pub async fn get_file(&self, file_id: i64, token: &str) -> Result<File> {
+        let url = format!("{}/api/v1/files/{}", self.base_url.read().await, file_id);
+        let file = self.get_json_with_token(&url, None::<&str>, token).await?;
+        Ok(file)
+    }
+
+    pub async fn get_folders_and_files(
+        &self,
+        course_id: i64,
+        token: &str,
+    ) -> Result<FoldersAndFiles> {
         let folders = self.list_course_folders(course_id, token).await?;
         let files = self.list_course_files(course_id, token).await?;
         Ok(FoldersAndFiles::new(folders, files))
@@ -395,6 +1052,26 @@ impl Client {
         )
     }
 
+    // Where sync_course_files saves a course file, None if the folder can't be mapped
+    pub fn get_course_file_path(
+        &self,
+        course: &Course,
+        folder: &Folder,
+        file: &File,
+        save_dir: &str,
+    ) -> Option<PathBuf> {
+        let folder_name = &folder.full_name;
+        if folder_name.len() < 12 {
+            return None;
+        }
+
+        let mut path = Path::new(save_dir).join(self.get_course_identifier(course));
+        if folder_name != "course files" {
+            path = path.join(&folder_name[13..]);
+        }
+        Some(path.join(&file.display_name))
+    }
+
     pub async fn sync_course_files(
         &self,
         course: &Course,
@@ -410,19 +1087,11 @@ impl Client {
             .filter(|file| {
                 let folder = folders_map.get(&file.folder_id);
                 match folder {
-                    Some(folder) => {
-                        let folder_name = &folder.full_name;
-                        if folder_name.len() < 12 {
-                            return false;
-                        }
-
-                        let mut path = Path::new(save_dir).join(self.get_course_identifier(course));
-                        if folder_name != "course files" {
-                            path = path.join(&folder_name[13..]);
-                        }
-                        path = path.join(&file.display_name);
-                        fs::metadata(&path).is_err()
-                    }
+                    Some(folder) => match self.get_course_file_path(course, folder, file, save_dir)
+                    {
+                        Some(path) => fs::metadata(&path).is_err(),
+                        None => false,
+                    },
                     None => true,
                 }
             })
@@ -505,7 +1174,7 @@ impl Client {
         token: &str,
     ) -> Result<Submission> {
         let url = format!(
-            "{}/api/v1/courses/{}/assignments/{}/submissions/{}?include[]=submission_comments",
+            "{}/api/v1/courses/{}/assignments/{}/submissions/{}?include[]=submission_comments&include[]=group",
             self.base_url.read().await,
             course_id,
             assignment_id,
@@ -522,7 +1191,7 @@ impl Client {
         token: &str,
     ) -> Result<Vec<Submission>> {
         let url = format!(
-            "{}/api/v1/courses/{}/assignments/{}/submissions?include[]=submission_comments",
+            "{}/api/v1/courses/{}/assignments/{}/submissions?include[]=submission_comments&include[]=group",
             self.base_url.read().await,
             course_id,
             assignment_id
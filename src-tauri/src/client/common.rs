@@ -1,30 +1,70 @@
 use reqwest::{
-    header::{HeaderValue, ACCEPT, CONTENT_TYPE},
-    Body, Response,
+    header::{HeaderValue, ACCEPT, CONTENT_TYPE, WWW_AUTHENTICATE},
+    multipart::Form,
+    Body, Response, StatusCode,
 };
 use serde::{de::DeserializeOwned, Serialize};
 
-use super::Client;
-use crate::{error::Result, utils};
+use super::{constants::AUTH_DOMAIN, AuthListener, Client};
+use crate::{
+    error::{AppError, Result},
+    model::AuthService,
+    utils,
+};
 
 impl Client {
+    pub fn set_auth_listener(&self, listener: AuthListener) {
+        *self.auth_listener.write().unwrap() = Some(listener);
+    }
+
+    // Call after the user logged in again so new failures are reported
+    pub fn reset_auth_failures(&self) {
+        self.auth_failures.lock().unwrap().clear();
+    }
+
+    pub fn report_unauthorized(&self, service: AuthService) -> AppError {
+        tracing::warn!("Credentials for {:?} were rejected", service);
+        if self.auth_failures.lock().unwrap().insert(service) {
+            if let Some(listener) = self.auth_listener.read().unwrap().as_ref() {
+                listener(service);
+            }
+        }
+        AppError::Unauthorized(service)
+    }
+
+    // Canvas answers 401 both for invalid tokens and for missing permissions,
+    // only the former comes with a WWW-Authenticate challenge
+    pub(super) fn check_canvas_auth(&self, response: Response) -> Result<Response> {
+        if response.status() == StatusCode::UNAUTHORIZED
+            && response.headers().contains_key(WWW_AUTHENTICATE)
+        {
+            return Err(self.report_unauthorized(AuthService::Canvas));
+        }
+        Ok(response)
+    }
+
+    // Expired jAccount sessions are redirected to the jAccount login page
+    pub(super) fn is_redirected_to_login(response: &Response) -> bool {
+        response.url().domain() == Some(AUTH_DOMAIN)
+    }
+
     pub async fn get_request_with_token<T: Serialize + ?Sized>(
         &self,
         url: &str,
         query: Option<&T>,
         token: &str,
     ) -> Result<Response> {
-        let mut req = self
-            .cli
-            .get(url)
-            .header("Authorization", format!("Bearer {}", token));
+        let mut req = self.cli.get(url).header(
+            "Authorization",
+            format!("Bearer {}", self.canvas_token(token).await?),
+        );
 
         if let Some(query) = query {
             req = req.query(query)
         }
 
         let res = req.send().await?;
-        Ok(res)
+        self.check_canvas_auth(res)
     }
 
     pub async fn get_json_with_token<T: Serialize + ?Sized, D: DeserializeOwned>(
@@ -51,13 +91,16 @@ impl Client {
         let mut request = self
             .cli
             .post(url)
-            .header("Authorization".to_owned(), format!("Bearer {}", token))
+            .header(
+                "Authorization".to_owned(),
+                format!("Bearer {}", self.canvas_token(token).await?),
+            )
             .form(form);
         if let Some(query) = query {
             request = request.query(query);
         }
         let response = request.send().await?;
-        Ok(response)
+        self.check_canvas_auth(response)
     }
 
     pub async fn put_form_with_token<T: Serialize + ?Sized, Q: Serialize + ?Sized>(
@@ -70,13 +113,35 @@ impl Client {
         let mut request = self
             .cli
             .put(url)
-            .header("Authorization".to_owned(), format!("Bearer {}", token))
+            .header(
+                "Authorization".to_owned(),
+                format!("Bearer {}", self.canvas_token(token).await?),
+            )
             .form(form);
         if let Some(query) = query {
             request = request.query(query);
         }
         let response = request.send().await?;
-        Ok(response)
+        self.check_canvas_auth(response)
+    }
+
+    pub async fn post_multipart_with_token(
+        &self,
+        url: &str,
+        form: Form,
+        token: &str,
+    ) -> Result<Response> {
+        let response = self
+            .cli
+            .post(url)
+            .header(
+                "Authorization".to_owned(),
+                format!("Bearer {}", self.canvas_token(token).await?),
+            )
+            .multipart(form)
+            .send()
+            .await?;
+        self.check_canvas_auth(response)
     }
 
     pub async fn post_request<D: DeserializeOwned, B: Into<Body>>(
@@ -125,8 +190,46 @@ impl Client {
             req = req.query(query);
         }
 
-        let response = req.send().await?.error_for_status()?;
+        let response = req.send().await?;
+        if matches!(
+            response.status(),
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN
+        ) || Client::is_redirected_to_login(&response)
+        {
+            return Err(self.report_unauthorized(AuthService::Video));
+        }
+        let response = response.error_for_status()?;
         let json = utils::parse_json(&response.bytes().await?)?;
         Ok(json)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_report_unauthorized_once() {
+        let client = Client::new();
+        let count = Arc::new(AtomicUsize::new(0));
+        let counter = count.clone();
+        client.set_auth_listener(Arc::new(move |service| {
+            assert_eq!(service, AuthService::Canvas);
+            counter.fetch_add(1, Ordering::SeqCst);
+        }));
+
+        let err = client.report_unauthorized(AuthService::Canvas);
+        assert!(matches!(err, AppError::Unauthorized(AuthService::Canvas)));
+        client.report_unauthorized(AuthService::Canvas);
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+
+        client.reset_auth_failures();
+        client.report_unauthorized(AuthService::Canvas);
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+    }
+}
@@ -8,17 +8,14 @@ pub const VIDEO_OAUTH_KEY_URL: &str = "https://courses.sjtu.edu.cn/app/vodvideo/
 pub const VIDEO_INFO_URL: &str =
     "https://courses.sjtu.edu.cn/app/system/resource/vodVideo/getvideoinfos";
 pub const AUTH_URL: &str = "https://jaccount.sjtu.edu.cn";
+pub const AUTH_DOMAIN: &str = "jaccount.sjtu.edu.cn";
 pub const MY_SJTU_URL: &str = "https://my.sjtu.edu.cn/ui/appmyinfo";
 pub const EXPRESS_LOGIN_URL: &str = "https://jaccount.sjtu.edu.cn/jaccount/expresslogin";
-pub const OAUTH_PATH: &str =
-    "aHR0cHM6Ly9jb3Vyc2VzLnNqdHUuZWR1LmNuL2FwcC92b2R2aWRlby92b2RWaWRlb1BsYXkuZDJq";
-pub const OAUTH_RANDOM: &str = "oauth_ABCDE=ABCDEFGH&oauth_VWXYZ=STUVWXYZ";
-pub const OAUTH_RANDOM_P1: &str = "oauth_ABCDE";
-pub const OAUTH_RANDOM_P2: &str = "oauth_VWXYZ";
-pub const OAUTH_RANDOM_P1_VAL: &str = "ABCDEFGH";
-pub const OAUTH_RANDOM_P2_VAL: &str = "STUVWXYZ";
+// signed requests name the player page they come from in `oauth-path`
+pub const VIDEO_PLAY_PAGE_URL: &str = "https://courses.sjtu.edu.cn/app/vodvideo/vodVideoPlay.d2j";
 pub const CHUNK_SIZE: u64 = 16 * 1024 * 1024;
 pub const VIDEO_CHUNK_SIZE: u64 = 4 * 1024 * 1024;
+pub const VIDEO_CHUNK_RETRIES: u32 = 3;
 
 pub const JBOX_LOGIN_URL: &str =
     "https://pan.sjtu.edu.cn/user/v1/sign-in/sso-login-redirect/xpw8ou8y";
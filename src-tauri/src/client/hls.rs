@@ -0,0 +1,640 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use bytes::Bytes;
+use cbc::cipher::{block_padding::Pkcs7, BlockDecryptMut, KeyIvInit};
+use reqwest::{
+    header::{RANGE, REFERER},
+    StatusCode, Url,
+};
+use serde::{Deserialize, Serialize};
+use tokio::{sync::Mutex, task::JoinSet};
+use tokio_util::sync::CancellationToken;
+
+use super::{video::with_retries, Client};
+use crate::{
+    error::{AppError, Result},
+    model::{ProgressPayload, VideoPlayInfo},
+};
+
+type Aes128CbcDec = cbc::Decryptor<aes::Aes128>;
+
+// Master playlists may point at further master playlists, but not forever
+const MAX_PLAYLIST_DEPTH: usize = 4;
+const SEGMENT_STATE_FILE_NAME: &str = "state.json";
+
+#[derive(Debug, Clone, PartialEq)]
+struct SegmentKey {
+    uri: String,
+    iv: Option<[u8; 16]>,
+}
+
+// Length and offset of a sub-range of the resource
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct ByteRange {
+    length: u64,
+    offset: u64,
+}
+
+impl ByteRange {
+    fn header(&self) -> String {
+        format!("bytes={}-{}", self.offset, self.offset + self.length - 1)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Segment {
+    uri: String,
+    sequence: u64,
+    key: Option<SegmentKey>,
+    range: Option<ByteRange>,
+}
+
+impl Segment {
+    // Without an explicit IV the media sequence number is used, big endian
+    fn iv(&self, key: &SegmentKey) -> [u8; 16] {
+        key.iv
+            .unwrap_or_else(|| (self.sequence as u128).to_be_bytes())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Variant {
+    uri: String,
+    bandwidth: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Playlist {
+    Master(Vec<Variant>),
+    Media(Vec<Segment>),
+}
+
+pub fn is_hls_url(url: &str) -> bool {
+    Url::parse(url).is_ok_and(|url| url.path().ends_with(".m3u8"))
+}
+
+fn invalid_playlist(message: String) -> AppError {
+    AppError::VideoDownloadError(format!("invalid playlist: {}", message))
+}
+
+// `KEY=value,KEY="quoted, value"` attribute lists of playlist tags
+fn parse_attributes(list: &str) -> HashMap<String, String> {
+    let mut attributes = HashMap::new();
+    let mut rest = list.trim();
+    while let Some((key, value)) = rest.split_once('=') {
+        let (value, remaining) = match value.strip_prefix('"') {
+            Some(quoted) => {
+                let end = quoted.find('"').unwrap_or(quoted.len());
+                let remaining = quoted[end..].trim_start_matches('"');
+                (&quoted[..end], remaining)
+            }
+            None => value.split_once(',').map_or((value, ""), |(v, r)| (v, r)),
+        };
+        attributes.insert(key.trim().to_owned(), value.to_owned());
+        rest = remaining.trim_start_matches(',').trim_start();
+    }
+    attributes
+}
+
+fn parse_iv(value: &str) -> Result<[u8; 16]> {
+    let hex = value.trim_start_matches("0x").trim_start_matches("0X");
+    u128::from_str_radix(hex, 16)
+        .map(|iv| iv.to_be_bytes())
+        .map_err(|_| invalid_playlist(format!("invalid IV {}", value)))
+}
+
+// `<length>[@<offset>]`, without an offset the range follows the previous one
+fn parse_byte_range(value: &str, previous_end: Option<u64>) -> Result<ByteRange> {
+    let invalid = || invalid_playlist(format!("invalid byte range {}", value));
+    let (length, offset) = match value.split_once('@') {
+        Some((length, offset)) => (length, Some(offset)),
+        None => (value, None),
+    };
+    let length: u64 = length.trim().parse().map_err(|_| invalid())?;
+    let offset = match offset {
+        Some(offset) => offset.trim().parse().map_err(|_| invalid())?,
+        None => previous_end.ok_or_else(invalid)?,
+    };
+    if length == 0 {
+        return Err(invalid());
+    }
+    Ok(ByteRange { length, offset })
+}
+
+fn resolve_uri(base: &Url, uri: &str) -> Result<String> {
+    base.join(uri)
+        .map(|url| url.to_string())
+        .map_err(|e| invalid_playlist(format!("invalid uri {}: {}", uri, e)))
+}
+
+fn parse_playlist(base: &Url, content: &str) -> Result<Playlist> {
+    let mut lines = content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty());
+    if lines.next() != Some("#EXTM3U") {
+        return Err(invalid_playlist("missing #EXTM3U header".to_owned()));
+    }
+
+    let mut variants = vec![];
+    let mut segments = vec![];
+    let mut sequence = 0;
+    let mut key: Option<SegmentKey> = None;
+    let mut pending_variant: Option<u64> = None;
+    let mut pending_range: Option<&str> = None;
+    // uri and end of the last sub-range, the next one may leave out its offset
+    // if it is in the same resource
+    let mut range_end: Option<(String, u64)> = None;
+    let mut has_map = false;
+    for line in lines {
+        if let Some(attributes) = line.strip_prefix("#EXT-X-STREAM-INF:") {
+            let attributes = parse_attributes(attributes);
+            let bandwidth = attributes
+                .get("BANDWIDTH")
+                .and_then(|bandwidth| bandwidth.parse().ok())
+                .unwrap_or_default();
+            pending_variant = Some(bandwidth);
+        } else if let Some(value) = line.strip_prefix("#EXT-X-MEDIA-SEQUENCE:") {
+            sequence = value
+                .parse()
+                .map_err(|_| invalid_playlist(format!("invalid media sequence {}", value)))?;
+        } else if let Some(attributes) = line.strip_prefix("#EXT-X-KEY:") {
+            let attributes = parse_attributes(attributes);
+            key = match attributes.get("METHOD").map(String::as_str) {
+                Some("NONE") => None,
+                Some("AES-128") => {
+                    let uri = attributes
+                        .get("URI")
+                        .ok_or_else(|| invalid_playlist("key without uri".to_owned()))?;
+                    Some(SegmentKey {
+                        uri: resolve_uri(base, uri)?,
+                        iv: attributes.get("IV").map(|iv| parse_iv(iv)).transpose()?,
+                    })
+                }
+                method => {
+                    return Err(invalid_playlist(format!(
+                        "unsupported encryption {:?}",
+                        method
+                    )))
+                }
+            };
+        } else if let Some(value) = line.strip_prefix("#EXT-X-BYTERANGE:") {
+            pending_range = Some(value);
+        } else if let Some(attributes) = line.strip_prefix("#EXT-X-MAP:") {
+            // the init section of fragmented mp4 streams goes first, once
+            let attributes = parse_attributes(attributes);
+            if let (false, Some(uri)) = (has_map, attributes.get("URI")) {
+                // the media sequence number only stands in as the IV of media
+                // segments, an encrypted init section must name its IV
+                if key.as_ref().is_some_and(|key| key.iv.is_none()) {
+                    return Err(invalid_playlist(
+                        "encrypted init section without an IV".to_owned(),
+                    ));
+                }
+                let range = attributes
+                    .get("BYTERANGE")
+                    .map(|range| parse_byte_range(range, Some(0)))
+                    .transpose()?;
+                segments.push(Segment {
+                    uri: resolve_uri(base, uri)?,
+                    sequence,
+                    key: key.clone(),
+                    range,
+                });
+                has_map = true;
+            }
+        } else if line.starts_with('#') {
+            continue;
+        } else if let Some(bandwidth) = pending_variant.take() {
+            variants.push(Variant {
+                uri: resolve_uri(base, line)?,
+                bandwidth,
+            });
+        } else {
+            let uri = resolve_uri(base, line)?;
+            let range = match pending_range.take() {
+                Some(value) => {
+                    let previous_end = range_end
+                        .as_ref()
+                        .filter(|(last_uri, _)| *last_uri == uri)
+                        .map(|(_, end)| *end);
+                    Some(parse_byte_range(value, previous_end)?)
+                }
+                None => None,
+            };
+            range_end = range.map(|range| (uri.clone(), range.offset + range.length));
+            segments.push(Segment {
+                uri,
+                sequence,
+                key: key.clone(),
+                range,
+            });
+            sequence += 1;
+        }
+    }
+
+    if !variants.is_empty() {
+        Ok(Playlist::Master(variants))
+    } else if !segments.is_empty() {
+        Ok(Playlist::Media(segments))
+    } else {
+        Err(invalid_playlist("no segments or variants".to_owned()))
+    }
+}
+
+fn decrypt_segment(data: &[u8], key: &[u8], iv: &[u8; 16]) -> Result<Vec<u8>> {
+    let decryptor = Aes128CbcDec::new_from_slices(key, iv)
+        .map_err(|_| AppError::VideoDownloadError(format!("invalid key of {} bytes", key.len())))?;
+    let mut buf = data.to_vec();
+    let len = decryptor
+        .decrypt_padded_mut::<Pkcs7>(&mut buf)
+        .map_err(|_| AppError::VideoDownloadError("failed to decrypt segment".to_owned()))?
+        .len();
+    buf.truncate(len);
+    Ok(buf)
+}
+
+#[derive(Debug, Default)]
+struct SegmentProgress {
+    uuid: String,
+    done_segments: u64,
+    done_bytes: u64,
+    total_segments: u64,
+}
+
+impl SegmentProgress {
+    fn add(&mut self, size: u64) {
+        self.done_segments += 1;
+        self.done_bytes += size;
+    }
+
+    // The total assumes the remaining segments are as large as the done ones
+    // on average, it is exact once every segment is done
+    fn payload(&self) -> ProgressPayload {
+        let total = (self.done_bytes * self.total_segments)
+            .checked_div(self.done_segments)
+            .unwrap_or_default();
+        ProgressPayload {
+            uuid: self.uuid.clone(),
+            processed: self.done_bytes,
+            total,
+        }
+    }
+}
+
+// What the segments of an unfinished download belong to, saved next to them
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct SegmentState {
+    video_id: i64,
+    playlist_url: String,
+    segment_count: usize,
+}
+
+impl SegmentState {
+    fn load(path: &Path) -> Option<Self> {
+        serde_json::from_slice(&fs::read(path).ok()?).ok()
+    }
+
+    fn save(&self, path: &Path) -> Result<()> {
+        fs::write(path, serde_json::to_vec(self)?)?;
+        Ok(())
+    }
+}
+
+// Segments of another video or variant saved under the same name are removed
+// instead of being joined into this one
+fn prepare_segment_dir(segment_dir: &Path, state: &SegmentState) -> Result<()> {
+    let state_path = segment_dir.join(SEGMENT_STATE_FILE_NAME);
+    if segment_dir.exists() && SegmentState::load(&state_path).as_ref() != Some(state) {
+        tracing::info!("Starting over the segments in {:?}", segment_dir);
+        fs::remove_dir_all(segment_dir)?;
+    }
+    fs::create_dir_all(segment_dir)?;
+    state.save(&state_path)
+}
+
+fn segment_path(segment_dir: &Path, index: usize) -> PathBuf {
+    segment_dir.join(format!("{}.ts", index))
+}
+
+// Joins the downloaded segments into `save_path`. Recordings run to gigabytes,
+// so this is done on a blocking thread.
+fn join_segments(segment_dir: &Path, segment_count: usize, save_path: &str) -> Result<()> {
+    let part_path = format!("{}.part", save_path);
+    let mut output_file = File::create(&part_path)?;
+    for index in 0..segment_count {
+        io::copy(
+            &mut File::open(segment_path(segment_dir, index))?,
+            &mut output_file,
+        )?;
+    }
+    output_file.sync_all()?;
+    drop(output_file);
+    fs::rename(&part_path, save_path)?;
+    fs::remove_dir_all(segment_dir)?;
+    Ok(())
+}
+
+impl Client {
+    async fn get_hls_resource(&self, url: &str, range: Option<ByteRange>) -> Result<Bytes> {
+        with_retries(url, || async {
+            let mut request = self
+                .cli
+                .get(url)
+                .header(REFERER, "https://courses.sjtu.edu.cn");
+            if let Some(range) = range {
+                request = request.header(RANGE, range.header());
+            }
+            let response = request.send().await?.error_for_status()?;
+            // a server ignoring the range would hand us the whole resource
+            if range.is_some() && response.status() != StatusCode::PARTIAL_CONTENT {
+                return Err(AppError::VideoDownloadError(format!(
+                    "{} doesn't support byte ranges",
+                    url
+                )));
+            }
+            Ok(response.bytes().await?)
+        })
+        .await
+    }
+
+    // Follows master playlists to the media playlist of the best variant,
+    // returns its url and its segments
+    async fn get_hls_segments(&self, url: &str) -> Result<(String, Vec<Segment>)> {
+        let mut url = url.to_owned();
+        for _ in 0..MAX_PLAYLIST_DEPTH {
+            let base = Url::parse(&url).map_err(|e| invalid_playlist(e.to_string()))?;
+            let content = self.get_hls_resource(&url, None).await?;
+            match parse_playlist(&base, &String::from_utf8_lossy(&content))? {
+                Playlist::Media(segments) => return Ok((url, segments)),
+                Playlist::Master(variants) => {
+                    let best = variants
+                        .into_iter()
+                        .max_by_key(|variant| variant.bandwidth)
+                        .unwrap();
+                    tracing::info!("Using HLS variant of bandwidth {}", best.bandwidth);
+                    url = best.uri;
+                }
+            }
+        }
+        Err(invalid_playlist("too many nested playlists".to_owned()))
+    }
+
+    // Downloads every segment into `{save_path}.segments`, decrypting them on
+    // the way, then joins them into `save_path`. Segments already on disk are
+    // kept if they belong to the same playlist, so a cancelled or failed
+    // download resumes like a ranged one.
+    // Progress is counted in bytes like other downloads. Sizes of segments
+    // aren't known upfront, so the total is estimated from the ones done.
+    pub(super) async fn download_hls_video<F: Fn(ProgressPayload) + Send + 'static>(
+        self: Arc<Self>,
+        video: &VideoPlayInfo,
+        save_path: &str,
+        connections: usize,
+        cancel_token: CancellationToken,
+        progress_handler: F,
+    ) -> Result<()> {
+        let (playlist_url, segments) = self.get_hls_segments(&video.rtmp_url_hdv).await?;
+        let mut keys = HashMap::new();
+        for key in segments.iter().filter_map(|segment| segment.key.as_ref()) {
+            if !keys.contains_key(&key.uri) {
+                let value = self.get_hls_resource(&key.uri, None).await?;
+                keys.insert(key.uri.clone(), value);
+            }
+        }
+
+        let segment_dir = PathBuf::from(format!("{}.segments", save_path));
+        prepare_segment_dir(
+            &segment_dir,
+            &SegmentState {
+                video_id: video.id,
+                playlist_url,
+                segment_count: segments.len(),
+            },
+        )?;
+        let mut pending = VecDeque::new();
+        let mut progress = SegmentProgress {
+            uuid: video.id.to_string(),
+            total_segments: segments.len() as u64,
+            ..Default::default()
+        };
+        for index in 0..segments.len() {
+            match fs::metadata(segment_path(&segment_dir, index)) {
+                Ok(metadata) => progress.add(metadata.len()),
+                Err(_) => pending.push_back(index),
+            }
+        }
+        progress_handler(progress.payload());
+
+        let connections = connections.clamp(1, pending.len().max(1));
+        tracing::info!(
+            "Downloading {} of {} HLS segments of {} with {} connections",
+            pending.len(),
+            segments.len(),
+            save_path,
+            connections
+        );
+        let segments = Arc::new(segments);
+        let keys = Arc::new(keys);
+        let queue = Arc::new(Mutex::new(pending));
+        let progress_handler = Arc::new(Mutex::new(progress_handler));
+        let progress = Arc::new(Mutex::new(progress));
+        let abort_token = cancel_token.child_token();
+        let mut tasks = JoinSet::new();
+        for _ in 0..connections {
+            let self_clone = self.clone();
+            let segment_dir = segment_dir.clone();
+            let segments = segments.clone();
+            let keys = keys.clone();
+            let queue = queue.clone();
+            let progress = progress.clone();
+            let progress_handler = progress_handler.clone();
+            let abort_token = abort_token.clone();
+            tasks.spawn(async move {
+                loop {
+                    let Some(index) = queue.lock().await.pop_front() else {
+                        return Ok(());
+                    };
+                    let segment = &segments[index];
+                    let data = tokio::select! {
+                        _ = abort_token.cancelled() => return Ok(()),
+                        data = self_clone.get_hls_resource(&segment.uri, segment.range) => data?,
+                    };
+                    let data = match &segment.key {
+                        Some(key) => decrypt_segment(&data, &keys[&key.uri], &segment.iv(key))?,
+                        None => data.to_vec(),
+                    };
+                    // written aside first so a crash never leaves half a segment
+                    let path = segment_path(&segment_dir, index);
+                    let tmp_path = path.with_extension("tmp");
+                    let size = data.len() as u64;
+                    fs::write(&tmp_path, data)?;
+                    fs::rename(&tmp_path, &path)?;
+
+                    let mut progress_guard = progress.lock().await;
+                    progress_guard.add(size);
+                    progress_handler.lock().await(progress_guard.payload());
+                }
+            });
+        }
+        let mut result = Ok(());
+        while let Some(task_result) = tasks.join_next().await {
+            if let Err(e) = task_result.map_err(AppError::from).and_then(|r| r) {
+                abort_token.cancel();
+                if result.is_ok() {
+                    result = Err(e);
+                }
+            }
+        }
+        result?;
+        if cancel_token.is_cancelled() {
+            tracing::info!("Download of {} cancelled", save_path);
+            return Err(AppError::VideoDownloadCancelled(save_path.to_owned()));
+        }
+
+        let segment_count = segments.len();
+        let save_path_clone = save_path.to_owned();
+        tokio::task::spawn_blocking(move || {
+            join_segments(&segment_dir, segment_count, &save_path_clone)
+        })
+        .await??;
+        tracing::info!("Successfully downloaded HLS video to {}", save_path);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prepare_segment_dir() -> Result<()> {
+        let segment_dir = std::env::temp_dir().join(format!("segments_{}", uuid::Uuid::new_v4()));
+        let state = SegmentState {
+            video_id: 1,
+            playlist_url: "https://example.com/index.m3u8".to_owned(),
+            segment_count: 2,
+        };
+        prepare_segment_dir(&segment_dir, &state)?;
+        fs::write(segment_path(&segment_dir, 0), b"segment")?;
+        prepare_segment_dir(&segment_dir, &state)?;
+        assert!(segment_path(&segment_dir, 0).exists());
+
+        let other = SegmentState {
+            video_id: 2,
+            ..state
+        };
+        prepare_segment_dir(&segment_dir, &other)?;
+        assert!(!segment_path(&segment_dir, 0).exists());
+        assert_eq!(
+            SegmentState::load(&segment_dir.join(SEGMENT_STATE_FILE_NAME)),
+            Some(other)
+        );
+        fs::remove_dir_all(&segment_dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_master_playlist() -> Result<()> {
+        let base = Url::parse("https://example.com/video/index.m3u8").unwrap();
+        let content = "#EXTM3U
+#EXT-X-STREAM-INF:BANDWIDTH=800000,RESOLUTION=640x360,CODECS=\"avc1.4d401e,mp4a.40.2\"
+low/index.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=2400000,RESOLUTION=1280x720
+https://cdn.example.com/high.m3u8
+";
+        let Playlist::Master(variants) = parse_playlist(&base, content)? else {
+            panic!("expected a master playlist");
+        };
+        assert_eq!(variants.len(), 2);
+        assert_eq!(variants[0].uri, "https://example.com/video/low/index.m3u8");
+        assert_eq!(variants[0].bandwidth, 800000);
+        assert_eq!(variants[1].uri, "https://cdn.example.com/high.m3u8");
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_media_playlist() -> Result<()> {
+        let base = Url::parse("https://example.com/video/index.m3u8").unwrap();
+        let content = "#EXTM3U
+#EXT-X-TARGETDURATION:10
+#EXT-X-MEDIA-SEQUENCE:7
+#EXTINF:10.0,
+0.ts
+#EXT-X-KEY:METHOD=AES-128,URI=\"key?id=1,2\"
+#EXTINF:10.0,
+1.ts
+#EXT-X-KEY:METHOD=AES-128,URI=\"/key2\",IV=0x000000000000000000000000000000ff
+#EXTINF:10.0,
+2.ts
+#EXT-X-ENDLIST
+";
+        let Playlist::Media(segments) = parse_playlist(&base, content)? else {
+            panic!("expected a media playlist");
+        };
+        assert_eq!(segments.len(), 3);
+        assert_eq!(segments[0].uri, "https://example.com/video/0.ts");
+        assert_eq!(segments[0].key, None);
+
+        let key = segments[1].key.as_ref().unwrap();
+        assert_eq!(key.uri, "https://example.com/video/key?id=1,2");
+        // the IV defaults to the media sequence number
+        let mut iv = [0; 16];
+        iv[15] = 8;
+        assert_eq!(segments[1].iv(key), iv);
+
+        let key = segments[2].key.as_ref().unwrap();
+        assert_eq!(key.uri, "https://example.com/key2");
+        iv[15] = 0xff;
+        assert_eq!(segments[2].iv(key), iv);
+
+        let content = "#EXTM3U
+#EXT-X-MAP:URI=\"init.mp4\",BYTERANGE=\"720@0\"
+#EXT-X-BYTERANGE:1000@720
+main.mp4
+#EXT-X-BYTERANGE:500
+main.mp4
+";
+        let Playlist::Media(segments) = parse_playlist(&base, content)? else {
+            panic!("expected a media playlist");
+        };
+        let ranges: Vec<_> = segments.iter().map(|segment| segment.range).collect();
+        assert_eq!(
+            ranges,
+            [
+                Some(ByteRange {
+                    length: 720,
+                    offset: 0
+                }),
+                Some(ByteRange {
+                    length: 1000,
+                    offset: 720
+                }),
+                Some(ByteRange {
+                    length: 500,
+                    offset: 1720
+                }),
+            ]
+        );
+        assert_eq!(ranges[2].unwrap().header(), "bytes=1720-2219");
+        // an offset can only be left out after a range of the same resource
+        let content = "#EXTM3U\n#EXT-X-BYTERANGE:500\nmain.mp4\n";
+        assert!(parse_playlist(&base, content).is_err());
+        // the media sequence number is no IV for an init section
+        let content =
+            "#EXTM3U\n#EXT-X-KEY:METHOD=AES-128,URI=\"key\"\n#EXT-X-MAP:URI=\"init.mp4\"\n0.m4s\n";
+        assert!(parse_playlist(&base, content).is_err());
+
+        let content = "#EXTM3U\n#EXT-X-KEY:METHOD=SAMPLE-AES,URI=\"key\"\n0.ts\n";
+        assert!(parse_playlist(&base, content).is_err());
+        assert!(is_hls_url("https://example.com/video/index.m3u8?token=1"));
+        assert!(!is_hls_url("https://example.com/video.mp4"));
+        Ok(())
+    }
+}
@@ -14,8 +14,8 @@ use tauri::Url;
 use crate::{
     error::{AppError, Result},
     model::{
-        ConfirmChunkUploadResult, File, JBoxErrorMessage, JBoxLoginInfo, JboxLoginResult,
-        PersonalSpaceInfo, ProgressPayload, StartChunkUploadContext,
+        AuthService, ConfirmChunkUploadResult, File, JBoxErrorMessage, JBoxLoginInfo,
+        JboxLoginResult, PersonalSpaceInfo, ProgressPayload, StartChunkUploadContext,
     },
     utils,
 };
@@ -30,6 +30,9 @@ impl Client {
             .get_request(JBOX_LOGIN_URL, None::<&str>)
             .await?
             .error_for_status()?;
+        if Client::is_redirected_to_login(&resp) {
+            return Err(self.report_unauthorized(AuthService::Jaccount));
+        }
         let re = Regex::new(r"code=(.+?)&state=").unwrap();
         let url = resp.url().to_string();
         let Some(captures) = re.captures(&url) else {
@@ -1,12 +1,20 @@
 use reqwest::cookie::Jar;
-use std::sync::Arc;
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex},
+};
 use tokio::sync::RwLock;
 
+use crate::model::AuthService;
+use oauth::{OAuthSession, TokenRefreshListener};
+
 pub mod annual;
 pub mod basic;
 mod common;
 pub mod constants;
+pub mod hls;
 pub mod jbox;
+pub mod oauth;
 pub mod video;
 
 pub struct Client {
@@ -14,4 +22,14 @@ pub struct Client {
     jar: Arc<Jar>,
     base_url: RwLock<String>,
     token: RwLock<String>,
+    auth_listener: std::sync::RwLock<Option<AuthListener>>,
+    // services already reported, so parallel requests notify only once
+    auth_failures: Mutex<HashSet<AuthService>>,
+    oauth_session: std::sync::RwLock<Option<OAuthSession>>,
+    // only one refresh at a time, refresh tokens may be single use
+    oauth_refresh_lock: tokio::sync::Mutex<()>,
+    token_refresh_listener: std::sync::RwLock<Option<TokenRefreshListener>>,
 }
+
+// Called when a service rejects the stored credentials
+pub type AuthListener = Arc<dyn Fn(AuthService) + Send + Sync>;
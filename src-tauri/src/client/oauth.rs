@@ -0,0 +1,195 @@
+use std::sync::Arc;
+
+use reqwest::StatusCode;
+
+use super::Client;
+use crate::{
+    error::{AppError, Result},
+    model::{AuthService, OAuthToken},
+    utils,
+};
+
+// Refresh this long before canvas considers the token expired
+const REFRESH_MARGIN_SECS: i64 = 5 * 60;
+
+// Called with every token obtained by a refresh so it can be persisted
+pub type TokenRefreshListener = Arc<dyn Fn(OAuthSession) + Send + Sync>;
+
+// Access token obtained with a developer key, refreshed automatically
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OAuthSession {
+    pub client_id: String,
+    pub client_secret: String,
+    pub access_token: String,
+    pub refresh_token: String,
+    // unix timestamp, 0 if the token doesn't expire
+    pub expires_at: i64,
+    // callers may still pass the token which was just replaced
+    pub replaced_token: Option<String>,
+}
+
+impl OAuthSession {
+    fn expires_soon(&self) -> bool {
+        self.expires_at > 0
+            && chrono::Utc::now().timestamp() + REFRESH_MARGIN_SECS >= self.expires_at
+    }
+
+    fn owns(&self, token: &str) -> bool {
+        token == self.access_token || self.replaced_token.as_deref() == Some(token)
+    }
+}
+
+pub fn expires_at(expires_in: Option<i64>) -> i64 {
+    match expires_in {
+        Some(expires_in) if expires_in > 0 => chrono::Utc::now().timestamp() + expires_in,
+        _ => 0,
+    }
+}
+
+impl Client {
+    pub fn set_oauth_session(&self, session: Option<OAuthSession>) {
+        *self.oauth_session.write().unwrap() = session;
+    }
+
+    pub fn get_oauth_session(&self) -> Option<OAuthSession> {
+        self.oauth_session.read().unwrap().clone()
+    }
+
+    pub fn set_token_refresh_listener(&self, listener: TokenRefreshListener) {
+        *self.token_refresh_listener.write().unwrap() = Some(listener);
+    }
+
+    pub async fn get_oauth_authorize_url(
+        &self,
+        client_id: &str,
+        redirect_uri: &str,
+        state: &str,
+    ) -> Result<String> {
+        let url = reqwest::Url::parse_with_params(
+            &format!("{}/login/oauth2/auth", self.base_url.read().await),
+            &[
+                ("client_id", client_id),
+                ("response_type", "code"),
+                ("redirect_uri", redirect_uri),
+                ("state", state),
+            ],
+        )
+        .map_err(|e| AppError::OAuth(e.to_string()))?;
+        Ok(url.to_string())
+    }
+
+    async fn request_oauth_token(&self, form: &[(&str, &str)]) -> Result<OAuthToken> {
+        let url = format!("{}/login/oauth2/token", self.base_url.read().await);
+        let response = self.cli.post(&url).form(form).send().await?;
+        let status = response.status();
+        // canvas answers 400 or 401 when the code or refresh token is invalid or revoked
+        if matches!(status, StatusCode::BAD_REQUEST | StatusCode::UNAUTHORIZED) {
+            let body = response.text().await?;
+            tracing::error!("OAuth token request rejected: {} {}", status, body);
+            return Err(AppError::OAuth(format!(
+                "token request rejected ({})",
+                status
+            )));
+        }
+        let body = response.error_for_status()?.bytes().await?;
+        utils::parse_json(&body)
+    }
+
+    pub async fn exchange_oauth_code(
+        &self,
+        client_id: &str,
+        client_secret: &str,
+        redirect_uri: &str,
+        code: &str,
+    ) -> Result<OAuthToken> {
+        self.request_oauth_token(&[
+            ("grant_type", "authorization_code"),
+            ("client_id", client_id),
+            ("client_secret", client_secret),
+            ("redirect_uri", redirect_uri),
+            ("code", code),
+        ])
+        .await
+    }
+
+    async fn refresh_oauth_session(&self, session: &OAuthSession) -> Result<OAuthSession> {
+        let token = self
+            .request_oauth_token(&[
+                ("grant_type", "refresh_token"),
+                ("client_id", &session.client_id),
+                ("client_secret", &session.client_secret),
+                ("refresh_token", &session.refresh_token),
+            ])
+            .await?;
+        Ok(OAuthSession {
+            access_token: token.access_token,
+            // canvas keeps the refresh token unless it sends a new one
+            refresh_token: token
+                .refresh_token
+                .unwrap_or_else(|| session.refresh_token.clone()),
+            expires_at: expires_at(token.expires_in),
+            replaced_token: Some(session.access_token.clone()),
+            ..session.clone()
+        })
+    }
+
+    // The token to send for a request made with `token`, refreshing the OAuth
+    // session first if `token` belongs to it and is about to expire
+    pub(super) async fn canvas_token(&self, token: &str) -> Result<String> {
+        match self.oauth_session.read().unwrap().as_ref() {
+            Some(session) if session.owns(token) => {
+                if !session.expires_soon() {
+                    return Ok(session.access_token.clone());
+                }
+            }
+            _ => return Ok(token.to_owned()),
+        }
+
+        let _guard = self.oauth_refresh_lock.lock().await;
+        // another request may have refreshed while we waited for the lock
+        let Some(session) = self.oauth_session.read().unwrap().clone() else {
+            return Ok(token.to_owned());
+        };
+        if !session.expires_soon() {
+            return Ok(session.access_token);
+        }
+        let session = match self.refresh_oauth_session(&session).await {
+            Ok(session) => session,
+            Err(AppError::OAuth(message)) => {
+                tracing::error!("Failed to refresh OAuth token: {}", message);
+                return Err(self.report_unauthorized(AuthService::Canvas));
+            }
+            Err(e) => return Err(e),
+        };
+        tracing::info!("OAuth token refreshed");
+        self.set_oauth_session(Some(session.clone()));
+        if let Some(listener) = self.token_refresh_listener.read().unwrap().as_ref() {
+            listener(session.clone());
+        }
+        Ok(session.access_token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_canvas_token_passthrough() -> Result<()> {
+        let client = Client::new();
+        assert_eq!(client.canvas_token("manual").await?, "manual");
+
+        let session = OAuthSession {
+            access_token: "new".to_owned(),
+            expires_at: expires_at(Some(3600)),
+            replaced_token: Some("old".to_owned()),
+            ..Default::default()
+        };
+        client.set_oauth_session(Some(session));
+        // tokens of the session are swapped for the current one, others are kept
+        assert_eq!(client.canvas_token("old").await?, "new");
+        assert_eq!(client.canvas_token("new").await?, "new");
+        assert_eq!(client.canvas_token("manual").await?, "manual");
+        Ok(())
+    }
+}
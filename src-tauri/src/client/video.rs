@@ -1,13 +1,17 @@
 use std::{
-    collections::HashMap,
-    fs::File,
+    collections::{BTreeMap, BTreeSet, HashMap, VecDeque},
+    fs::{self, OpenOptions},
+    future::Future,
     io::Write,
+    path::Path,
     sync::Arc,
-    time::{SystemTime, UNIX_EPOCH},
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 use base64::{engine::general_purpose::STANDARD, Engine};
+use bytes::Bytes;
 use md5::{Digest, Md5};
+use rand::Rng;
 use regex::Regex;
 use reqwest::{
     cookie::CookieStore,
@@ -20,31 +24,155 @@ use select::{
     node::Node,
     predicate::{Attr, Name},
 };
-use serde::{de::DeserializeOwned, Serialize};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_json::Value;
 use tauri::Url;
 use tokio::{sync::Mutex, task::JoinSet};
+use tokio_util::sync::CancellationToken;
 
 use super::{
     constants::{
         AUTH_URL, CANVAS_LOGIN_URL, EXPRESS_LOGIN_URL, MY_SJTU_URL, VIDEO_BASE_URL,
         VIDEO_LOGIN_URL, VIDEO_OAUTH_KEY_URL,
     },
+    hls::is_hls_url,
     Client,
 };
 use crate::{
     client::constants::{
-        OAUTH_PATH, OAUTH_RANDOM, OAUTH_RANDOM_P1, OAUTH_RANDOM_P1_VAL, OAUTH_RANDOM_P2,
-        OAUTH_RANDOM_P2_VAL, VIDEO_CHUNK_SIZE, VIDEO_INFO_URL,
+        VIDEO_CHUNK_RETRIES, VIDEO_CHUNK_SIZE, VIDEO_INFO_URL, VIDEO_PLAY_PAGE_URL,
     },
     error::{AppError, Result},
     model::{
-        CanvasVideo, CanvasVideoResponse, GetCanvasVideoInfoResponse, ItemPage, ProgressPayload,
-        Subject, VideoCourse, VideoInfo, VideoPlayInfo,
+        AuthService, CanvasVideo, CanvasVideoResponse, GetCanvasVideoInfoResponse, ItemPage,
+        ProgressPayload, Subject, VideoCourse, VideoInfo, VideoPlayInfo,
     },
-    utils::{self, write_file_at_offset},
+    utils::{self, mask_secret, write_file_at_offset},
 };
 
+// The video site checks the md5 of the request path with all parameters,
+// including the `oauth-*` headers, sorted by name
+fn sign_video_request(url: &str, params: &BTreeMap<String, String>) -> String {
+    let path = Url::parse(url)
+        .map(|url| url.path().to_owned())
+        .unwrap_or_default();
+    let query = params
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, value))
+        .collect::<Vec<_>>()
+        .join("&");
+    format!("{:x}", Md5::digest(format!("{}?{}", path, query)))
+}
+
+// Like `oauth_ABCDE=ABCDEFGH`
+fn random_oauth_pair() -> (String, String) {
+    let letters = |len: usize| -> String {
+        let mut rng = rand::thread_rng();
+        (0..len)
+            .map(|_| rng.gen_range(b'A'..=b'Z') as char)
+            .collect()
+    };
+    (format!("oauth_{}", letters(5)), letters(8))
+}
+
+// Rejected signatures and unknown consumer keys come back as an error object
+// or a login page instead of the video
+fn parse_video_info(bytes: &[u8]) -> Result<VideoInfo> {
+    let is_video = serde_json::from_slice::<Value>(bytes)
+        .is_ok_and(|value| value.get("id").is_some_and(|id| !id.is_null()));
+    if !is_video {
+        tracing::warn!(
+            "Video info was rejected: {}",
+            String::from_utf8_lossy(&bytes[..bytes.len().min(200)])
+        );
+        return Err(AppError::VideoSignatureRejected);
+    }
+    utils::parse_json(bytes)
+}
+
+// Chunks of an unfinished download, saved next to the partial file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct VideoDownloadState {
+    // states of older versions have no id and are started over
+    #[serde(default)]
+    video_id: i64,
+    url: String,
+    size: u64,
+    chunk_size: u64,
+    completed: BTreeSet<u64>,
+}
+
+impl VideoDownloadState {
+    fn new(video_id: i64, url: &str, size: u64) -> Self {
+        Self {
+            video_id,
+            url: url.to_owned(),
+            size,
+            chunk_size: VIDEO_CHUNK_SIZE,
+            completed: Default::default(),
+        }
+    }
+
+    // Play urls of the same video may change between sessions, so a state is
+    // matched by the id of the video and the size of the file
+    fn load(path: &str, video_id: i64, size: u64) -> Option<Self> {
+        let state: Self = serde_json::from_slice(&fs::read(path).ok()?).ok()?;
+        (state.video_id == video_id && state.size == size && state.chunk_size > 0).then_some(state)
+    }
+
+    fn save(&self, path: &str) -> Result<()> {
+        fs::write(path, serde_json::to_vec(self)?)?;
+        Ok(())
+    }
+
+    fn chunk_count(&self) -> u64 {
+        self.size.div_ceil(self.chunk_size)
+    }
+
+    // inclusive byte range of a chunk
+    fn chunk_range(&self, index: u64) -> (u64, u64) {
+        let begin = index * self.chunk_size;
+        let end = (begin + self.chunk_size).min(self.size) - 1;
+        (begin, end)
+    }
+
+    fn pending_chunks(&self) -> Vec<u64> {
+        (0..self.chunk_count())
+            .filter(|index| !self.completed.contains(index))
+            .collect()
+    }
+
+    fn completed_bytes(&self) -> u64 {
+        self.completed
+            .iter()
+            .map(|index| {
+                let (begin, end) = self.chunk_range(*index);
+                end - begin + 1
+            })
+            .sum()
+    }
+}
+
+// Retries a request of a video download with backoff, so a flaky connection
+// doesn't fail the whole download
+pub(super) async fn with_retries<T, F, Fut>(what: &str, request: F) -> Result<T>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let mut attempt = 1;
+    loop {
+        match request().await {
+            Err(e) if attempt < VIDEO_CHUNK_RETRIES => {
+                tracing::warn!("Retrying {} (attempt {}): {:?}", what, attempt, e);
+                tokio::time::sleep(Duration::from_millis(500 * attempt as u64)).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
 // Apis here are for course video
 // We take references from: https://github.com/prcwcy/sjtu-canvas-video-download/blob/master/sjtu_canvas_video.py
 impl Client {
@@ -93,11 +221,8 @@ impl Client {
         self.jar
             .add_cookie_str(cookie, &Url::parse(AUTH_URL).unwrap());
         let response = self.get_request(VIDEO_LOGIN_URL, None::<&str>).await?;
-        let url = response.url();
-        if let Some(domain) = url.domain() {
-            if domain == "jaccount.sjtu.edu.cn" {
-                return Err(AppError::LoginError);
-            }
+        if Client::is_redirected_to_login(&response) {
+            return Err(self.report_unauthorized(AuthService::Jaccount));
         }
         if let Some(cookies) = self.jar.cookies(&Url::parse(VIDEO_BASE_URL).unwrap()) {
             if let Ok(cookies) = cookies.to_str() {
@@ -111,11 +236,8 @@ impl Client {
         self.jar
             .add_cookie_str(cookie, &Url::parse(AUTH_URL).unwrap());
         let response = self.get_request(CANVAS_LOGIN_URL, None::<&str>).await?;
-        let url = response.url();
-        if let Some(domain) = url.domain() {
-            if domain == "jaccount.sjtu.edu.cn" {
-                return Err(AppError::LoginError);
-            }
+        if Client::is_redirected_to_login(&response) {
+            return Err(self.report_unauthorized(AuthService::Jaccount));
         }
         Ok(())
     }
@@ -254,13 +376,12 @@ impl Client {
                 // &courId=
                 // &ltiCourseId=
                 // &courseName=
-                tracing::info!("Header: {:?}", location_header);
                 let params: Vec<_> = location_header.to_str()?.split(&['&', '?'][..]).collect();
                 let canvas_course_id = params.iter().find_map(|s| s.strip_prefix("courId="));
                 // tokenId
                 let token_id = params.iter().find_map(|s| s.strip_prefix("tokenId="));
                 tracing::info!("Course Id: {:?}", canvas_course_id);
-                tracing::info!("Token Id: {:?}", token_id);
+                tracing::info!("Token Id: {:?}", token_id.map(mask_secret));
                 Ok((
                     canvas_course_id.map(|s| s.to_owned()),
                     token_id.map(|s| s.to_owned()),
@@ -281,7 +402,7 @@ impl Client {
         }
 
         tracing::info!("canvas_course_id: {:?}", canvas_course_id);
-        tracing::info!("token_id: {:?}", token_id);
+        tracing::info!("token_id: {:?}", token_id.as_deref().map(mask_secret));
 
         // Get Token from token_id
         // https://v.sjtu.edu.cn/jy-application-canvas-sjtu/lti3/getAccessTokenByTokenId?tokenId=
@@ -293,7 +414,6 @@ impl Client {
 
         let resp = self.cli.get(&url).send().await?;
         let body = resp.text().await?;
-        tracing::info!("body: {}", body);
 
         let json: Value = serde_json::from_str(&body)?;
         let token = json["data"]["token"]
@@ -302,7 +422,7 @@ impl Client {
                 "Token not found",
             )))?;
 
-        tracing::info!("token: {}", token);
+        tracing::info!("token: {}", mask_secret(token));
 
         let canvas_course_id = canvas_course_id.unwrap();
         let url =
@@ -368,18 +488,6 @@ impl Client {
         Ok(courses.remove(0))
     }
 
-    fn get_oauth_signature(
-        &self,
-        video_id: i64,
-        oauth_nonce: &str,
-        oauth_consumer_key: &str,
-    ) -> String {
-        let signature_string = format!("/app/system/resource/vodVideo/getvideoinfos?id={}&oauth-consumer-key={}&oauth-nonce={}&oauth-path={}&{}&playTypeHls=true",
-        video_id, oauth_consumer_key, oauth_nonce, OAUTH_PATH, OAUTH_RANDOM);
-        let md5 = Md5::digest(signature_string);
-        format!("{:x}", md5)
-    }
-
     fn get_oauth_nonce(&self) -> String {
         let now = SystemTime::now();
         let since_the_epoch = now.duration_since(UNIX_EPOCH).expect("Time went backwards");
@@ -398,6 +506,18 @@ impl Client {
         Ok(response)
     }
 
+    // For the local media server, the range requested by the player is passed on
+    pub async fn get_video_stream(&self, url: &str, range: Option<&str>) -> Result<Response> {
+        let mut request = self
+            .cli
+            .get(url)
+            .header(REFERER, "https://courses.sjtu.edu.cn");
+        if let Some(range) = range {
+            request = request.header(RANGE, range);
+        }
+        Ok(request.send().await?)
+    }
+
     async fn get_download_video_size(&self, url: &str) -> Result<u64> {
         let resp = self.download_video_partial(url, 0, 0).await?;
         let range = resp.headers().get(CONTENT_RANGE);
@@ -415,22 +535,62 @@ impl Client {
         }
     }
 
+    async fn download_video_chunk(&self, url: &str, begin: u64, end: u64) -> Result<Bytes> {
+        let what = format!("bytes {}-{} of {}", begin, end, url);
+        with_retries(&what, || async {
+            let response = self.download_video_partial(url, begin, end).await?;
+            let status = response.status();
+            if status != StatusCode::PARTIAL_CONTENT && !(status == StatusCode::OK && begin == 0) {
+                return Err(AppError::VideoDownloadError(format!(
+                    "{}: status {}",
+                    what, status
+                )));
+            }
+            let bytes = response.bytes().await?;
+            if bytes.len() as u64 != end - begin + 1 {
+                return Err(AppError::VideoDownloadError(format!(
+                    "{}: got {} bytes",
+                    what,
+                    bytes.len()
+                )));
+            }
+            Ok(bytes)
+        })
+        .await
+    }
+
+    // Downloads into `{save_path}.part` and records finished chunks in a state
+    // file next to it, so an interrupted or cancelled download resumes where it
+    // stopped. The partial file is only renamed to `save_path` once complete.
+    // HLS playlists are handed to the segment downloader instead.
     pub async fn download_video<F: Fn(ProgressPayload) + Send + 'static>(
         self: Arc<Self>,
         video: &VideoPlayInfo,
         save_path: &str,
+        connections: usize,
+        cancel_token: CancellationToken,
         progress_handler: F,
     ) -> Result<()> {
-        let output_file = Arc::new(Mutex::new(File::create(save_path)?));
         let url = &video.rtmp_url_hdv;
+        if is_hls_url(url) {
+            return self
+                .download_hls_video(
+                    video,
+                    save_path,
+                    connections,
+                    cancel_token,
+                    progress_handler,
+                )
+                .await;
+        }
         let size = self.get_download_video_size(url).await?;
         let payload = ProgressPayload {
             uuid: video.id.to_string(),
             processed: 0,
             total: size,
         };
-        progress_handler(payload.clone());
         if size == 0 {
+            progress_handler(payload);
             tracing::warn!(
                 "try to download video as {}, but size is 0, can't download",
                 save_path
@@ -438,62 +598,106 @@ impl Client {
             return Err(AppError::VideoDownloadError(save_path.to_owned()));
         }
 
+        let part_path = format!("{}.part", save_path);
+        let state_path = format!("{}.download.json", save_path);
+        let state = match VideoDownloadState::load(&state_path, video.id, size) {
+            Some(state) if Path::new(&part_path).exists() => {
+                tracing::info!(
+                    "Resuming download of {} with {} chunks done",
+                    save_path,
+                    state.completed.len()
+                );
+                state
+            }
+            _ => {
+                // whatever is left belongs to another download
+                let _ = fs::remove_file(&part_path);
+                VideoDownloadState::new(video.id, url, size)
+            }
+        };
+        let output_file = Arc::new(Mutex::new(
+            OpenOptions::new()
+                .create(true)
+                .truncate(false)
+                .write(true)
+                .open(&part_path)?,
+        ));
+        let pending = state.pending_chunks();
+        let payload = ProgressPayload {
+            processed: state.completed_bytes(),
+            ..payload
+        };
+        progress_handler(payload.clone());
+
+        let connections = connections.clamp(1, pending.len().max(1));
+        tracing::info!(
+            "Downloading {} chunks of {} with {} connections",
+            pending.len(),
+            save_path,
+            connections
+        );
+        let queue = Arc::new(Mutex::new(VecDeque::from(pending)));
+        let state = Arc::new(Mutex::new(state));
         let progress_handler = Arc::new(Mutex::new(progress_handler));
         let payload = Arc::new(Mutex::new(payload));
-
-        let nproc = num_cpus::get();
-        tracing::info!("nproc: {}", nproc);
-        let chunk_size = size / nproc as u64;
+        // cancelled by the caller, or by us when a chunk fails for good
+        let abort_token = cancel_token.child_token();
         let mut tasks = JoinSet::new();
-        for i in 0..nproc {
-            let begin = i as u64 * chunk_size;
-            let end = if i == nproc - 1 {
-                size - 1
-            } else {
-                (i + 1) as u64 * chunk_size - 1
-            };
+        for _ in 0..connections {
             let self_clone = self.clone();
-            let save_path = save_path.to_owned();
-            let output_file = output_file.clone();
             let url = url.clone();
+            let state_path = state_path.clone();
+            let output_file = output_file.clone();
+            let queue = queue.clone();
+            let state = state.clone();
             let payload = payload.clone();
             let progress_handler = progress_handler.clone();
+            let abort_token = abort_token.clone();
             tasks.spawn(async move {
-                let mut current_begin = begin;
-                while current_begin < end {
-                    let response = self_clone
-                        .download_video_partial(
-                            &url,
-                            current_begin,
-                            current_begin + VIDEO_CHUNK_SIZE,
-                        )
-                        .await?;
-                    let status = response.status();
-                    if !(status == StatusCode::OK || status == StatusCode::PARTIAL_CONTENT) {
-                        tracing::error!("status not ok: {}", status);
-                        return Err(AppError::VideoDownloadError(save_path));
-                    }
-                    let bytes = response.bytes().await?;
-                    let read_bytes = bytes.len() as u64;
-                    tracing::info!("read_bytes: {:?}", read_bytes);
+                loop {
+                    let Some(index) = queue.lock().await.pop_front() else {
+                        return Ok(());
+                    };
+                    let (begin, end) = state.lock().await.chunk_range(index);
+                    let bytes = tokio::select! {
+                        _ = abort_token.cancelled() => return Ok(()),
+                        bytes = self_clone.download_video_chunk(&url, begin, end) => bytes?,
+                    };
                     {
                         let mut file = output_file.lock().await;
-                        write_file_at_offset(file.by_ref(), &bytes, current_begin)?;
-                        // release lock automatically after scope release
+                        write_file_at_offset(file.by_ref(), &bytes, begin)?;
+                    }
+                    {
+                        let mut state = state.lock().await;
+                        state.completed.insert(index);
+                        state.save(&state_path)?;
                     }
-
-                    current_begin += read_bytes;
 
                     let mut payload_guard = payload.lock().await;
-                    payload_guard.processed += read_bytes;
+                    payload_guard.processed += bytes.len() as u64;
                     progress_handler.lock().await(payload_guard.clone());
                 }
-                Ok(())
             });
         }
-        while let Some(result) = tasks.join_next().await {
-            result??;
+        let mut result = Ok(());
+        while let Some(task_result) = tasks.join_next().await {
+            if let Err(e) = task_result.map_err(AppError::from).and_then(|r| r) {
+                abort_token.cancel();
+                if result.is_ok() {
+                    result = Err(e);
+                }
+            }
+        }
+        result?;
+        if cancel_token.is_cancelled() {
+            tracing::info!("Download of {} cancelled", save_path);
+            return Err(AppError::VideoDownloadCancelled(save_path.to_owned()));
         }
+
+        output_file.lock().await.sync_all()?;
+        drop(output_file);
+        fs::rename(&part_path, save_path)?;
+        let _ = fs::remove_file(&state_path);
         tracing::info!("Successfully downloaded video to {}", save_path);
         Ok(())
     }
@@ -519,26 +723,38 @@ impl Client {
         Ok(resp.data)
     }
 
+    // Fails with `VideoSignatureRejected` when the consumer key or the session
+    // isn't accepted any more
     pub async fn get_video_info(
         &self,
         video_id: i64,
         oauth_consumer_key: &str,
     ) -> Result<VideoInfo> {
-        let mut form_data = HashMap::new();
+        let mut form_data = BTreeMap::new();
+        form_data.insert("id".to_owned(), video_id.to_string());
+        form_data.insert("playTypeHls".to_owned(), "true".to_owned());
+        // the player page adds two random pairs to every signed request
+        for _ in 0..2 {
+            let (key, value) = random_oauth_pair();
+            form_data.insert(key, value);
+        }
+
         let oauth_nonce = self.get_oauth_nonce();
-        let oauth_signature = self.get_oauth_signature(video_id, &oauth_nonce, oauth_consumer_key);
+        let oauth_path = STANDARD.encode(VIDEO_PLAY_PAGE_URL);
+        let mut signed_params = form_data.clone();
+        signed_params.insert(
+            "oauth-consumer-key".to_owned(),
+            oauth_consumer_key.to_owned(),
+        );
+        signed_params.insert("oauth-nonce".to_owned(), oauth_nonce.clone());
+        signed_params.insert("oauth-path".to_owned(), oauth_path.clone());
+        let oauth_signature = sign_video_request(VIDEO_INFO_URL, &signed_params);
 
         tracing::debug!("oauth_nonce: {}", oauth_nonce);
         tracing::debug!("oauth_signature: {}", oauth_signature);
-        tracing::debug!("oauth_consumer_key: {}", oauth_consumer_key);
+        tracing::debug!("oauth_consumer_key: {}", mask_secret(oauth_consumer_key));
         tracing::debug!("video_id: {}", video_id);
 
-        let video_id_str = video_id.to_string();
-        form_data.insert("playTypeHls", "true");
-        form_data.insert("id", &video_id_str);
-        form_data.insert(OAUTH_RANDOM_P1, OAUTH_RANDOM_P1_VAL);
-        form_data.insert(OAUTH_RANDOM_P2, OAUTH_RANDOM_P2_VAL);
-
         let response = self
             .cli
             .post(VIDEO_INFO_URL)
@@ -546,14 +762,19 @@ impl Client {
             .header(ACCEPT, "application/json")
             .header("oauth-consumer-key", oauth_consumer_key)
             .header("oauth-nonce", oauth_nonce)
-            .header("oauth-path", OAUTH_PATH)
+            .header("oauth-path", oauth_path)
             .header("oauth-signature", oauth_signature)
             .send()
-            .await?
-            .error_for_status()?;
-        let bytes = response.bytes().await?;
-        let video = utils::parse_json(&bytes)?;
-        Ok(video)
+            .await?;
+        if matches!(
+            response.status(),
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN
+        ) || Client::is_redirected_to_login(&response)
+        {
+            return Err(AppError::VideoSignatureRejected);
+        }
+        let bytes = response.error_for_status()?.bytes().await?;
+        parse_video_info(&bytes)
     }
 }
 
@@ -586,7 +807,7 @@ mod tests {
         };
         let cli_cloned = cli.clone();
         cli_cloned
-            .download_video(&video_info, save_path, |_| {})
+            .download_video(&video_info, save_path, 4, CancellationToken::new(), |_| {})
             .await?;
 
         // download original video
@@ -602,16 +823,72 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_video_download_state() -> Result<()> {
+        let size = 2 * VIDEO_CHUNK_SIZE + 10;
+        let mut state = VideoDownloadState::new(1, "url", size);
+        assert_eq!(state.pending_chunks(), vec![0, 1, 2]);
+        assert_eq!(state.chunk_range(0), (0, VIDEO_CHUNK_SIZE - 1));
+        assert_eq!(state.chunk_range(2), (2 * VIDEO_CHUNK_SIZE, size - 1));
+
+        state.completed.extend([0, 2]);
+        assert_eq!(state.pending_chunks(), vec![1]);
+        assert_eq!(state.completed_bytes(), VIDEO_CHUNK_SIZE + 10);
+
+        let path = std::env::temp_dir().join("test_video_download_state.json");
+        let path = path.to_str().unwrap();
+        state.save(path)?;
+        // another video or size means another file, which is downloaded from scratch
+        assert_eq!(VideoDownloadState::load(path, 1, size), Some(state));
+        assert_eq!(VideoDownloadState::load(path, 1, size + 1), None);
+        assert_eq!(VideoDownloadState::load(path, 2, size), None);
+        fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_video_info() -> Result<()> {
+        let video = VideoInfo {
+            id: 1,
+            ..Default::default()
+        };
+        assert_eq!(parse_video_info(&serde_json::to_vec(&video)?)?.id, 1);
+        assert!(matches!(
+            parse_video_info(br#"{"code":"401","msg":"signature error"}"#),
+            Err(AppError::VideoSignatureRejected)
+        ));
+        assert!(matches!(
+            parse_video_info(b"<html></html>"),
+            Err(AppError::VideoSignatureRejected)
+        ));
+        Ok(())
+    }
+
     #[test]
     fn test_get_oauth_signature() -> Result<()> {
-        let cli = Client::new();
         let oauth_nonce = "1709784720392";
-        let id = 3601811;
+        let id = "3601811";
         let oauth_consumer_key = "DADD2CA9923D5E31331C4B79B39A1E4B";
+        let params: BTreeMap<String, String> = [
+            ("id", id),
+            ("playTypeHls", "true"),
+            ("oauth_ABCDE", "ABCDEFGH"),
+            ("oauth_VWXYZ", "STUVWXYZ"),
+            ("oauth-consumer-key", oauth_consumer_key),
+            ("oauth-nonce", oauth_nonce),
+            ("oauth-path", &STANDARD.encode(VIDEO_PLAY_PAGE_URL)),
+        ]
+        .into_iter()
+        .map(|(key, value)| (key.to_owned(), value.to_owned()))
+        .collect();
         assert_eq!(
             "2b499a5303048d6522118e79711c5ee0",
-            cli.get_oauth_signature(id, oauth_nonce, oauth_consumer_key)
+            sign_video_request(VIDEO_INFO_URL, &params)
         );
+
+        let (key, value) = random_oauth_pair();
+        assert!(key.starts_with("oauth_") && key.len() == 11);
+        assert!(value.len() == 8 && value.chars().all(|c| c.is_ascii_uppercase()));
         Ok(())
     }
 }
@@ -1,6 +1,8 @@
 use std::io;
 use thiserror::Error;
 
+use crate::model::AuthService;
+
 #[derive(Error, Debug)]
 pub enum AppError {
     #[error("Network error: {0}")]
@@ -40,12 +42,50 @@ pub enum AppError {
     NotAllowedToCreateDefaultAccount,
     #[error("Mutex error")]
     MutexError,
-    #[error("Failed to open stdout")]
-    OpenStdoutError,
     #[error("Failed to open stderr")]
     OpenStderrError,
     #[error("Failed to download video {0}")]
     VideoDownloadError(String),
+    #[error("Download of video {0} was cancelled")]
+    VideoDownloadCancelled(String),
+    #[error("ffmpeg failed: {0}")]
+    FfmpegError(String),
+    #[error("ffmpeg was cancelled")]
+    FfmpegCancelled,
+    #[error("Speech to text failed: {0}")]
+    SpeechToText(String),
+    #[error("Failed to extract slides: {0}")]
+    SlideExtraction(String),
+    #[error("Media server error: {0}")]
+    MediaServer(String),
+    #[error("Invalid video name template: {0}")]
+    VideoNameTemplate(String),
+    #[error("Video site rejected the request signature")]
+    VideoSignatureRejected,
+    #[error("Video site session expired and couldn't be renewed: {0}, please log in with jAccount again")]
+    VideoSessionUnrecoverable(String),
+    #[error("Permission denied: {0}")]
+    PermissionDenied(String),
+    #[error("Course {0} not found")]
+    CourseNotFound(i64),
+    #[error("Quiz result unavailable: {0}")]
+    QuizResultUnavailable(String),
+    #[error("Failed to grade group: {0}")]
+    GroupGrading(String),
+    #[error("Invalid recipients: {0}")]
+    InvalidRecipients(String),
+    #[error("Secret store error: {0}")]
+    SecretStore(String),
+    #[error("Invalid config {0}: {1}")]
+    InvalidConfig(String, String),
+    #[error("Config version {0} is newer than supported version {1}, please upgrade the app")]
+    UnsupportedConfigVersion(u32, u32),
+    #[error("Invalid profile bundle: {0}")]
+    InvalidProfileBundle(String),
+    #[error("Credentials for {0:?} expired, please log in again")]
+    Unauthorized(AuthService),
+    #[error("OAuth error: {0}")]
+    OAuth(String),
 }
 
 impl serde::Serialize for AppError {
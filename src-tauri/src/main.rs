@@ -5,20 +5,26 @@ use std::sync::Arc;
 
 use error::Result;
 use model::{
-    Account, AccountInfo, AnnualReport, AppConfig, Assignment, CalendarEvent, CanvasVideo, Colors,
-    Course, DiscussionTopic, File, Folder, FullDiscussion, LogLevel, QRCodeScanResult,
-    RelationshipTopo, Subject, Submission, User, UserSubmissions, VideoAggregateParams,
-    VideoCourse, VideoInfo, VideoPlayInfo,
+    Account, AccountInfo, Announcement, AnnouncementChanges, AnnualReport, AppConfig, Assignment,
+    AssignmentOverride, Attachment, CalendarEvent, CanvasVideo, Colors, Conversation,
+    ConversationScope, Course, DiscussionTopic, DiscussionView, Enrollment, FfmpegJob,
+    FfmpegJobParams, File, Folder, FullDiscussion, GroupCategoryWithGroups, LectureMergeParams,
+    LogLevel, MergedCourses, Module, Page, ProfileBundlePreview, ProfileConflictStrategy,
+    ProfileImportReport, QRCodeScanResult, Quiz, QuizResult, QuizStatistics, QuizSubmission,
+    RelationshipTopo, Reply, SearchFilter, SearchHit, SearchIndexStatus, SecretStoreStatus,
+    Section, SlideExtractParams, SlideExtractResult, Subject, Submission, SubtitleParams,
+    SubtitleResult, User, UserSubmissions, VideoAggregateParams, VideoBatchReport, VideoCourse,
+    VideoInfo, VideoLibraryEntry, VideoPlayInfo,
 };
 
-use tauri::{api::path::config_dir, Runtime, Window};
+use tauri::{api::path::config_dir, Manager, Runtime, Window};
 use tracing::Level;
 use tracing_subscriber::{
     fmt::{self, writer::MakeWriterExt},
     layer::SubscriberExt,
 };
 
-use crate::app::App;
+use crate::app::{auth::ReloginHandler, session::Sessions, App};
 mod app;
 mod client;
 mod error;
@@ -29,7 +35,16 @@ mod utils;
 extern crate lazy_static;
 
 lazy_static! {
-    static ref APP: App = App::new();
+    static ref APP: Arc<App> = Arc::new(App::new());
+    static ref SESSIONS: Sessions = Default::default();
+}
+
+// The selected account is always served by APP so its config lives in one place
+async fn get_session(account: &Account) -> Result<Arc<App>> {
+    if APP.get_current_account().await == *account {
+        return Ok(APP.clone());
+    }
+    SESSIONS.get_or_load(account).await
 }
 
 #[tauri::command]
@@ -52,7 +67,42 @@ async fn run_video_aggregate<R: Runtime>(
     window: Window<R>,
     params: VideoAggregateParams,
 ) -> Result<i32> {
-    App::run_video_aggregate(window, &params).await
+    APP.run_video_aggregate(window, &params).await
+}
+
+#[tauri::command]
+async fn submit_ffmpeg_job(params: FfmpegJobParams) -> Result<String> {
+    APP.submit_ffmpeg_job(params)
+}
+
+#[tauri::command]
+fn list_ffmpeg_jobs() -> Vec<FfmpegJob> {
+    APP.list_ffmpeg_jobs()
+}
+
+#[tauri::command]
+fn cancel_ffmpeg_job(id: String) -> bool {
+    APP.cancel_ffmpeg_job(&id)
+}
+
+#[tauri::command]
+fn clear_ffmpeg_jobs() {
+    APP.clear_ffmpeg_jobs()
+}
+
+#[tauri::command]
+async fn generate_subtitles(params: SubtitleParams) -> Result<SubtitleResult> {
+    APP.generate_subtitles(&params).await
+}
+
+#[tauri::command]
+fn cancel_subtitle_generation(video_path: String) -> bool {
+    APP.cancel_subtitle_generation(&video_path)
+}
+
+#[tauri::command]
+async fn extract_slides(params: SlideExtractParams) -> Result<SlideExtractResult> {
+    APP.extract_slides(&params).await
 }
 
 #[tauri::command]
@@ -72,12 +122,123 @@ fn read_account_info() -> Result<AccountInfo> {
 
 #[tauri::command]
 async fn switch_account(account: Account) -> Result<()> {
-    APP.switch_account(&account).await
+    APP.switch_account(&account).await?;
+    // a separately loaded session of this account would go stale now
+    SESSIONS.unload(&account).await;
+    Ok(())
 }
 
 #[tauri::command]
 async fn delete_account(account: Account) -> Result<()> {
-    APP.delete_account(&account).await
+    APP.delete_account(&account).await?;
+    SESSIONS.unload(&account).await;
+    Ok(())
+}
+
+#[tauri::command]
+async fn export_profile_bundle(path: String, passphrase: Option<String>) -> Result<()> {
+    APP.export_profile_bundle(&path, passphrase.as_deref())
+        .await
+}
+
+#[tauri::command]
+fn preview_profile_bundle(path: String) -> Result<ProfileBundlePreview> {
+    App::preview_profile_bundle(&path)
+}
+
+#[tauri::command]
+async fn import_profile_bundle(
+    path: String,
+    strategy: ProfileConflictStrategy,
+    passphrase: Option<String>,
+) -> Result<ProfileImportReport> {
+    let report = APP
+        .import_profile_bundle(&path, strategy, passphrase.as_deref())
+        .await?;
+    // loaded sessions would keep serving the replaced configs
+    for imported in &report.imported {
+        SESSIONS.unload(&imported.to).await;
+    }
+    Ok(report)
+}
+
+#[tauri::command]
+async fn load_account_session(account: Account) -> Result<()> {
+    get_session(&account).await?;
+    Ok(())
+}
+
+#[tauri::command]
+async fn unload_account_session(account: Account) -> bool {
+    SESSIONS.unload(&account).await
+}
+
+#[tauri::command]
+async fn list_account_sessions() -> Vec<Account> {
+    let current_account = APP.get_current_account().await;
+    let mut accounts = vec![current_account.clone()];
+    for account in SESSIONS.loaded_accounts().await {
+        if account != current_account {
+            accounts.push(account);
+        }
+    }
+    accounts
+}
+
+#[tauri::command]
+async fn list_merged_courses() -> MergedCourses {
+    SESSIONS.list_merged_courses(&APP).await
+}
+
+#[tauri::command]
+async fn list_account_courses(account: Account) -> Result<Vec<Course>> {
+    get_session(&account).await?.list_courses().await
+}
+
+#[tauri::command]
+async fn list_account_course_assignments(
+    account: Account,
+    course_id: i64,
+) -> Result<Vec<Assignment>> {
+    get_session(&account)
+        .await?
+        .list_course_assignments(course_id)
+        .await
+}
+
+#[tauri::command]
+async fn list_account_course_files(account: Account, course_id: i64) -> Result<Vec<File>> {
+    get_session(&account)
+        .await?
+        .list_course_files(course_id)
+        .await
+}
+
+#[tauri::command]
+async fn list_account_announcements(
+    account: Account,
+    course_ids: Vec<i64>,
+    start_date: Option<String>,
+    end_date: Option<String>,
+) -> Result<Vec<Announcement>> {
+    get_session(&account)
+        .await?
+        .list_announcements(&course_ids, start_date.as_deref(), end_date.as_deref())
+        .await
+}
+
+#[tauri::command]
+async fn download_account_file<R: Runtime>(
+    window: Window<R>,
+    account: Account,
+    file: File,
+) -> Result<()> {
+    get_session(&account)
+        .await?
+        .download_file(&file, &|progress| {
+            let _ = window.emit("download://progress", progress);
+        })
+        .await
 }
 
 #[tauri::command]
@@ -127,6 +288,305 @@ async fn get_full_discussion(course_id: i64, topic_id: i64) -> Result<FullDiscus
     APP.get_full_discussion(course_id, topic_id).await
 }
 
+#[tauri::command]
+async fn export_discussion(course: Course, topic: DiscussionTopic) -> Result<String> {
+    APP.export_discussion(&course, &topic).await
+}
+
+#[tauri::command]
+async fn export_course_discussions(course: Course) -> Result<Vec<String>> {
+    APP.export_course_discussions(&course).await
+}
+
+#[tauri::command]
+async fn post_discussion_entry(
+    course_id: i64,
+    topic: DiscussionTopic,
+    message: String,
+    attachment_path: Option<String>,
+) -> Result<DiscussionView> {
+    APP.post_discussion_entry(course_id, &topic, &message, attachment_path.as_deref())
+        .await
+}
+
+#[tauri::command]
+async fn reply_discussion_entry(
+    course_id: i64,
+    topic: DiscussionTopic,
+    entry_id: i64,
+    message: String,
+    attachment_path: Option<String>,
+) -> Result<Reply> {
+    APP.reply_discussion_entry(
+        course_id,
+        &topic,
+        entry_id,
+        &message,
+        attachment_path.as_deref(),
+    )
+    .await
+}
+
+#[tauri::command]
+async fn rate_discussion_entry(
+    course_id: i64,
+    topic: DiscussionTopic,
+    entry_id: i64,
+    liked: bool,
+) -> Result<()> {
+    APP.rate_discussion_entry(course_id, &topic, entry_id, liked)
+        .await
+}
+
+#[tauri::command]
+async fn mark_discussion_entry_read(
+    course_id: i64,
+    topic_id: i64,
+    entry_id: i64,
+    read: bool,
+) -> Result<DiscussionTopic> {
+    APP.mark_discussion_entry_read(course_id, topic_id, entry_id, read)
+        .await
+}
+
+#[tauri::command]
+async fn mark_discussion_topic_read(course_id: i64, topic_id: i64) -> Result<DiscussionTopic> {
+    APP.mark_discussion_topic_read(course_id, topic_id).await
+}
+
+#[tauri::command]
+async fn list_modules(course_id: i64) -> Result<Vec<Module>> {
+    APP.list_modules(course_id).await
+}
+
+#[tauri::command]
+async fn list_pages(course_id: i64) -> Result<Vec<Page>> {
+    APP.list_pages(course_id).await
+}
+
+#[tauri::command]
+async fn get_page(course_id: i64, page_url: String) -> Result<Page> {
+    APP.get_page(course_id, &page_url).await
+}
+
+#[tauri::command]
+async fn export_course_modules(course: Course) -> Result<String> {
+    APP.export_course_modules(&course).await
+}
+
+#[tauri::command]
+async fn list_quizzes(course_id: i64) -> Result<Vec<Quiz>> {
+    APP.list_quizzes(course_id).await
+}
+
+#[tauri::command]
+async fn get_my_quiz_result(course_id: i64, quiz: Quiz) -> Result<QuizResult> {
+    APP.get_my_quiz_result(course_id, &quiz).await
+}
+
+#[tauri::command]
+async fn list_quiz_submissions(course_id: i64, quiz_id: i64) -> Result<Vec<QuizSubmission>> {
+    APP.list_quiz_submissions(course_id, quiz_id).await
+}
+
+#[tauri::command]
+async fn get_quiz_statistics(course_id: i64, quiz_id: i64) -> Result<Option<QuizStatistics>> {
+    APP.get_quiz_statistics(course_id, quiz_id).await
+}
+
+#[tauri::command]
+async fn export_quiz_submissions(course_id: i64, quiz_id: i64, save_name: String) -> Result<()> {
+    APP.export_quiz_submissions(course_id, quiz_id, &save_name)
+        .await
+}
+
+#[tauri::command]
+async fn list_conversations(scope: ConversationScope, page: u64) -> Result<Vec<Conversation>> {
+    APP.list_conversations(scope, page).await
+}
+
+#[tauri::command]
+async fn get_conversation(conversation_id: i64) -> Result<Conversation> {
+    APP.get_conversation(conversation_id).await
+}
+
+#[tauri::command]
+async fn mark_conversation_read(conversation_id: i64, read: bool) -> Result<()> {
+    APP.mark_conversation_read(conversation_id, read).await
+}
+
+#[tauri::command]
+async fn download_conversation_attachment<R: Runtime>(
+    window: Window<R>,
+    attachment: Attachment,
+) -> Result<()> {
+    APP.download_conversation_attachment(&attachment, &|progress| {
+        let _ = window.emit("download://progress", progress);
+    })
+    .await
+}
+
+#[tauri::command]
+async fn send_conversation(
+    course_id: i64,
+    recipient_ids: Vec<i64>,
+    subject: String,
+    body: String,
+    bulk: bool,
+) -> Result<Vec<Conversation>> {
+    APP.send_conversation(course_id, &recipient_ids, &subject, &body, bulk)
+        .await
+}
+
+#[tauri::command]
+async fn reply_conversation(conversation_id: i64, body: String) -> Result<Conversation> {
+    APP.reply_conversation(conversation_id, &body).await
+}
+
+#[tauri::command]
+async fn list_students_missing_assignment(course_id: i64, assignment_id: i64) -> Result<Vec<User>> {
+    APP.list_students_missing_assignment(course_id, assignment_id)
+        .await
+}
+
+#[tauri::command]
+async fn message_students_missing_assignment(
+    course_id: i64,
+    assignment_id: i64,
+    subject: String,
+    body: String,
+) -> Result<Vec<User>> {
+    APP.message_students_missing_assignment(course_id, assignment_id, &subject, &body)
+        .await
+}
+
+#[tauri::command]
+async fn list_course_groups(course_id: i64) -> Result<Vec<GroupCategoryWithGroups>> {
+    APP.list_course_groups(course_id).await
+}
+
+#[tauri::command]
+async fn list_group_users(group_id: i64) -> Result<Vec<User>> {
+    APP.list_group_users(group_id).await
+}
+
+#[tauri::command]
+async fn grade_group_submission(
+    course_id: i64,
+    assignment: Assignment,
+    group_id: i64,
+    grade: String,
+    comment: Option<String>,
+) -> Result<Vec<User>> {
+    APP.grade_group_submission(course_id, &assignment, group_id, &grade, comment.as_deref())
+        .await
+}
+
+#[tauri::command]
+async fn export_group_rosters(course_id: i64, save_name: String) -> Result<()> {
+    APP.export_group_rosters(course_id, &save_name).await
+}
+
+#[tauri::command]
+async fn list_course_sections(course_id: i64) -> Result<Vec<Section>> {
+    APP.list_course_sections(course_id).await
+}
+
+#[tauri::command]
+async fn list_section_enrollments(section_id: i64) -> Result<Vec<Enrollment>> {
+    APP.list_section_enrollments(section_id).await
+}
+
+#[tauri::command]
+async fn list_section_students(course_id: i64, section_ids: Vec<i64>) -> Result<Vec<User>> {
+    APP.list_section_students(course_id, &section_ids).await
+}
+
+#[tauri::command]
+async fn list_section_assignment_submissions(
+    section_ids: Vec<i64>,
+    assignment_id: i64,
+) -> Result<Vec<Submission>> {
+    APP.list_section_assignment_submissions(&section_ids, assignment_id)
+        .await
+}
+
+#[tauri::command]
+async fn add_assignment_section_override(
+    course_id: i64,
+    assignment_id: i64,
+    section_id: i64,
+    due_at: Option<String>,
+    unlock_at: Option<String>,
+    lock_at: Option<String>,
+) -> Result<AssignmentOverride> {
+    APP.add_assignment_section_override(
+        course_id,
+        assignment_id,
+        section_id,
+        due_at.as_deref(),
+        unlock_at.as_deref(),
+        lock_at.as_deref(),
+    )
+    .await
+}
+
+#[tauri::command]
+async fn list_announcements(
+    course_ids: Vec<i64>,
+    start_date: Option<String>,
+    end_date: Option<String>,
+) -> Result<Vec<Announcement>> {
+    APP.list_announcements(&course_ids, start_date.as_deref(), end_date.as_deref())
+        .await
+}
+
+#[tauri::command]
+async fn list_unread_announcements(start_date: Option<String>) -> Result<Vec<Announcement>> {
+    APP.list_unread_announcements(start_date.as_deref()).await
+}
+
+#[tauri::command]
+async fn mark_announcement_read(course_id: i64, topic_id: i64, read: bool) -> Result<()> {
+    APP.mark_announcement_read(course_id, topic_id, read).await
+}
+
+#[tauri::command]
+async fn download_announcement_attachments<R: Runtime>(
+    window: Window<R>,
+    announcement: Announcement,
+) -> Result<Vec<String>> {
+    APP.download_announcement_attachments(&announcement, |progress| {
+        let _ = window.emit("download://progress", progress);
+    })
+    .await
+}
+
+#[tauri::command]
+async fn check_announcement_updates(start_date: Option<String>) -> Result<AnnouncementChanges> {
+    APP.check_announcement_updates(start_date.as_deref()).await
+}
+
+#[tauri::command]
+async fn build_search_index(course_ids: Vec<i64>) -> Result<SearchIndexStatus> {
+    APP.build_search_index(&course_ids).await
+}
+
+#[tauri::command]
+async fn get_search_index_status() -> Result<SearchIndexStatus> {
+    APP.get_search_index_status().await
+}
+
+#[tauri::command]
+async fn clear_search_index() -> Result<()> {
+    APP.clear_search_index().await
+}
+
+#[tauri::command]
+async fn search(query: String, filter: SearchFilter, limit: usize) -> Result<Vec<SearchHit>> {
+    APP.search(&query, &filter, limit).await
+}
+
 #[tauri::command]
 async fn sync_course_files(course: Course) -> Result<Vec<File>> {
     APP.sync_course_files(&course).await
@@ -197,11 +657,31 @@ async fn get_config() -> AppConfig {
     APP.get_config().await
 }
 
+#[tauri::command]
+async fn login_with_oauth() -> Result<User> {
+    APP.login_with_oauth().await
+}
+
+#[tauri::command]
+async fn get_config_load_error() -> Option<String> {
+    APP.get_config_load_error()
+}
+
 #[tauri::command]
 async fn get_raw_config() -> Result<String> {
     APP.get_raw_config().await
 }
 
+#[tauri::command]
+async fn get_secret_store_status() -> SecretStoreStatus {
+    APP.get_secret_store_status()
+}
+
+#[tauri::command]
+async fn unlock_secret_store(passphrase: String) -> Result<SecretStoreStatus> {
+    APP.unlock_secret_store(&passphrase).await
+}
+
 #[tauri::command]
 fn check_path(path: String) -> bool {
     App::check_path(&path)
@@ -339,7 +819,7 @@ async fn get_colors() -> Result<Colors> {
 #[tauri::command]
 async fn save_config(config: AppConfig) -> Result<()> {
     tracing::info!("Receive config: {:?}", config);
-    APP.save_config(config).await
+    APP.save_edited_config(config).await
 }
 
 #[tauri::command]
@@ -528,6 +1008,21 @@ async fn prepare_proxy() -> Result<bool> {
     APP.prepare_proxy().await
 }
 
+#[tauri::command]
+async fn prepare_lecture_player(info: VideoInfo) -> Result<String> {
+    APP.prepare_lecture_player(&info).await
+}
+
+#[tauri::command]
+async fn get_video_library() -> Result<Vec<VideoLibraryEntry>> {
+    APP.get_video_library().await
+}
+
+#[tauri::command]
+async fn rescan_video_library() -> Result<Vec<VideoLibraryEntry>> {
+    APP.rescan_video_library().await
+}
+
 #[tauri::command]
 async fn stop_proxy() {
     APP.stop_proxy().await
@@ -536,16 +1031,62 @@ async fn stop_proxy() {
 #[tauri::command]
 async fn download_video<R: Runtime>(
     window: Window<R>,
+    info: VideoInfo,
     video: VideoPlayInfo,
     save_name: String,
-) -> Result<()> {
+) -> Result<String> {
     let window = Arc::new(window);
-    APP.download_video(&video, &save_name, move |progress| {
+    APP.download_lecture_video(&info, &video, &save_name, move |progress| {
         let _ = window.clone().emit("video_download://progress", progress);
     })
     .await
 }
 
+#[tauri::command]
+fn cancel_video_download(video_id: String) -> bool {
+    APP.cancel_video_download(&video_id)
+}
+
+#[tauri::command]
+async fn download_video_course<R: Runtime>(
+    window: Window<R>,
+    batch_id: String,
+    course: VideoCourse,
+) -> Result<VideoBatchReport> {
+    APP.download_video_course(&batch_id, &course, move |progress| {
+        let _ = window.emit("video_batch_download://progress", progress);
+    })
+    .await
+}
+
+#[tauri::command]
+async fn download_canvas_videos<R: Runtime>(
+    window: Window<R>,
+    batch_id: String,
+    videos: Vec<CanvasVideo>,
+) -> Result<VideoBatchReport> {
+    APP.download_canvas_videos(&batch_id, &videos, move |progress| {
+        let _ = window.emit("video_batch_download://progress", progress);
+    })
+    .await
+}
+
+#[tauri::command]
+fn cancel_video_batch(batch_id: String) -> bool {
+    APP.cancel_video_batch(&batch_id)
+}
+
+#[tauri::command]
+async fn merge_lecture_channels<R: Runtime>(
+    window: Window<R>,
+    params: LectureMergeParams,
+) -> Result<String> {
+    APP.merge_lecture_channels(&params, move |progress| {
+        let _ = window.emit("lecture_merge://progress", progress);
+    })
+    .await
+}
+
 // Apis for jbox
 #[tauri::command]
 async fn login_jbox() -> Result<()> {
@@ -615,21 +1156,100 @@ async fn main() -> Result<()> {
     setup_log()?;
     APP.init().await?;
     tauri::Builder::default()
+        .setup(|app| {
+            let handle = app.handle();
+            let handler: ReloginHandler = Arc::new(move |payload| {
+                let _ = handle.emit_all("auth://relogin", payload);
+            });
+            APP.watch_auth(handler.clone());
+            SESSIONS.set_relogin_handler(handler);
+            let handle = app.handle();
+            APP.watch_ffmpeg_jobs(Arc::new(move |job| {
+                let _ = handle.emit_all("ffmpeg_job://update", job);
+            }));
+            // validate stored credentials early instead of failing mid-operation,
+            // expired ones are reported to the frontend by the relogin event
+            tauri::async_runtime::spawn(async {
+                APP.check_credentials().await;
+            });
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             read_log_content,
             console_log,
             is_ffmpeg_installed,
             run_video_aggregate,
+            submit_ffmpeg_job,
+            list_ffmpeg_jobs,
+            cancel_ffmpeg_job,
+            clear_ffmpeg_jobs,
+            generate_subtitles,
+            cancel_subtitle_generation,
+            extract_slides,
             collect_relationship,
             switch_account,
             create_account,
             delete_account,
+            export_profile_bundle,
+            preview_profile_bundle,
+            import_profile_bundle,
+            load_account_session,
+            unload_account_session,
+            list_account_sessions,
+            list_merged_courses,
+            list_account_courses,
+            list_account_course_assignments,
+            list_account_course_files,
+            list_account_announcements,
+            download_account_file,
             read_account_info,
             list_accounts,
             list_courses,
             list_user_submissions,
             get_full_discussion,
             list_discussion_topics,
+            export_discussion,
+            export_course_discussions,
+            post_discussion_entry,
+            reply_discussion_entry,
+            rate_discussion_entry,
+            mark_discussion_entry_read,
+            mark_discussion_topic_read,
+            list_modules,
+            list_pages,
+            get_page,
+            export_course_modules,
+            list_quizzes,
+            get_my_quiz_result,
+            list_quiz_submissions,
+            get_quiz_statistics,
+            export_quiz_submissions,
+            list_conversations,
+            get_conversation,
+            mark_conversation_read,
+            download_conversation_attachment,
+            send_conversation,
+            reply_conversation,
+            list_students_missing_assignment,
+            message_students_missing_assignment,
+            list_course_groups,
+            list_group_users,
+            grade_group_submission,
+            export_group_rosters,
+            list_course_sections,
+            list_section_enrollments,
+            list_section_students,
+            list_section_assignment_submissions,
+            add_assignment_section_override,
+            list_announcements,
+            list_unread_announcements,
+            mark_announcement_read,
+            download_announcement_attachments,
+            check_announcement_updates,
+            build_search_index,
+            get_search_index_status,
+            clear_search_index,
+            search,
             sync_course_files,
             list_course_files,
             list_course_images,
@@ -652,7 +1272,11 @@ async fn main() -> Result<()> {
             get_folder_by_id,
             get_colors,
             get_config,
+            login_with_oauth,
+            get_config_load_error,
             get_raw_config,
+            get_secret_store_status,
+            unlock_secret_store,
             save_config,
             save_file_content,
             open_course_file,
@@ -690,8 +1314,16 @@ async fn main() -> Result<()> {
             get_video_info,
             get_canvas_video_info,
             download_video,
+            cancel_video_download,
+            download_video_course,
+            download_canvas_videos,
+            cancel_video_batch,
+            merge_lecture_channels,
             login_video_website,
             prepare_proxy,
+            prepare_lecture_player,
+            get_video_library,
+            rescan_video_library,
             stop_proxy,
             // Apis for jbox
             login_jbox,
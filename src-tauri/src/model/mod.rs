@@ -2,6 +2,8 @@ use std::collections::HashMap;
 
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
+use crate::utils::mask_secret;
+
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Course {
     pub id: i64,
@@ -64,6 +66,8 @@ pub struct File {
     pub mime_class: String,
     #[serde(default, rename = "content-type")]
     pub content_type: String,
+    #[serde(default)]
+    pub updated_at: Option<String>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -86,7 +90,7 @@ pub struct Folder {
     pub folders_count: i64,
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, Default)]
 pub enum Account {
     #[default]
     Default,
@@ -94,6 +98,78 @@ pub enum Account {
     Custom(String),
 }
 
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SecretStoreStatus {
+    // "keyring" or "encrypted_file", None while locked
+    pub backend: Option<String>,
+    pub unlocked: bool,
+    pub encrypted_file_exists: bool,
+    // credentials are only kept in memory until the store is unlocked
+    pub unsaved_secrets: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuthService {
+    Canvas,
+    Jaccount,
+    Video,
+    Jbox,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OAuthToken {
+    pub access_token: String,
+    #[serde(default)]
+    pub refresh_token: Option<String>,
+    #[serde(default)]
+    pub expires_in: Option<i64>,
+}
+
+// Payload of the auth://relogin event
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReloginPayload {
+    pub account: Account,
+    pub service: AuthService,
+}
+
+// What to do with bundled accounts which already exist on this machine
+#[derive(Default, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProfileConflictStrategy {
+    #[default]
+    Skip,
+    Overwrite,
+    // import under a new custom account name
+    Rename,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProfileAccountPreview {
+    pub account: Account,
+    pub exists: bool,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProfileBundlePreview {
+    pub exported_at: String,
+    pub current_account: Account,
+    pub includes_secrets: bool,
+    pub accounts: Vec<ProfileAccountPreview>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProfileImportedAccount {
+    pub from: Account,
+    pub to: Account,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProfileImportReport {
+    pub imported: Vec<ProfileImportedAccount>,
+    pub skipped: Vec<Account>,
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct AccountInfo {
     pub current_account: Account,
@@ -126,8 +202,14 @@ pub enum AccountType {
     JI,
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+// Bump together with a new step in app/config.rs whenever the on-disk format changes
+pub const CONFIG_VERSION: u32 = 1;
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub struct AppConfig {
+    // configs written before versioning have no version, i.e. 0
+    #[serde(default)]
+    pub version: u32,
     #[serde(default)]
     pub token: String,
     #[serde(default)]
@@ -150,11 +232,74 @@ pub struct AppConfig {
     pub course_assignment_file_bindings: HashMap<i64, Vec<File>>,
     #[serde(default)]
     pub show_alert_map: HashMap<String, bool>,
+    // developer key for the OAuth2 login, an alternative to pasting a token
+    #[serde(default)]
+    pub canvas_client_id: String,
+    #[serde(default)]
+    pub canvas_client_secret: String,
+    #[serde(default)]
+    pub canvas_refresh_token: String,
+    // unix timestamp, 0 for tokens which don't expire
+    #[serde(default)]
+    pub canvas_token_expires_at: i64,
+    // parallel range requests of a video download
+    #[serde(default = "default_video_download_connections")]
+    pub video_download_connections: usize,
+    // local speech-to-text command, `{input}` is replaced with a wav file and
+    // `{output}` with the path the engine writes `{output}.srt` to
+    #[serde(default)]
+    pub stt_command: String,
+    // where lecture recordings are saved relative to `save_path`, e.g.
+    // `{course}/{date}_{index}_{channel}.mp4`, the built-in names when empty
+    #[serde(default)]
+    pub video_name_template: String,
+}
+
+// Configs end up in logs, so credentials are masked
+impl std::fmt::Debug for AppConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut jbox_login_info = self.jbox_login_info.clone();
+        jbox_login_info.access_token = mask_secret(&jbox_login_info.access_token);
+        f.debug_struct("AppConfig")
+            .field("version", &self.version)
+            .field("token", &mask_secret(&self.token))
+            .field("save_path", &self.save_path)
+            .field("account_type", &self.account_type)
+            .field("serve_as_plaintext", &self.serve_as_plaintext)
+            .field("ja_auth_cookie", &mask_secret(&self.ja_auth_cookie))
+            .field("video_cookies", &mask_secret(&self.video_cookies))
+            .field("oauth_consumer_key", &self.oauth_consumer_key)
+            .field("proxy_port", &self.proxy_port)
+            .field("jbox_login_info", &jbox_login_info)
+            .field(
+                "course_assignment_file_bindings",
+                &self.course_assignment_file_bindings,
+            )
+            .field("show_alert_map", &self.show_alert_map)
+            .field("canvas_client_id", &self.canvas_client_id)
+            .field(
+                "canvas_client_secret",
+                &mask_secret(&self.canvas_client_secret),
+            )
+            .field(
+                "canvas_refresh_token",
+                &mask_secret(&self.canvas_refresh_token),
+            )
+            .field("canvas_token_expires_at", &self.canvas_token_expires_at)
+            .field(
+                "video_download_connections",
+                &self.video_download_connections,
+            )
+            .field("stt_command", &self.stt_command)
+            .field("video_name_template", &self.video_name_template)
+            .finish()
+    }
 }
 
 impl Default for AppConfig {
     fn default() -> Self {
         Self {
+            version: CONFIG_VERSION,
             token: Default::default(),
             save_path: Default::default(),
             account_type: Default::default(),
@@ -166,6 +311,13 @@ impl Default for AppConfig {
             jbox_login_info: Default::default(),
             course_assignment_file_bindings: Default::default(),
             show_alert_map: Default::default(),
+            canvas_client_id: Default::default(),
+            canvas_client_secret: Default::default(),
+            canvas_refresh_token: Default::default(),
+            canvas_token_expires_at: Default::default(),
+            video_download_connections: default_video_download_connections(),
+            stt_command: Default::default(),
+            video_name_template: Default::default(),
         }
     }
 }
@@ -174,6 +326,10 @@ fn default_proxy_port() -> u16 {
     3030
 }
 
+fn default_video_download_connections() -> usize {
+    4
+}
+
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ProgressPayload {
     pub uuid: String,
@@ -181,12 +337,41 @@ pub struct ProgressPayload {
     pub total: u64,
 }
 
+// Progress of a batch of lecture downloads, counted in files
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VideoBatchProgress {
+    pub batch_id: String,
+    // finished files, including skipped and failed ones
+    pub completed: u64,
+    pub total: u64,
+    pub current_name: String,
+    pub current: Option<ProgressPayload>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VideoBatchFailure {
+    pub name: String,
+    pub error: String,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VideoBatchReport {
+    pub downloaded: Vec<String>,
+    // already downloaded before
+    pub skipped: Vec<String>,
+    pub failed: Vec<VideoBatchFailure>,
+}
+
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct User {
     pub id: i64,
     pub name: String,
+    // missing in the abbreviated users embedded in sections
+    #[serde(default)]
     pub created_at: String,
+    #[serde(default)]
     pub sortable_name: String,
+    #[serde(default)]
     pub short_name: String,
     #[serde(default)]
     pub login_id: String,
@@ -250,6 +435,11 @@ pub struct Assignment {
     pub all_dates: Vec<AssignmentDate>,
     #[serde(default)]
     pub score_statistics: Option<ScoreStatistics>,
+    // set for group assignments
+    #[serde(default)]
+    pub group_category_id: Option<i64>,
+    #[serde(default)]
+    pub grade_group_students_individually: Option<bool>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -321,6 +511,17 @@ pub struct Submission {
     #[serde(default)]
     pub submission_comments: Vec<SubmissionComment>,
     pub workflow_state: WorkflowState,
+    // only present for group assignments, id and name are null if the student has no group
+    #[serde(default)]
+    pub group: Option<SubmissionGroup>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SubmissionGroup {
+    #[serde(default)]
+    pub id: Option<i64>,
+    #[serde(default)]
+    pub name: Option<String>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -341,6 +542,24 @@ pub struct Attachment {
     pub content_type: String,
 }
 
+impl From<&Attachment> for File {
+    fn from(attachment: &Attachment) -> Self {
+        File {
+            id: attachment.id,
+            uuid: attachment.uuid.clone(),
+            folder_id: attachment.folder_id.unwrap_or_default(),
+            display_name: attachment.display_name.clone(),
+            filename: attachment.filename.clone(),
+            url: attachment.url.clone(),
+            size: attachment.size.max(0) as u64,
+            locked: attachment.locked,
+            mime_class: attachment.mime_class.clone(),
+            content_type: attachment.content_type.clone(),
+            updated_at: None,
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[allow(clippy::enum_variant_names)]
 pub enum EnrollmentRole {
@@ -365,6 +584,11 @@ pub struct Enrollment {
     pub role_id: i64,
     pub user_id: i64,
     pub enrollment_state: String,
+    #[serde(default)]
+    pub course_section_id: Option<i64>,
+    // only returned when listing enrollments of a course or section
+    #[serde(default)]
+    pub user: Option<User>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -545,14 +769,30 @@ pub struct JBoxLoginInfo {
     pub space_id: String,
     #[serde(default)]
     pub access_token: String,
+    // unix timestamp, 0 if unknown
+    #[serde(default)]
+    pub expires_at: i64,
+}
+
+impl JBoxLoginInfo {
+    // Tokens expiring within a minute are treated as expired so uploads don't fail halfway
+    pub fn is_expired(&self) -> bool {
+        self.expires_at > 0 && chrono::Utc::now().timestamp() + 60 >= self.expires_at
+    }
 }
 
 impl From<PersonalSpaceInfo> for JBoxLoginInfo {
     fn from(p: PersonalSpaceInfo) -> Self {
+        let expires_at = if p.expires_in > 0 {
+            chrono::Utc::now().timestamp() + p.expires_in
+        } else {
+            0
+        };
         JBoxLoginInfo {
             library_id: p.library_id,
             space_id: p.space_id,
             access_token: p.access_token,
+            expires_at,
         }
     }
 }
@@ -774,6 +1014,9 @@ pub struct DiscussionTopic {
     pub html_url: String,
     #[serde(default)]
     pub url: String,
+    // only returned by the announcements api, e.g. "course_123"
+    #[serde(default)]
+    pub context_code: Option<String>,
     #[serde(default)]
     pub pinned: Option<bool>,
     #[serde(default)]
@@ -847,6 +1090,8 @@ pub struct DiscussionView {
     #[serde(default)]
     pub message: Option<String>,
     #[serde(default)]
+    pub attachment: Option<Attachment>,
+    #[serde(default)]
     pub replies: Vec<Reply>,
 }
 
@@ -866,7 +1111,14 @@ pub struct Reply {
     #[serde(default)]
     pub rating_sum: Option<i64>,
     #[serde(default)]
+    pub deleted: Option<bool>,
+    #[serde(default)]
     pub message: Option<String>,
+    #[serde(default)]
+    pub attachment: Option<Attachment>,
+    // replies to a reply, only present in threaded discussions
+    #[serde(default)]
+    pub replies: Vec<Reply>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -982,6 +1234,254 @@ pub struct VideoAggregateParams {
     pub sub_video_alpha: u8,
     // 0% ~ 50%, 25% by default
     pub sub_video_size_percentage: u8,
+    #[serde(default)]
+    pub sub_video_position: OverlayPosition,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum OverlayPosition {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    #[default]
+    BottomRight,
+}
+
+// How the screen channel is laid over the classroom channel
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OverlayOptions {
+    #[serde(default)]
+    pub position: OverlayPosition,
+    // 0% ~ 50%
+    #[serde(default = "default_overlay_size_percentage")]
+    pub size_percentage: u8,
+    // 0% ~ 100%
+    #[serde(default = "default_overlay_alpha")]
+    pub alpha: u8,
+}
+
+impl Default for OverlayOptions {
+    fn default() -> Self {
+        Self {
+            position: Default::default(),
+            size_percentage: default_overlay_size_percentage(),
+            alpha: default_overlay_alpha(),
+        }
+    }
+}
+
+fn default_overlay_size_percentage() -> u8 {
+    25
+}
+
+fn default_overlay_alpha() -> u8 {
+    100
+}
+
+// Parsed from the `time=` of ffmpeg's output, in seconds
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FfmpegProgress {
+    pub time: f64,
+    // of the input, 0 if it's unknown
+    pub duration: f64,
+    pub percentage: f64,
+}
+
+// What an ffmpeg job does with its input
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum FfmpegJobKind {
+    // lays `sub_path` over `input_path`
+    Overlay {
+        input_path: String,
+        sub_path: String,
+        #[serde(default)]
+        options: OverlayOptions,
+    },
+    // re-encodes as H.265, a higher crf gives a smaller file
+    Transcode {
+        input_path: String,
+        #[serde(default = "default_transcode_crf")]
+        crf: u8,
+    },
+    // the codec follows the extension of the output
+    ExtractAudio {
+        input_path: String,
+        // resampled to 16kHz mono for speech recognition
+        #[serde(default)]
+        for_speech: bool,
+    },
+    // 0.5x ~ 4x
+    SpeedUp {
+        input_path: String,
+        speed: f64,
+    },
+    // one frame every `interval` seconds, the output is a pattern like `%06d.png`
+    SampleFrames {
+        input_path: String,
+        interval: f64,
+    },
+}
+
+fn default_transcode_crf() -> u8 {
+    28
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FfmpegJobParams {
+    pub kind: FfmpegJobKind,
+    pub output_path: String,
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FfmpegJobState {
+    #[default]
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+    Cancelled,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FfmpegJob {
+    pub id: String,
+    pub params: FfmpegJobParams,
+    pub state: FfmpegJobState,
+    pub progress: FfmpegProgress,
+    // last line ffmpeg printed when it failed
+    pub error: Option<String>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SubtitleParams {
+    // a downloaded lecture recording
+    pub video_path: String,
+    // shown in search results
+    #[serde(default)]
+    pub course_name: String,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SubtitleResult {
+    pub srt_path: String,
+    pub vtt_path: String,
+    pub cue_count: usize,
+}
+
+// A downloaded lecture recording
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VideoLibraryEntry {
+    // relative to the save directory, separated by `/`
+    pub path: String,
+    // 0 when the file was found by a rescan and its name doesn't tell
+    #[serde(default)]
+    pub video_id: i64,
+    #[serde(default)]
+    pub channel: usize,
+    #[serde(default)]
+    pub course: String,
+    #[serde(default)]
+    pub teacher: String,
+    #[serde(default)]
+    pub classroom: String,
+    // `2024-03-01 08:00:00`
+    #[serde(default)]
+    pub begin_time: String,
+    #[serde(default)]
+    pub end_time: String,
+    #[serde(default)]
+    pub size: u64,
+}
+
+// Served by the local media server, all channels of a lecture share one
+// timeline so the player keeps them in sync by seeking them together
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LecturePlayerManifest {
+    pub id: String,
+    pub title: String,
+    pub subject: String,
+    pub begin_time: String,
+    pub channels: Vec<PlayerChannel>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlayerChannel {
+    // 0 is the classroom, the others are screen recordings
+    pub channel: usize,
+    pub url: String,
+    // served from a downloaded file instead of the video site
+    pub local: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SlideExtractParams {
+    // a downloaded screen channel recording
+    pub video_path: String,
+    // `{video name}_slides` next to the video when empty
+    #[serde(default)]
+    pub output_dir: String,
+    // seconds between sampled frames
+    #[serde(default = "default_slide_interval")]
+    pub interval: f64,
+    // 0 ~ 1, how much a frame has to differ from the last slide to start a new one
+    #[serde(default = "default_slide_threshold")]
+    pub threshold: f64,
+}
+
+fn default_slide_interval() -> f64 {
+    2.0
+}
+
+fn default_slide_threshold() -> f64 {
+    0.08
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Slide {
+    pub index: usize,
+    // seconds since the start of the recording
+    pub timestamp: f64,
+    pub image_path: String,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SlideExtractResult {
+    pub output_dir: String,
+    pub pdf_path: String,
+    pub slides: Vec<Slide>,
+    pub sampled_frames: usize,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LectureMergeParams {
+    pub main_video: VideoPlayInfo,
+    pub sub_video: VideoPlayInfo,
+    pub main_save_name: String,
+    pub sub_save_name: String,
+    pub output_name: String,
+    #[serde(default)]
+    pub overlay: OverlayOptions,
+    // remove both downloaded channels once merged
+    #[serde(default)]
+    pub delete_sources: bool,
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum LectureMergeStage {
+    #[default]
+    DownloadMain,
+    DownloadSub,
+    Merge,
+    Done,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LectureMergeProgress {
+    pub output_name: String,
+    pub stage: LectureMergeStage,
+    // of the current stage
+    pub percentage: f64,
 }
 
 pub enum LogLevel {
@@ -1017,3 +1517,490 @@ pub struct AnnualReport {
     pub year: i32,
     pub course_to_statistic: HashMap<i64, AnnualCourseStatistic>,
 }
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchDocumentKind {
+    File,
+    Assignment,
+    Discussion,
+    Announcement,
+    Subtitle,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchDocument {
+    // unique across kinds, e.g. "file:123"
+    pub id: String,
+    pub kind: SearchDocumentKind,
+    pub course_id: i64,
+    pub course_name: String,
+    pub title: String,
+    // plain text, html already stripped
+    pub content: String,
+    pub url: String,
+    // set for files that have been downloaded locally
+    pub local_path: Option<String>,
+    pub date: Option<String>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchFilter {
+    // empty means all courses
+    #[serde(default)]
+    pub course_ids: Vec<i64>,
+    // empty means all kinds
+    #[serde(default)]
+    pub kinds: Vec<SearchDocumentKind>,
+    // RFC 3339, inclusive
+    #[serde(default)]
+    pub start_date: Option<String>,
+    #[serde(default)]
+    pub end_date: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchHit {
+    pub document: SearchDocument,
+    pub score: f64,
+    pub snippet: String,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchIndexStatus {
+    pub document_count: usize,
+    // course id -> time the course was last indexed
+    pub indexed_courses: HashMap<i64, String>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Announcement {
+    pub course_id: i64,
+    pub course_name: String,
+    pub topic: DiscussionTopic,
+}
+
+impl Announcement {
+    pub fn is_unread(&self) -> bool {
+        self.topic.read_state == "unread"
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AnnouncementChanges {
+    // announcements never seen by a previous check
+    pub created: Vec<Announcement>,
+    // announcements whose title, message or attachments changed since the last check
+    pub updated: Vec<Announcement>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Module {
+    pub id: i64,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub position: i64,
+    #[serde(default)]
+    pub unlock_at: Option<String>,
+    #[serde(default)]
+    pub state: Option<String>,
+    #[serde(default)]
+    pub published: Option<bool>,
+    #[serde(default)]
+    pub items_count: i64,
+    #[serde(default)]
+    pub items_url: String,
+    // omitted by canvas when the module has too many items
+    #[serde(default)]
+    pub items: Option<Vec<ModuleItem>>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ModuleItem {
+    pub id: i64,
+    #[serde(default)]
+    pub module_id: i64,
+    #[serde(default)]
+    pub position: i64,
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub indent: i64,
+    // File, Page, Discussion, Assignment, Quiz, SubHeader, ExternalUrl or ExternalTool
+    #[serde(rename = "type", default)]
+    pub item_type: String,
+    #[serde(default)]
+    pub content_id: Option<i64>,
+    #[serde(default)]
+    pub html_url: Option<String>,
+    #[serde(default)]
+    pub url: Option<String>,
+    #[serde(default)]
+    pub page_url: Option<String>,
+    #[serde(default)]
+    pub external_url: Option<String>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Page {
+    #[serde(default)]
+    pub page_id: i64,
+    // the page slug used in urls
+    #[serde(default)]
+    pub url: String,
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub created_at: Option<String>,
+    #[serde(default)]
+    pub updated_at: Option<String>,
+    // only present when fetching a single page
+    #[serde(default)]
+    pub body: Option<String>,
+    #[serde(default)]
+    pub published: bool,
+    #[serde(default)]
+    pub front_page: bool,
+    #[serde(default)]
+    pub html_url: String,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Quiz {
+    pub id: i64,
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub html_url: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub quiz_type: String,
+    #[serde(default)]
+    pub assignment_id: Option<i64>,
+    #[serde(default)]
+    pub time_limit: Option<i64>,
+    #[serde(default)]
+    pub allowed_attempts: i64,
+    #[serde(default)]
+    pub question_count: i64,
+    #[serde(default)]
+    pub points_possible: Option<f64>,
+    #[serde(default)]
+    pub due_at: Option<String>,
+    #[serde(default)]
+    pub lock_at: Option<String>,
+    #[serde(default)]
+    pub unlock_at: Option<String>,
+    #[serde(default)]
+    pub published: bool,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QuizQuestion {
+    pub id: i64,
+    #[serde(default)]
+    pub position: Option<i64>,
+    #[serde(default)]
+    pub question_name: String,
+    #[serde(default)]
+    pub question_type: String,
+    #[serde(default)]
+    pub question_text: String,
+    #[serde(default)]
+    pub points_possible: Option<f64>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QuizSubmission {
+    pub id: i64,
+    #[serde(default)]
+    pub quiz_id: i64,
+    #[serde(default)]
+    pub user_id: Option<i64>,
+    #[serde(default)]
+    pub submission_id: Option<i64>,
+    #[serde(default)]
+    pub attempt: Option<i64>,
+    #[serde(default)]
+    pub score: Option<f64>,
+    #[serde(default)]
+    pub kept_score: Option<f64>,
+    #[serde(default)]
+    pub started_at: Option<String>,
+    #[serde(default)]
+    pub finished_at: Option<String>,
+    // seconds
+    #[serde(default)]
+    pub time_spent: Option<i64>,
+    #[serde(default)]
+    pub workflow_state: String,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QuizSubmissionsPage {
+    #[serde(default)]
+    pub quiz_submissions: Vec<QuizSubmission>,
+}
+
+// canvas reports correctness as true, false, "partial" or "undefined"
+fn deserialize_quiz_correctness<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let value = serde_json::Value::deserialize(deserializer)?;
+    let correctness = match value {
+        serde_json::Value::Bool(true) => "correct",
+        serde_json::Value::Bool(false) => "incorrect",
+        serde_json::Value::String(s) => return Ok(s),
+        _ => "undefined",
+    };
+    Ok(correctness.to_owned())
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QuizQuestionResult {
+    pub question_id: i64,
+    // correct, incorrect, partial or undefined (not graded yet)
+    #[serde(default, deserialize_with = "deserialize_quiz_correctness")]
+    pub correct: String,
+    #[serde(default)]
+    pub points: f64,
+    #[serde(default)]
+    pub text: String,
+    // filled from the quiz questions when the user is allowed to see them
+    #[serde(default)]
+    pub question_name: Option<String>,
+    #[serde(default)]
+    pub points_possible: Option<f64>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QuizSubmissionAttempt {
+    #[serde(default)]
+    pub attempt: Option<i64>,
+    #[serde(default)]
+    pub score: Option<f64>,
+    #[serde(default)]
+    pub submitted_at: Option<String>,
+    // only present for quiz submissions
+    #[serde(default)]
+    pub submission_data: Option<Vec<QuizQuestionResult>>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QuizSubmissionHistory {
+    #[serde(default)]
+    pub submission_history: Vec<QuizSubmissionAttempt>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QuizResult {
+    pub quiz_id: i64,
+    pub attempt: Option<i64>,
+    pub score: Option<f64>,
+    pub questions: Vec<QuizQuestionResult>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QuizQuestionStatistic {
+    // ids are strings in the statistics api
+    pub id: String,
+    #[serde(default)]
+    pub position: Option<i64>,
+    #[serde(default)]
+    pub question_type: String,
+    #[serde(default)]
+    pub question_text: String,
+    #[serde(default)]
+    pub responses: i64,
+    #[serde(default)]
+    pub answered_student_count: Option<i64>,
+    #[serde(default)]
+    pub correct_student_count: Option<i64>,
+    #[serde(default)]
+    pub partially_correct_student_count: Option<i64>,
+    #[serde(default)]
+    pub incorrect_student_count: Option<i64>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QuizStatistics {
+    pub id: String,
+    #[serde(default)]
+    pub question_statistics: Vec<QuizQuestionStatistic>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QuizStatisticsResponse {
+    #[serde(default)]
+    pub quiz_statistics: Vec<QuizStatistics>,
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConversationScope {
+    #[default]
+    Inbox,
+    Unread,
+    Starred,
+    Sent,
+    Archived,
+}
+
+impl ConversationScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConversationScope::Inbox => "inbox",
+            ConversationScope::Unread => "unread",
+            ConversationScope::Starred => "starred",
+            ConversationScope::Sent => "sent",
+            ConversationScope::Archived => "archived",
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConversationParticipant {
+    pub id: i64,
+    #[serde(default)]
+    pub name: String,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConversationMessage {
+    pub id: i64,
+    #[serde(default)]
+    pub created_at: Option<String>,
+    #[serde(default)]
+    pub body: String,
+    #[serde(default)]
+    pub author_id: i64,
+    #[serde(default)]
+    pub generated: bool,
+    #[serde(default)]
+    pub attachments: Vec<Attachment>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Conversation {
+    pub id: i64,
+    #[serde(default)]
+    pub subject: Option<String>,
+    // read, unread or archived
+    #[serde(default)]
+    pub workflow_state: String,
+    #[serde(default)]
+    pub last_message: Option<String>,
+    #[serde(default)]
+    pub last_message_at: Option<String>,
+    #[serde(default)]
+    pub message_count: i64,
+    #[serde(default)]
+    pub starred: bool,
+    #[serde(default)]
+    pub context_name: Option<String>,
+    #[serde(default)]
+    pub audience: Vec<i64>,
+    #[serde(default)]
+    pub participants: Vec<ConversationParticipant>,
+    // only present when fetching a single conversation
+    #[serde(default)]
+    pub messages: Vec<ConversationMessage>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GroupCategory {
+    pub id: i64,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub role: Option<String>,
+    #[serde(default)]
+    pub self_signup: Option<String>,
+    #[serde(default)]
+    pub group_limit: Option<i64>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Group {
+    pub id: i64,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub group_category_id: i64,
+    #[serde(default)]
+    pub members_count: i64,
+    // not returned by canvas, filled when listing groups with members
+    #[serde(default)]
+    pub members: Vec<User>,
+}
+
+// A background job on canvas, workflow_state is one of queued, running,
+// completed and failed
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Progress {
+    pub id: i64,
+    #[serde(default)]
+    pub workflow_state: String,
+    #[serde(default)]
+    pub completion: Option<f64>,
+    #[serde(default)]
+    pub message: Option<String>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GroupCategoryWithGroups {
+    pub category: GroupCategory,
+    pub groups: Vec<Group>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Section {
+    pub id: i64,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub course_id: i64,
+    #[serde(default)]
+    pub sis_section_id: Option<String>,
+    #[serde(default)]
+    pub start_at: Option<String>,
+    #[serde(default)]
+    pub end_at: Option<String>,
+    #[serde(default)]
+    pub total_students: Option<i64>,
+    // null instead of empty when the section has no students
+    #[serde(default)]
+    pub students: Option<Vec<User>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AccountCourse {
+    pub account: Account,
+    pub course: Course,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AccountError {
+    pub account: Account,
+    pub error: String,
+}
+
+// Accounts whose courses couldn't be listed are reported in errors instead of
+// failing the whole list
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MergedCourses {
+    pub courses: Vec<AccountCourse>,
+    pub errors: Vec<AccountError>,
+}
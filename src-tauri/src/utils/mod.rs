@@ -4,6 +4,10 @@ use std::{
 };
 
 use crate::error::{AppError, Result};
+use select::{
+    document::Document,
+    predicate::{Name, Predicate, Text},
+};
 use serde::de::DeserializeOwned;
 use std::any::type_name;
 use uuid::Uuid;
@@ -94,6 +98,77 @@ pub fn get_file_name(file_path: &str) -> String {
         .to_owned()
 }
 
+// Replace characters that are not allowed in file names on Windows/macOS/Linux
+pub fn sanitize_file_name(name: &str) -> String {
+    let sanitized: String = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    let sanitized = sanitized.trim().trim_end_matches('.').trim_end();
+    if sanitized.is_empty() {
+        "untitled".to_owned()
+    } else {
+        sanitized.to_owned()
+    }
+}
+
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// Keeps a short prefix so logged credentials can still be told apart
+pub fn mask_secret(secret: &str) -> String {
+    if secret.is_empty() {
+        return String::new();
+    }
+    let prefix: String = secret.chars().take(4).collect();
+    if secret.chars().count() <= 8 {
+        "***".to_owned()
+    } else {
+        format!("{}***", prefix)
+    }
+}
+
+// Plain text of an html fragment, whitespace collapsed, scripts and styles dropped
+pub fn html_to_text(html: &str) -> String {
+    let document = Document::from(html);
+    let ignored = Name("script").or(Name("style"));
+    let mut words = vec![];
+    for node in document.find(Text) {
+        let mut parent = node.parent();
+        let mut is_ignored = false;
+        while let Some(node) = parent {
+            if ignored.matches(&node) {
+                is_ignored = true;
+                break;
+            }
+            parent = node.parent();
+        }
+        if is_ignored {
+            continue;
+        }
+        if let Some(text) = node.as_text() {
+            words.extend(text.split_whitespace());
+        }
+    }
+    words.join(" ")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -145,4 +220,36 @@ mod tests {
         assert_eq!(get_file_name(win_path), expected_file_name);
         Ok(())
     }
+
+    #[test]
+    fn test_sanitize_file_name() -> Result<()> {
+        assert_eq!(sanitize_file_name("Lab 1: a/b?"), "Lab 1_ a_b_");
+        assert_eq!(sanitize_file_name("  report.  "), "report");
+        assert_eq!(sanitize_file_name("..."), "untitled");
+        assert_eq!(sanitize_file_name("第一次作业"), "第一次作业");
+        Ok(())
+    }
+
+    #[test]
+    fn test_html_to_text() {
+        let html = "<p>Homework <b>1</b></p><script>var a = 1;</script><p>due\n  Monday</p>";
+        assert_eq!(html_to_text(html), "Homework 1 due Monday");
+        assert_eq!(html_to_text(""), "");
+    }
+
+    #[test]
+    fn test_escape_html() -> Result<()> {
+        assert_eq!(
+            escape_html(r#"<a href="x">Tom & Jerry's</a>"#),
+            "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&#39;s&lt;/a&gt;"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_mask_secret() {
+        assert_eq!(mask_secret(""), "");
+        assert_eq!(mask_secret("short"), "***");
+        assert_eq!(mask_secret("22449~abcdefghijklmn"), "2244***");
+    }
 }
@@ -44,7 +44,7 @@ export default function VideoDownloadTable({
         let maxRetries = 3;
         while (retries < maxRetries) {
             try {
-                await invoke("download_video", { video, saveName: task.video.name });
+                await invoke("download_video", { info: task.info, video, saveName: task.video.name });
                 updateTaskProgress(uuid, 100);
                 // messageApi.success("下载成功！", 0.5);
                 break;
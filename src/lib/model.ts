@@ -230,6 +230,7 @@ export interface FileDownloadTask {
 
 export interface VideoDownloadTask {
     key: string;
+    info: VideoInfo;
     video: VideoPlayInfo;
     progress: number;
     state: DownloadState;
@@ -254,6 +255,13 @@ export interface AccountInfo {
     all_accounts: string[];
 }
 
+export interface SecretStoreStatus {
+    backend?: string;
+    unlocked: boolean;
+    encrypted_file_exists: boolean;
+    unsaved_secrets: boolean;
+}
+
 export interface ExportUsersConfig {
     save_name: string;
 }
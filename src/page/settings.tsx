@@ -1,7 +1,7 @@
-import { Button, Form, Image, Input, InputNumber, Select, Space, Tour } from "antd";
+import { Alert, Button, Form, Image, Input, InputNumber, Select, Space, Tour } from "antd";
 import BasicLayout from "../components/layout";
 import { useEffect, useRef, useState } from "react";
-import { AccountInfo, AppConfig, LOG_LEVEL_INFO, User } from "../lib/model";
+import { AccountInfo, AppConfig, LOG_LEVEL_INFO, SecretStoreStatus, User } from "../lib/model";
 import { invoke } from "@tauri-apps/api";
 import useMessage from "antd/es/message/useMessage";
 import { getConfig, saveConfig } from "../lib/store";
@@ -28,6 +28,8 @@ export default function SettingsPage() {
     const [currentAccount, setCurrentAccount] = useState<string>("");
     const [rawConfig, setRawConfig] = useState<string>("");
     const [showLogModal, setShowLogModal] = useState<boolean>(false);
+    const [secretStoreStatus, setSecretStoreStatus] = useState<SecretStoreStatus | undefined>();
+    const [passphrase, setPassphrase] = useState<string>("");
 
     const steps: TourProps['steps'] = [
         {
@@ -59,9 +61,26 @@ export default function SettingsPage() {
         setAccounts(accounts);
     }
 
+    const initSecretStoreStatus = async () => {
+        let status = await invoke("get_secret_store_status") as SecretStoreStatus;
+        setSecretStoreStatus(status);
+    }
+
+    const handleUnlockSecretStore = async () => {
+        try {
+            let status = await invoke("unlock_secret_store", { passphrase }) as SecretStoreStatus;
+            setSecretStoreStatus(status);
+            setPassphrase("");
+            messageApi.success("解锁成功，凭据已加密保存！");
+        } catch (e) {
+            messageApi.error(`解锁失败😢：${e}`);
+        }
+    }
+
     const initConfig = async () => {
         try {
             await initAccounts();
+            await initSecretStoreStatus();
             let config = await getConfig(true);
             let accountInfo = await invoke("read_account_info") as AccountInfo;
             setCurrentAccount(accountInfo.current_account);
@@ -82,6 +101,7 @@ export default function SettingsPage() {
         try {
             await saveConfig(config);
             messageApi.success("保存成功！");
+            await initSecretStoreStatus();
             if (rawConfig) {
                 await getRawConfig();
             }
@@ -162,6 +182,25 @@ export default function SettingsPage() {
     return <BasicLayout>
         {contextHolder}
         <Space direction="vertical" style={{ width: "100%" }}>
+            {secretStoreStatus && !secretStoreStatus.unlocked && <Alert
+                type="warning"
+                showIcon
+                message={secretStoreStatus.encrypted_file_exists ?
+                    "凭据已加密保存，请输入口令解锁" :
+                    "未找到系统钥匙串，请设置一个口令以加密保存凭据"}
+                description={<Space direction="vertical">
+                    {secretStoreStatus.unsaved_secrets && <span>Token 和 Cookie 目前只保存在内存中，解锁前关闭应用将会丢失。</span>}
+                    <Space>
+                        <Password
+                            style={{ width: 250 }}
+                            placeholder="请输入口令"
+                            value={passphrase}
+                            onChange={e => setPassphrase(e.target.value)}
+                        />
+                        <Button disabled={!passphrase} onClick={handleUnlockSecretStore}>解锁</Button>
+                    </Space>
+                </Space>}
+            />}
             {accountMode === "select" && < Space >
                 <span>选择账号：</span>
                 <Select
@@ -22,6 +22,7 @@ export default function VideoPage() {
     const courses = useCourses();
     const [messageApi, contextHolder] = useMessage();
     const [plays, setPlays] = useState<VideoPlayInfo[]>([]);
+    const [videoInfo, setVideoInfo] = useState<VideoInfo | undefined>();
     const [selectedVideo, setSelectedVideo] = useState<CanvasVideo | undefined>();
     const [videos, setVideos] = useState<CanvasVideo[]>([]);
     const [notLogin, setNotLogin] = useState<boolean>(true);
@@ -82,6 +83,7 @@ export default function VideoPage() {
         setSelectedVideo(undefined);
         setPlayURLs([]);
         setPlays([]);
+        setVideoInfo(undefined);
         setMainPlayURL("");
         setMutedPlayURL("");
         handleGetVideos(selected);
@@ -99,6 +101,7 @@ export default function VideoPage() {
                 let suffix = index > 2 ? `_${index}.mp4` : '.mp4';
                 play.name = `${video.videoName}${part}${suffix}`;
             });
+            setVideoInfo(videoInfo);
             setPlays(plays);
         } catch (e) {
             messageApi.error(`获取视频信息的时候出现错误🙅：${e}`);
@@ -109,6 +112,7 @@ export default function VideoPage() {
         let video = videos.find(video => video.videoId === selected);
         if (video) {
             setPlays([]);
+            setVideoInfo(undefined);
             setPlayURLs([]);
             setMainPlayURL("");
             setMutedPlayURL("");
@@ -127,10 +131,14 @@ export default function VideoPage() {
     }
 
     const handleDownloadVideo = (video: VideoPlayInfo) => {
+        if (!videoInfo) {
+            return;
+        }
         let videoId = video.id + "";
         if (!downloadTasks.find(task => task.key === videoId)) {
             setDownloadTasks(tasks => [...tasks, {
                 key: videoId,
+                info: videoInfo,
                 video,
                 video_name: video.name,
                 progress: 0,